@@ -0,0 +1,17 @@
+use std::process::Command;
+
+/// Records the commit being built for `--version`. Builds outside a git
+/// checkout, or without git installed, simply leave it out.
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let commit = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(commit) = commit.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+        println!("cargo:rustc-env=SSH_TUI_GIT_COMMIT={}", commit);
+    }
+}
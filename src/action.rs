@@ -0,0 +1,158 @@
+/// User-facing commands that can be triggered from a keybinding or the
+/// command palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Connect,
+    ConnectAs,
+    ConnectWithArgs,
+    QuickConnect,
+    ConnectMosh,
+    Sftp,
+    CopyId,
+    Tunnel,
+    OpenInTmux,
+    TestConnection,
+    Resolve,
+    Compare,
+    CopyHostname,
+    FixPermissions,
+    Recent,
+    History,
+    Edit,
+    EditRaw,
+    New,
+    Duplicate,
+    Import,
+    Delete,
+    ToggleDisabled,
+    ToggleShowDisabled,
+    CycleSort,
+    ToggleSortDirection,
+    ToggleMask,
+    Reload,
+    SwitchProfile,
+    SaveAs,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::Connect,
+        Action::ConnectAs,
+        Action::ConnectWithArgs,
+        Action::QuickConnect,
+        Action::ConnectMosh,
+        Action::Sftp,
+        Action::CopyId,
+        Action::Tunnel,
+        Action::OpenInTmux,
+        Action::TestConnection,
+        Action::Resolve,
+        Action::Compare,
+        Action::CopyHostname,
+        Action::FixPermissions,
+        Action::Recent,
+        Action::History,
+        Action::Edit,
+        Action::EditRaw,
+        Action::New,
+        Action::Duplicate,
+        Action::Import,
+        Action::Delete,
+        Action::ToggleDisabled,
+        Action::ToggleShowDisabled,
+        Action::CycleSort,
+        Action::ToggleSortDirection,
+        Action::ToggleMask,
+        Action::Reload,
+        Action::SwitchProfile,
+        Action::SaveAs,
+        Action::Quit,
+    ];
+
+    /// Stable identifier, independent of the label wording. The palette
+    /// matches it as well as the label.
+    pub fn id(self) -> &'static str {
+        match self {
+            Action::Connect => "connect",
+            Action::ConnectAs => "connect-as",
+            Action::ConnectWithArgs => "connect-with-args",
+            Action::QuickConnect => "quick-connect",
+            Action::ConnectMosh => "connect-mosh",
+            Action::Sftp => "sftp",
+            Action::CopyId => "copy-id",
+            Action::Tunnel => "tunnel",
+            Action::OpenInTmux => "open-tmux",
+            Action::TestConnection => "test-connection",
+            Action::Resolve => "resolve",
+            Action::Compare => "compare",
+            Action::CopyHostname => "copy-hostname",
+            Action::FixPermissions => "fix-permissions",
+            Action::Recent => "recent",
+            Action::History => "history",
+            Action::Edit => "edit",
+            Action::EditRaw => "edit-raw",
+            Action::New => "new",
+            Action::Duplicate => "duplicate",
+            Action::Import => "import",
+            Action::Delete => "delete",
+            Action::ToggleDisabled => "toggle-disabled",
+            Action::ToggleShowDisabled => "toggle-show-disabled",
+            Action::CycleSort => "cycle-sort",
+            Action::ToggleSortDirection => "toggle-sort-direction",
+            Action::ToggleMask => "toggle-mask",
+            Action::Reload => "reload",
+            Action::SwitchProfile => "switch-profile",
+            Action::SaveAs => "save-as",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Connect => "Connect to selected host",
+            Action::ConnectAs => "Connect to selected host as another user",
+            Action::ConnectWithArgs => "Connect to selected host with extra ssh arguments",
+            Action::QuickConnect => "Quick connect by alias",
+            Action::ConnectMosh => "Connect to selected host with mosh",
+            Action::Sftp => "Open SFTP session to selected host",
+            Action::CopyId => "Copy public key to selected host (ssh-copy-id)",
+            Action::Tunnel => "Open port forwards only (ssh -N)",
+            Action::OpenInTmux => "Open marked hosts in tmux windows",
+            Action::TestConnection => "Test connection to selected host",
+            Action::Resolve => "Resolve selected host's addresses",
+            Action::Compare => "Compare selected host with another",
+            Action::CopyHostname => "Copy selected host's hostname",
+            Action::FixPermissions => "Restrict SSH config and key permissions to 0600",
+            Action::Recent => "Recent connections",
+            Action::History => "Connection history",
+            Action::Edit => "Edit selected host",
+            Action::EditRaw => "Edit selected host's raw block",
+            Action::New => "New host",
+            Action::Duplicate => "Duplicate selected host",
+            Action::Import => "Import hosts from pasted config text or a file",
+            Action::Delete => "Delete marked or selected hosts",
+            Action::ToggleDisabled => "Comment out / restore selected host",
+            Action::ToggleShowDisabled => "Show/hide disabled hosts",
+            Action::CycleSort => "Sort hosts by file order / alias / hostname",
+            Action::ToggleSortDirection => "Reverse the sort direction",
+            Action::ToggleMask => "Mask/unmask sensitive details",
+            Action::Reload => "Reload SSH config",
+            Action::SwitchProfile => "Switch SSH config profile",
+            Action::SaveAs => "Save hosts to another file",
+            Action::Quit => "Quit",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn action_ids_are_unique() {
+        let ids: HashSet<_> = Action::ALL.iter().map(|action| action.id()).collect();
+        assert_eq!(ids.len(), Action::ALL.len());
+    }
+}
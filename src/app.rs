@@ -1,16 +1,245 @@
+#[cfg(unix)]
+use crate::permissions::{self, PermissionStatus};
 use crate::{
+    action::Action,
+    clipboard,
     config::Config,
-    ssh::SshConnection,
-    ssh_config::{load_host_entries, update_host_entry, upsert_host_entry, HostEntry},
+    editor::TextEditor,
+    history::{self, HistoryEntry},
+    hook, keys, log,
+    motion::{Motion, MotionState},
+    search::{fuzzy_match, split_tag_filters, FilteredList, MatchMode, Matcher},
+    ssh::{self, expand_control_path, ConnectTool, ControlTokens, SshConnection},
+    ssh_config::{
+        add_host_entry_matching, comment_host_block_occurrence, config_stamp,
+        delete_host_entry_occurrences, duplicate_aliases, export_host_entries,
+        find_alias_references, find_case_variants, lint_hosts, load_config,
+        load_system_host_entries, merge_host_entries, move_host_block_occurrence, parse_host_block,
+        parse_host_entries, pattern_matches, read_host_block, resolve_config_target,
+        uncomment_host_block_occurrence, update_host_entry_occurrence, ConfigStamp, ConflictPolicy,
+        FieldDiff, HostEntry, MoveDirection, Origin,
+    },
+    state::{unix_now, State},
 };
 use anyhow::Result;
-use std::time::Duration;
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    env, fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How many connection attempts the history popup lists.
+const HISTORY_LIMIT: usize = 20;
+
+const SINGLE_LINE_ERROR: &str = "Fields are single-line; line breaks and tabs are not allowed";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AppState {
     Normal,
     Edit,
     New,
+    Palette,
+    Confirm,
+    Recent,
+    KeyPicker,
+    Search,
+    Issues,
+    History,
+    QuickConnect,
+    SaveAs,
+    ConnectAs,
+    ConnectArgs,
+    ComparePicker,
+    Compare,
+    RawEdit,
+    ProfilePicker,
+    Import,
+}
+
+/// The profile name that stands for the SSH config found at startup.
+const DEFAULT_PROFILE: &str = "default";
+
+/// Picks the alias `query` most likely means: an exact match, then the
+/// first alias starting with it, then the first fuzzy match, all ignoring
+/// case. An empty query matches nothing.
+fn best_alias_match<'a>(query: &str, aliases: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    if query.is_empty() {
+        return None;
+    }
+    let query = query.to_lowercase();
+    let aliases: Vec<&str> = aliases.collect();
+    let lowered: Vec<String> = aliases.iter().map(|alias| alias.to_lowercase()).collect();
+    let position = lowered
+        .iter()
+        .position(|alias| *alias == query)
+        .or_else(|| lowered.iter().position(|alias| alias.starts_with(&query)))
+        .or_else(|| lowered.iter().position(|alias| fuzzy_match(&query, alias)))?;
+    Some(aliases[position])
+}
+
+/// Live check for the Host field; saving rejects wildcards outright.
+fn field_host_hint(host: &str) -> Option<String> {
+    host.contains(['*', '?'])
+        .then(|| "Wildcards make this a pattern, not a host alias".to_string())
+}
+
+/// Live check for the Port field. Empty is fine: ssh uses 22.
+fn field_port_hint(port: &str) -> Option<String> {
+    if port.is_empty() {
+        None
+    } else if !port.chars().all(|c| c.is_ascii_digit()) {
+        Some("Port must be a number".to_string())
+    } else if !matches!(port.parse::<u16>(), Ok(1..)) {
+        Some("Port must be between 1 and 65535".to_string())
+    } else {
+        None
+    }
+}
+
+/// Startup notice such as `duplicate alias 'web' (2 occurrences)`.
+fn duplicate_status(duplicates: &[(String, usize)]) -> String {
+    duplicates
+        .iter()
+        .map(|(alias, count)| format!("duplicate alias '{}' ({} occurrences)", alias, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Startup notice such as `config is mode 0644; press 'F' to chmod 0600`.
+fn permission_status(loose: &[(PathBuf, u32)]) -> String {
+    let files = loose
+        .iter()
+        .map(|(path, mode)| {
+            let name = path.file_name().unwrap_or(path.as_os_str());
+            format!("{} is mode {:04o}", name.to_string_lossy(), mode)
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{}; press 'F' to chmod 0600", files)
+}
+
+/// Startup warning for an SSH config that is a symlink to a file outside
+/// `home`, naming the file edits will really change.
+fn symlink_status(config: &Path, home: &Path) -> Option<String> {
+    fs::read_link(config).ok()?;
+    let target = resolve_config_target(config);
+    let home = fs::canonicalize(home).unwrap_or_else(|_| home.to_path_buf());
+    (!target.starts_with(&home)).then(|| {
+        format!(
+            "SSH config links to {} outside your home directory; edits are written there",
+            target.display()
+        )
+    })
+}
+
+/// Everything the issues panel lists: parser warnings in file order, then
+/// lint findings.
+fn config_issues(mut parse_warnings: Vec<String>, hosts: &[HostEntry]) -> Vec<String> {
+    parse_warnings.extend(lint_hosts(hosts));
+    parse_warnings
+}
+
+/// A footer message; the variant picks its color.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Info(String),
+    Error(String),
+    Success(String),
+}
+
+impl Status {
+    /// The footer text; errors are prefixed with `Error:`.
+    pub fn text(&self) -> String {
+        match self {
+            Status::Info(message) | Status::Success(message) => message.clone(),
+            Status::Error(message) => format!("Error: {}", message),
+        }
+    }
+}
+
+/// Work that runs in the background while the UI keeps drawing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OpKind {
+    TestConnection(String),
+    /// A DNS lookup, named by its `hostname:port` key.
+    Resolve(String),
+}
+
+impl OpKind {
+    pub fn label(&self) -> String {
+        match self {
+            OpKind::TestConnection(host) => format!("Testing connection to {}", host),
+            OpKind::Resolve(key) => format!("Resolving {}", key),
+        }
+    }
+}
+
+/// What a background operation hands back to the UI thread.
+enum OpResult {
+    /// A message for the status line.
+    Status(Result<String>),
+    /// Addresses for a `hostname:port` key, empty when the lookup failed.
+    Resolved(String, Vec<IpAddr>),
+}
+
+/// Looks up a hostname's addresses; swapped out in tests so they never
+/// touch the network.
+type Resolver = fn(&str, u16) -> Vec<IpAddr>;
+
+/// Two hosts side by side and the directives that differ between them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comparison {
+    pub left: String,
+    pub right: String,
+    pub diffs: Vec<FieldDiff>,
+}
+
+/// What the host list is ordered by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// The order of the config file.
+    #[default]
+    File,
+    /// Alias, then HostName when aliases tie.
+    Alias,
+    /// HostName (the alias when unset), then alias.
+    Hostname,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::File => SortKey::Alias,
+            SortKey::Alias => SortKey::Hostname,
+            SortKey::Hostname => SortKey::File,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::File => "file order",
+            SortKey::Alias => "alias",
+            SortKey::Hostname => "hostname",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HostSort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+/// An action waiting for an explicit yes from the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Confirmation {
+    pub prompt: String,
+    pub action: Action,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -20,6 +249,8 @@ pub enum FormField {
     User,
     Port,
     IdentityFile,
+    Tags,
+    Note,
 }
 
 impl FormField {
@@ -29,321 +260,4269 @@ impl FormField {
             FormField::HostName => FormField::User,
             FormField::User => FormField::Port,
             FormField::Port => FormField::IdentityFile,
-            FormField::IdentityFile => FormField::Host,
+            FormField::IdentityFile => FormField::Tags,
+            FormField::Tags => FormField::Note,
+            FormField::Note => FormField::Host,
         }
     }
 
     fn previous(self) -> Self {
         match self {
-            FormField::Host => FormField::IdentityFile,
+            FormField::Host => FormField::Note,
             FormField::HostName => FormField::Host,
             FormField::User => FormField::HostName,
             FormField::Port => FormField::User,
             FormField::IdentityFile => FormField::Port,
+            FormField::Tags => FormField::IdentityFile,
+            FormField::Note => FormField::Tags,
         }
     }
 }
 
 pub struct App {
     pub hosts: Vec<HostEntry>,
+    /// `Host *`-style blocks of the user config. Never listed; their
+    /// directives show as defaults of the hosts they match.
+    pattern_blocks: Vec<HostEntry>,
+    /// Indices into `hosts` that are currently listed, in display order.
+    pub visible: Vec<usize>,
+    /// Position of the selection within `visible`.
     pub selected: usize,
-    pub status: Option<String>,
+    pub show_disabled: bool,
+    pub sort: HostSort,
+    /// Hides key paths, proxy commands and password-like values in the
+    /// details pane, e.g. while sharing a screen.
+    pub mask_sensitive: bool,
+    /// Set by the `'` prefix: the next key is a letter to jump to.
+    pub jump_pending: bool,
+    /// Counts and `g` typed towards a vim-style motion.
+    pub motion: MotionState,
+    /// Indices into `hosts` marked for a batch open.
+    pub marked: HashSet<usize>,
+    pub status: Option<Status>,
+    /// The status message last seen by `expire_status` and when it first
+    /// appeared.
+    status_since: Option<(Status, Instant)>,
+    /// Work running off the UI thread; the footer shows a spinner while set.
+    pub pending_operation: Option<OpKind>,
+    pending_result: Option<Receiver<OpResult>>,
+    /// Advanced on every tick while an operation is pending.
+    pub spinner_frame: usize,
     pub state: AppState,
     pub form_entry: HostEntry,
     pub form_field: FormField,
+    /// Space-separated tags being edited, written back as `# tags:`.
+    pub form_tags: String,
+    /// Free-text note being edited, written back as `# note:`.
+    pub form_note: String,
+    /// The selected block's lines while it is edited as raw text, or the
+    /// text being pasted for an import.
+    pub raw_editor: TextEditor,
+    /// Hosts parsed for an import, upserted once the preview is accepted.
+    pending_import: Vec<HostEntry>,
     pub form_error: Option<String>,
-    #[allow(dead_code)]
+    /// Non-blocking notice about the form, such as an alias already in use.
+    pub form_hint: Option<String>,
+    /// Problem with the focused field's current value, shown under it
+    /// while typing.
+    pub field_hint: Option<String>,
+    /// Lines the parser passed over, followed by the findings of
+    /// [`lint_hosts`], for the loaded config.
+    pub lint_warnings: Vec<String>,
+    pub palette: FilteredList,
+    /// Alias typed into the quick-connect prompt.
+    pub quick_connect: String,
+    /// Destination typed into the save-as prompt.
+    pub save_as_path: String,
+    /// Username typed into the connect-as prompt.
+    pub connect_as_user: String,
+    /// User for the next connection only, set once the prompt is accepted
+    /// and consumed by `connect_to_host_with`.
+    connect_user_override: Option<String>,
+    /// Extra ssh arguments typed into the connect-with-args prompt.
+    pub connect_args: String,
+    /// Which `args_history` entry Up/Down put in the prompt, if any.
+    args_history_position: Option<usize>,
+    /// Arguments for the next connection only, consumed like
+    /// `connect_user_override`.
+    connect_args_override: Option<Vec<String>>,
+    pub confirmation: Option<Confirmation>,
+    pub recent: FilteredList,
+    pub recent_hosts: Vec<String>,
+    /// Hosts the selected one can be compared with.
+    pub compare_picker: FilteredList,
+    pub compare_candidates: Vec<String>,
+    /// `default`, the config found at startup, then the `[profiles]` names.
+    pub profile_picker: FilteredList,
+    pub profile_candidates: Vec<String>,
+    /// The `[profiles]` entry whose file is open; `None` for the default.
+    pub active_profile: Option<String>,
+    pub comparison: Option<Comparison>,
+    pub key_picker: FilteredList,
+    pub key_candidates: Vec<String>,
+    /// Set by `--print`: connecting picks the host instead of running ssh.
+    pub print_only: bool,
+    /// The alias picked in `--print` mode, written to stdout on exit.
+    pub chosen_host: Option<String>,
+    pub search_query: String,
+    pub match_mode: MatchMode,
+    /// Why the current query could not be used; the list keeps showing the
+    /// results of the last valid one.
+    pub search_error: Option<String>,
+    host_matcher: Option<Matcher>,
+    /// `tag:` terms of the search; listed hosts carry all of them.
+    tag_filter: Vec<String>,
     config: Config,
+    saved_state: State,
+    /// `None` keeps state in memory only, as in tests.
+    state_path: Option<PathBuf>,
+    /// Where connection attempts are appended; `None` records nothing.
+    history_path: Option<PathBuf>,
+    /// Newest first, read when the history popup opens.
+    pub history: Vec<HistoryEntry>,
     ssh_connection: SshConnection,
+    /// The config every read and write goes to: the active profile's file.
+    ssh_config_path: PathBuf,
+    /// The config found at startup, where the `default` profile points.
+    default_config_path: PathBuf,
+    system_config_path: PathBuf,
+    /// Hosts of the system config, read once at startup. The ones whose
+    /// alias the user config does not define are listed after the user's.
+    system_hosts: Vec<HostEntry>,
+    /// Stamp of the config `hosts` was parsed from; reloads skip the parse
+    /// while it is unchanged.
+    config_stamp: Option<ConfigStamp>,
+    ssh_dir: PathBuf,
+    home_dir: PathBuf,
+    /// Whether a host's IdentityFile is missing, keyed by index into
+    /// `hosts`. Filled in as hosts get selected so redraws never stat.
+    missing_identity: HashMap<usize, bool>,
+    /// Whether a host's key is passphrase-protected, read alongside
+    /// `missing_identity`; `None` when there is no readable key.
+    encrypted_identity: HashMap<usize, Option<bool>>,
+    /// The config and identity files group or others can access, with
+    /// their permission bits. Always empty off Unix.
+    loose_permissions: Vec<(PathBuf, u32)>,
+    control_socket_active: bool,
+    /// DNS results keyed by `hostname:port`; empty when resolution failed.
+    resolved: HashMap<String, Vec<IpAddr>>,
+    resolver: Resolver,
+    /// Candidates from the last Tab completion, and which one is filled in.
+    path_completions: Vec<String>,
+    completion_index: usize,
+    /// The form mode to return to when the key picker closes.
+    picker_return: AppState,
     original_host_name: Option<String>,
+    /// Which block of `original_host_name` the form edits when the alias
+    /// is defined more than once.
+    original_occurrence: usize,
 }
 
 impl App {
     pub fn new() -> Result<Self> {
         let config = Config::load()?;
-        let hosts = load_host_entries()?;
+        if config.log {
+            log::init(Some(Config::log_path()?));
+        }
+        let ssh_config_path = Config::ssh_config_path()?;
+        let config_stamp = config_stamp(&ssh_config_path);
+        let parsed = load_config(&ssh_config_path)?;
+        let hosts = parsed.hosts;
+        let duplicates = duplicate_aliases(&hosts);
+        let lint_warnings = config_issues(parsed.warnings, &hosts);
+        let system_config_path = Config::system_ssh_config_path();
+        let system_hosts = load_system_host_entries(&system_config_path);
+        let hosts = with_system_hosts(hosts, &system_hosts);
+        let status = if !ssh_config_path.exists() {
+            Some(Status::Info(
+                "No SSH config found — press 'n' to create your first host".to_string(),
+            ))
+        } else if duplicates.is_empty() {
+            None
+        } else {
+            Some(Status::Info(duplicate_status(&duplicates)))
+        };
+        let state_path = Config::state_path().ok();
+        let saved_state = state_path.as_deref().map(State::load).unwrap_or_default();
 
         let ssh_connection = SshConnection::new(
             config.ssh_binary.clone(),
             Duration::from_secs(config.timeout),
-        );
+        )
+        .with_mosh_binary(config.mosh_binary.clone())
+        .with_sftp_binary(config.sftp_binary.clone())
+        .with_ssh_copy_id_binary(config.ssh_copy_id_binary.clone());
 
-        Ok(Self {
+        let mut app = Self {
             hosts,
+            pattern_blocks: parsed.patterns,
+            visible: Vec::new(),
             selected: 0,
-            status: None,
+            show_disabled: false,
+            sort: HostSort::default(),
+            mask_sensitive: false,
+            jump_pending: false,
+            motion: MotionState::default(),
+            marked: HashSet::new(),
+            status,
+            status_since: None,
+            pending_operation: None,
+            pending_result: None,
+            spinner_frame: 0,
             state: AppState::Normal,
             form_entry: HostEntry::default(),
             form_field: FormField::Host,
+            form_tags: String::new(),
+            form_note: String::new(),
+            raw_editor: TextEditor::default(),
+            pending_import: Vec::new(),
             form_error: None,
+            form_hint: None,
+            field_hint: None,
+            lint_warnings,
+            palette: FilteredList::default(),
+            quick_connect: String::new(),
+            save_as_path: String::new(),
+            connect_as_user: String::new(),
+            connect_user_override: None,
+            connect_args: String::new(),
+            args_history_position: None,
+            connect_args_override: None,
+            confirmation: None,
+            recent: FilteredList::default(),
+            recent_hosts: Vec::new(),
+            compare_picker: FilteredList::default(),
+            compare_candidates: Vec::new(),
+            profile_picker: FilteredList::default(),
+            profile_candidates: Vec::new(),
+            active_profile: None,
+            comparison: None,
+            key_picker: FilteredList::default(),
+            key_candidates: Vec::new(),
+            print_only: false,
+            chosen_host: None,
+            search_query: String::new(),
+            match_mode: MatchMode::default(),
+            search_error: None,
+            host_matcher: None,
+            tag_filter: Vec::new(),
             config,
+            saved_state,
+            state_path,
+            history_path: Config::history_path().ok(),
+            history: Vec::new(),
             ssh_connection,
+            default_config_path: ssh_config_path.clone(),
+            ssh_config_path,
+            system_config_path,
+            system_hosts,
+            config_stamp,
+            ssh_dir: Config::ssh_dir()?,
+            home_dir: Config::home_dir()?,
+            missing_identity: HashMap::new(),
+            encrypted_identity: HashMap::new(),
+            loose_permissions: Vec::new(),
+            control_socket_active: false,
+            resolved: HashMap::new(),
+            resolver: ssh::resolve_host,
+            path_completions: Vec::new(),
+            completion_index: 0,
+            picker_return: AppState::New,
             original_host_name: None,
-        })
+            original_occurrence: 0,
+        };
+        app.scan_permissions();
+        if app.ssh_config_path.exists() && !app.loose_permissions.is_empty() {
+            app.status = Some(Status::Info(permission_status(&app.loose_permissions)));
+        }
+        if let Some(warning) = symlink_status(&app.ssh_config_path, &app.home_dir) {
+            app.status = Some(Status::Info(warning));
+        }
+        app.rebuild_view();
+        Ok(app)
+    }
+
+    /// Recomputes `visible` from `hosts`, keeping the selection in range.
+    pub fn rebuild_view(&mut self) {
+        self.visible = self
+            .hosts
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| self.show_disabled || !entry.disabled)
+            .filter(|(_, entry)| {
+                let tags = entry.tags();
+                self.tag_filter
+                    .iter()
+                    .all(|wanted| tags.iter().any(|tag| tag.eq_ignore_ascii_case(wanted)))
+            })
+            .filter(|(_, entry)| {
+                self.host_matcher.as_ref().is_none_or(|matcher| {
+                    matcher.is_match(&format!("{} {}", entry.host, entry.hostname()))
+                })
+            })
+            .map(|(index, _)| index)
+            .collect();
+        if self.sort.key == SortKey::File {
+            if self.sort.descending {
+                self.visible.reverse();
+            }
+        } else {
+            let hosts = &self.hosts;
+            let sort = self.sort;
+            self.visible
+                .sort_by(|&a, &b| compare_hosts(&hosts[a], &hosts[b], sort));
+        }
+        if self.selected >= self.visible.len() {
+            self.selected = self.visible.len().saturating_sub(1);
+        }
+        self.inspect_selected();
+    }
+
+    pub fn visible_hosts(&self) -> impl Iterator<Item = &HostEntry> {
+        self.visible.iter().map(|&index| &self.hosts[index])
     }
 
     pub fn next(&mut self) {
-        if self.hosts.is_empty() {
+        if self.visible.is_empty() {
             return;
         }
-        self.selected = (self.selected + 1) % self.hosts.len();
+        self.selected = (self.selected + 1) % self.visible.len();
+        self.inspect_selected();
     }
 
     pub fn previous(&mut self) {
-        if self.hosts.is_empty() {
+        if self.visible.is_empty() {
             return;
         }
         if self.selected == 0 {
-            self.selected = self.hosts.len() - 1;
+            self.selected = self.visible.len() - 1;
         } else {
             self.selected -= 1;
         }
+        self.inspect_selected();
     }
 
-    pub fn selected_host(&self) -> Option<&HostEntry> {
-        self.hosts.get(self.selected)
+    /// Moves the selection for a vim-style motion. A plain `j`/`k` wraps
+    /// around like `next`/`previous`; counted moves stop at either end, and
+    /// positions past the end select the last host.
+    pub fn apply_motion(&mut self, motion: Motion) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let last = self.visible.len() - 1;
+        self.selected = match motion {
+            Motion::Down(1) => return self.next(),
+            Motion::Up(1) => return self.previous(),
+            Motion::Down(count) => self.selected.saturating_add(count).min(last),
+            Motion::Up(count) => self.selected.saturating_sub(count),
+            Motion::Top => 0,
+            Motion::Bottom => last,
+            Motion::Position(position) => position.saturating_sub(1).min(last),
+        };
+        self.inspect_selected();
     }
 
-    pub fn selected_host_name(&self) -> Option<&str> {
-        self.selected_host().map(|entry| entry.host.as_str())
+    /// Waits for the letter of a jump-to-prefix.
+    pub fn start_jump(&mut self) {
+        self.jump_pending = true;
+        self.status = Some(Status::Info("Jump to: type a letter".to_string()));
     }
 
-    pub fn connect_to_host(&mut self, host: &str) -> Result<String> {
-        self.ssh_connection.connect(host)
+    /// Selects the next listed host after the current one whose alias
+    /// starts with `c`, ignoring case and wrapping around, so repeating the
+    /// same letter cycles through its hosts. Returns whether one matched.
+    pub fn jump_to_prefix(&mut self, c: char) -> bool {
+        self.jump_pending = false;
+        self.status = None;
+        let starts_with = |entry: &HostEntry| {
+            entry
+                .host
+                .chars()
+                .next()
+                .is_some_and(|first| first.to_lowercase().eq(c.to_lowercase()))
+        };
+        let count = self.visible.len();
+        let found = (1..=count)
+            .map(|offset| (self.selected + offset) % count)
+            .find(|&position| starts_with(&self.hosts[self.visible[position]]));
+        match found {
+            Some(position) => {
+                self.selected = position;
+                self.inspect_selected();
+                true
+            }
+            None => {
+                self.status = Some(Status::Info(format!("No host starts with '{}'", c)));
+                false
+            }
+        }
     }
 
-    pub fn set_status(&mut self, result: Result<String>) {
-        match result {
-            Ok(msg) => self.status = Some(msg),
-            Err(e) => self.status = Some(format!("Error: {}", e)),
+    /// Directives the selected host picks up from matching pattern blocks,
+    /// as `(patterns, keyword, value)`, for keywords it does not set
+    /// itself. The first block to set a keyword wins, as in ssh.
+    pub fn inherited_directives(&self) -> Vec<(&str, &str, &str)> {
+        let Some(entry) = self.selected_host() else {
+            return Vec::new();
+        };
+        let mut inherited: Vec<(&str, &str, &str)> = Vec::new();
+        for block in self
+            .pattern_blocks
+            .iter()
+            .filter(|block| block.pattern_applies_to(&entry.host))
+        {
+            for (keyword, value) in &block.directives {
+                let taken = keyword.is_empty()
+                    || !entry.get(keyword).is_empty()
+                    || inherited
+                        .iter()
+                        .any(|(_, seen, _)| seen.eq_ignore_ascii_case(keyword));
+                if !taken {
+                    inherited.push((&block.host, keyword, value.trim()));
+                }
+            }
         }
+        inherited
     }
 
-    pub fn is_form_active(&self) -> bool {
-        !matches!(self.state, AppState::Normal)
+    pub fn selected_host(&self) -> Option<&HostEntry> {
+        self.visible
+            .get(self.selected)
+            .and_then(|&index| self.hosts.get(index))
     }
 
-    pub fn enter_edit_mode(&mut self) {
-        if let Some(entry) = self.selected_host().cloned() {
-            self.form_entry = entry.clone();
-            self.original_host_name = Some(entry.host);
-            self.form_field = FormField::Host;
-            self.form_error = None;
-            self.state = AppState::Edit;
+    /// Alias of the selected host when it can be connected to. Disabled
+    /// hosts have no active block for ssh to read, so they are refused.
+    pub fn connectable_host_name(&mut self) -> Option<String> {
+        let entry = self.selected_host()?;
+        if entry.disabled {
+            self.status = Some(Status::Info(format!(
+                "Host '{}' is disabled; press # to enable it",
+                entry.host
+            )));
+            return None;
         }
+        Some(entry.host.clone())
     }
 
-    pub fn enter_new_mode(&mut self) {
-        self.form_entry = HostEntry::default();
-        self.original_host_name = None;
-        self.form_field = FormField::Host;
-        self.form_error = None;
-        self.state = AppState::New;
+    /// Picks the selected host for `--print`. Returns whether one was picked,
+    /// which ends the session.
+    pub fn choose_selected(&mut self) -> bool {
+        self.chosen_host = self.connectable_host_name();
+        self.chosen_host.is_some()
     }
 
-    pub fn cancel_form(&mut self) {
-        self.state = AppState::Normal;
-        self.form_entry = HostEntry::default();
-        self.form_error = None;
-        self.original_host_name = None;
+    pub fn toggle_show_disabled(&mut self) {
+        let focus = self.selected_host_name().map(str::to_string);
+        self.show_disabled = !self.show_disabled;
+        self.rebuild_view();
+        self.focus_host(focus.as_deref());
     }
 
-    pub fn focus_next_field(&mut self) {
-        if self.is_form_active() {
-            self.form_field = self.form_field.next();
-        }
+    /// Moves on to the next sort key (file order, alias, hostname),
+    /// keeping the selected host selected.
+    pub fn cycle_sort_key(&mut self) {
+        self.sort.key = self.sort.key.next();
+        self.resort();
     }
 
-    pub fn focus_previous_field(&mut self) {
-        if self.is_form_active() {
-            self.form_field = self.form_field.previous();
-        }
+    pub fn toggle_sort_direction(&mut self) {
+        self.sort.descending = !self.sort.descending;
+        self.resort();
     }
 
-    pub fn handle_form_input(&mut self, ch: char) {
-        if !self.is_form_active() || ch.is_control() {
-            return;
-        }
-        self.form_error = None;
-        let field = self.current_field_mut();
-        field.push(ch);
+    fn resort(&mut self) {
+        let focus = self.selected_host_name().map(str::to_string);
+        self.rebuild_view();
+        self.focus_host(focus.as_deref());
+        self.status = Some(Status::Info(format!(
+            "Sorted by {} ({})",
+            self.sort.key.label(),
+            if self.sort.descending {
+                "descending"
+            } else {
+                "ascending"
+            }
+        )));
     }
 
-    pub fn handle_form_backspace(&mut self) {
-        if !self.is_form_active() {
-            return;
-        }
-        self.form_error = None;
-        let field = self.current_field_mut();
-        field.pop();
+    pub fn toggle_mask_sensitive(&mut self) {
+        self.mask_sensitive = !self.mask_sensitive;
+        self.status = Some(Status::Info(if self.mask_sensitive {
+            "Sensitive details hidden".to_string()
+        } else {
+            "Sensitive details shown".to_string()
+        }));
     }
 
-    pub fn save_form(&mut self) {
-        if !self.is_form_active() {
+    /// Comments out the selected host's block, or restores it if it is
+    /// already disabled.
+    pub fn toggle_selected_disabled(&mut self) {
+        let Some(entry) = self.selected_host() else {
             return;
-        }
-
-        let mode = self.state;
-        let entry = self.form_entry.clone();
-
-        if let Err(err) = entry.validate() {
-            self.form_error = Some(err.to_string());
+        };
+        let host = entry.host.clone();
+        let disabled = entry.disabled;
+        if self.refuse_read_only(&host) {
             return;
         }
-
-        let result = match mode {
-            AppState::Edit => {
-                let original = self
-                    .original_host_name
-                    .clone()
-                    .unwrap_or_else(|| entry.host.clone());
-                update_host_entry(&original, &entry)
-            }
-            AppState::New => upsert_host_entry(&entry),
-            AppState::Normal => Ok(()),
+        let occurrence = self.selected_occurrence();
+        let matching = self.config.alias_match;
+        let result = if disabled {
+            uncomment_host_block_occurrence(&self.ssh_config_path, &host, occurrence, matching)
+        } else {
+            comment_host_block_occurrence(&self.ssh_config_path, &host, occurrence, matching)
         };
-
-        match result {
+        match result.and_then(|()| self.refresh_after_write(Some(host.clone()))) {
             Ok(()) => {
-                if let Err(err) = self.refresh_hosts(Some(entry.host.clone())) {
-                    self.form_error = Some(err.to_string());
-                    return;
-                }
-                self.state = AppState::Normal;
-                self.form_entry = HostEntry::default();
-                self.form_error = None;
-                self.original_host_name = None;
-                let action = match mode {
-                    AppState::Edit => "updated",
-                    AppState::New => "created",
-                    AppState::Normal => "saved",
-                };
-                self.status = Some(format!("Host '{}' {} successfully", entry.host, action));
-            }
-            Err(err) => {
-                self.form_error = Some(err.to_string());
+                let state = if disabled { "enabled" } else { "disabled" };
+                self.status = Some(Status::Success(format!("Host '{}' {}", host, state)));
             }
+            Err(err) => self.status = Some(Status::Error(err.to_string())),
         }
     }
 
-    fn refresh_hosts(&mut self, focus: Option<String>) -> Result<()> {
-        self.hosts = load_host_entries()?;
-        if self.hosts.is_empty() {
-            self.selected = 0;
-            return Ok(());
+    /// Where system hosts come from, for the details pane.
+    pub fn system_config_path(&self) -> &Path {
+        &self.system_config_path
+    }
+
+    /// Says so in the footer and returns true when `host` comes from the
+    /// system config, which the app never writes to.
+    fn refuse_read_only(&mut self, host: &str) -> bool {
+        let read_only = self
+            .hosts
+            .iter()
+            .any(|entry| entry.host == host && entry.is_read_only());
+        if read_only {
+            self.status = Some(Status::Info(format!(
+                "'{}' comes from {} and is read-only",
+                host,
+                self.system_config_path.display()
+            )));
         }
+        read_only
+    }
+
+    pub fn selected_host_name(&self) -> Option<&str> {
+        self.selected_host().map(|entry| entry.host.as_str())
+    }
+
+    /// Connects using the host's `# connect-with:` override, or ssh.
+    pub fn connect_to_host(&mut self, host: &str) -> Result<String> {
+        let tool = self.preferred_tool(host);
+        self.connect_to_host_with(host, tool)
+    }
 
-        if let Some(host) = focus {
-            if let Some(index) = self.hosts.iter().position(|entry| entry.host == host) {
-                self.selected = index;
-                return Ok(());
+    pub fn connect_to_host_with(&mut self, host: &str, tool: ConnectTool) -> Result<String> {
+        let user = self.connect_user_override.take();
+        let extra_args = self.connect_args_override.take();
+        let result = match (user.as_deref(), extra_args, tool) {
+            (Some(user), _, ConnectTool::Ssh) => self.ssh_connection.connect_as(host, user),
+            (None, args, ConnectTool::Ssh) => self.ssh_connection.connect_with_retry(
+                host,
+                args.as_deref().unwrap_or_default(),
+                self.config.connect_retries,
+            ),
+            (user, None, tool) => self
+                .ssh_connection
+                .connect_with(&ssh::destination(host, user), tool),
+            (user, Some(args), tool) => {
+                self.ssh_connection
+                    .connect_with_args(&ssh::destination(host, user), tool, &args)
             }
+        };
+        self.record_history(host, user.as_deref(), &result);
+        self.record_usage(host);
+        self.check_selected_control_socket();
+        match self.run_post_connect_hook(host) {
+            None => result,
+            Some(hook) => match result {
+                Ok(msg) => Ok(format!("{}; {}", msg, hook)),
+                Err(err) => {
+                    let message = format!("{}; {}", err, hook);
+                    Err(err.context(message))
+                }
+            },
         }
+    }
 
-        if self.selected >= self.hosts.len() {
-            self.selected = self.hosts.len() - 1;
+    /// A heads-up for a host whose key is passphrase-protected but not in
+    /// ssh-agent, so the passphrase prompt ssh is about to show comes as
+    /// no surprise. `None` for unencrypted keys and hosts without an
+    /// IdentityFile.
+    pub fn agent_key_warning(&self, host: &str) -> Option<String> {
+        let identity = self
+            .hosts
+            .iter()
+            .find(|entry| entry.host == host)
+            .map(|entry| entry.identity_file().trim())
+            .filter(|identity| !identity.is_empty())?;
+        let path = keys::expand_tilde(identity, &self.home_dir);
+        if keys::key_is_encrypted(&path) != Some(true) || self.ssh_connection.agent_has_key(&path) {
+            return None;
         }
-        Ok(())
+        Some(format!(
+            "{} is passphrase-protected and not loaded in ssh-agent (ssh-add {} loads it)",
+            identity, identity
+        ))
     }
 
-    fn current_field_mut(&mut self) -> &mut String {
-        match self.form_field {
-            FormField::Host => &mut self.form_entry.host,
-            FormField::HostName => &mut self.form_entry.hostname,
-            FormField::User => &mut self.form_entry.user,
-            FormField::Port => &mut self.form_entry.port,
-            FormField::IdentityFile => &mut self.form_entry.identity_file,
+    /// Appends the attempt to the history file. Best-effort: a failed write
+    /// is silently dropped.
+    fn record_history(&self, host: &str, user: Option<&str>, result: &Result<String>) {
+        let Some(path) = &self.history_path else {
+            return;
+        };
+        let mut entry = self
+            .hosts
+            .iter()
+            .find(|entry| entry.host == host)
+            .cloned()
+            .unwrap_or_else(|| HostEntry::new(host));
+        if let Some(user) = user {
+            entry.set("User", user);
         }
+        let (success, message) = match result {
+            Ok(message) => (true, message.clone()),
+            Err(err) => (false, err.to_string()),
+        };
+        history::append(
+            path,
+            &HistoryEntry {
+                timestamp: unix_now(),
+                alias: host.to_string(),
+                target: entry.connection_target(),
+                success,
+                result: message,
+            },
+        );
     }
 
-    #[cfg(test)]
-    pub fn test_with_hosts(hosts: Vec<HostEntry>) -> Self {
-        Self {
-            hosts,
-            selected: 0,
-            status: None,
-            state: AppState::Normal,
-            form_entry: HostEntry::default(),
-            form_field: FormField::Host,
-            form_error: None,
-            config: Config::default(),
-            ssh_connection: SshConnection::new("ssh".to_string(), Duration::from_secs(30)),
-            original_host_name: None,
-        }
+    /// Whether the app should quit once a session has ended: only with
+    /// `stay_open` off or `exit_after_connect` on, and only after a session
+    /// that ended cleanly, so a failed connection still shows its error.
+    pub fn exit_after_session(&self, result: &Result<String>) -> bool {
+        (!self.config.stay_open || self.config.exit_after_connect) && result.is_ok()
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::ssh::SshConnection;
+    /// Whether the terminal setup should turn on mouse capture.
+    pub fn mouse_capture(&self) -> bool {
+        self.config.mouse
+    }
 
-    fn host(name: &str) -> HostEntry {
-        HostEntry {
-            host: name.to_string(),
-            hostname: "example.com".to_string(),
-            user: "user".to_string(),
-            port: String::new(),
-            identity_file: String::new(),
-            proxy_command: String::new(),
-            extra: Vec::new(),
-        }
+    /// Runs ssh-copy-id for `host`, with its IdentityFile when it names
+    /// one. `~` is expanded here since no shell sees the argument.
+    pub fn copy_public_key(&self, host: &str) -> Result<String> {
+        let identity = self
+            .hosts
+            .iter()
+            .find(|entry| entry.host == host)
+            .map(|entry| entry.identity_file())
+            .filter(|identity| !identity.is_empty())
+            .map(|identity| keys::expand_tilde(identity, &self.home_dir));
+        let identity = identity.as_deref().map(Path::to_string_lossy);
+        self.ssh_connection.copy_id(host, identity.as_deref())
     }
 
-    fn test_app() -> App {
-        App {
-            hosts: vec![host("a"), host("b"), host("c")],
-            selected: 0,
-            status: None,
-            state: AppState::Normal,
-            form_entry: HostEntry::default(),
-            form_field: FormField::Host,
-            form_error: None,
-            config: Config::default(),
-            ssh_connection: SshConnection::new("ssh".to_string(), Duration::from_secs(30)),
-            original_host_name: None,
-        }
+    pub fn open_sftp(&self, host: &str) -> Result<String> {
+        self.ssh_connection.sftp(host)
     }
 
-    #[test]
-    fn next_wraps() {
-        let mut app = test_app();
-        app.selected = 2;
-        app.next();
-        assert_eq!(app.selected, 0);
+    pub fn open_tunnel(&self, host: &str) -> Result<String> {
+        self.ssh_connection.tunnel(host)
     }
 
-    #[test]
-    fn previous_wraps() {
-        let mut app = test_app();
-        app.previous();
-        assert_eq!(app.selected, 2);
+    /// Whether the selected host names an IdentityFile that is not on disk.
+    pub fn selected_identity_missing(&self) -> bool {
+        self.visible
+            .get(self.selected)
+            .is_some_and(|&index| self.identity_missing(index))
     }
 
-    #[test]
-    fn selected_host_returns_entry() {
-        let app = test_app();
-        assert_eq!(app.selected_host_name(), Some("a"));
+    /// Whether the host at `index` was found to have a missing IdentityFile
+    /// when it was last selected. Hosts never selected are not reported.
+    pub fn identity_missing(&self, index: usize) -> bool {
+        self.missing_identity.get(&index).copied().unwrap_or(false)
     }
 
-    #[test]
-    fn focus_navigation_cycles_fields() {
-        let mut app = test_app();
-        app.enter_new_mode();
-        app.focus_next_field();
-        assert_eq!(app.form_field, FormField::HostName);
-        app.focus_previous_field();
-        assert_eq!(app.form_field, FormField::Host);
+    /// Whether the selected host's key is passphrase-protected; `None` when
+    /// it has no IdentityFile or the key could not be read.
+    pub fn selected_key_encrypted(&self) -> Option<bool> {
+        let index = self.visible.get(self.selected)?;
+        self.encrypted_identity.get(index).copied().flatten()
     }
 
-    #[test]
-    fn handle_form_input_updates_field() {
-        let mut app = test_app();
-        app.enter_new_mode();
-        app.handle_form_input('s');
-        app.handle_form_input('1');
-        assert_eq!(app.form_entry.host, "s1");
-        app.handle_form_backspace();
-        assert_eq!(app.form_entry.host, "s");
+    /// Records which of the config and the hosts' identity files are
+    /// readable by group or others; ssh warns about the former and refuses
+    /// the latter.
+    fn scan_permissions(&mut self) {
+        #[cfg(unix)]
+        {
+            let mut paths = vec![self.ssh_config_path.clone()];
+            for entry in &self.hosts {
+                if entry.identity_file().is_empty() {
+                    continue;
+                }
+                let path = keys::expand_tilde(entry.identity_file(), &self.home_dir);
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+            self.loose_permissions = paths
+                .into_iter()
+                .filter_map(|path| match permissions::check_permissions(&path) {
+                    PermissionStatus::TooOpen(mode) => Some((path, mode)),
+                    _ => None,
+                })
+                .collect();
+        }
+    }
+
+    /// Chmods every file found by `scan_permissions` to 0600.
+    pub fn fix_permissions(&mut self) {
+        if self.loose_permissions.is_empty() {
+            self.status = Some(Status::Info(
+                "SSH config and keys are already private".to_string(),
+            ));
+            return;
+        }
+        #[cfg(unix)]
+        {
+            let result = self
+                .loose_permissions
+                .iter()
+                .try_for_each(|(path, _)| permissions::restrict_permissions(path))
+                .map(|()| {
+                    format!(
+                        "Set {} file(s) to mode {:04o}",
+                        self.loose_permissions.len(),
+                        permissions::PRIVATE_MODE
+                    )
+                });
+            self.scan_permissions();
+            self.set_status(result);
+        }
+    }
+
+    /// Refreshes what the details pane knows about the selected host.
+    fn inspect_selected(&mut self) {
+        self.check_selected_identity();
+        self.check_selected_control_socket();
+    }
+
+    /// Whether the selected host's ControlPath socket existed when it was
+    /// last selected or connected to.
+    pub fn selected_multiplexed(&self) -> bool {
+        self.control_socket_active
+    }
+
+    /// Looks for the selected host's control socket, taking `ControlPath`
+    /// from its own block or else from the first pattern block that applies
+    /// to it, such as `Host *`. Checked on each selection
+    /// since sessions come and go.
+    fn check_selected_control_socket(&mut self) {
+        self.control_socket_active = self.selected_host().is_some_and(|entry| {
+            let template = match entry.get("ControlPath") {
+                "" => self
+                    .pattern_blocks
+                    .iter()
+                    .filter(|block| block.pattern_applies_to(&entry.host))
+                    .map(|block| block.get("ControlPath"))
+                    .find(|template| !template.is_empty())
+                    .unwrap_or(""),
+                template => template,
+            };
+            let local_user = env::var("USER").unwrap_or_default();
+            let tokens = ControlTokens {
+                alias: &entry.host,
+                hostname: entry.target_hostname(),
+                user: match entry.user() {
+                    "" => &local_user,
+                    user => user,
+                },
+                port: match entry.port() {
+                    "" => "22",
+                    port => port,
+                },
+                local_user: &local_user,
+                home: &self.home_dir,
+            };
+            expand_control_path(template, &tokens).is_some_and(|path| path.exists())
+        });
+    }
+
+    /// Stats the selected host's IdentityFile once and remembers the result,
+    /// along with whether the key is encrypted.
+    fn check_selected_identity(&mut self) {
+        let Some(&index) = self.visible.get(self.selected) else {
+            return;
+        };
+        if self.missing_identity.contains_key(&index) {
+            return;
+        }
+        let entry = &self.hosts[index];
+        let path = keys::expand_tilde(entry.identity_file(), &self.home_dir);
+        let missing = !entry.identity_file().is_empty() && !path.exists();
+        let encrypted = if entry.identity_file().is_empty() {
+            None
+        } else {
+            keys::key_is_encrypted(&path)
+        };
+        self.missing_identity.insert(index, missing);
+        self.encrypted_identity.insert(index, encrypted);
+    }
+
+    /// Starts a BatchMode ssh check against the selected host on a worker
+    /// thread; `poll_pending` reports the outcome in the status line.
+    pub fn test_selected_connection(&mut self) {
+        let Some(host) = self.connectable_host_name() else {
+            return;
+        };
+        let connection = self.ssh_connection.clone();
+        let target = host.clone();
+        self.start_operation(OpKind::TestConnection(host), move || {
+            OpResult::Status(connection.test_connection(&target).and_then(|test| {
+                if test.passed {
+                    Ok(test.summary(&target))
+                } else {
+                    Err(anyhow::anyhow!(test.summary(&target)))
+                }
+            }))
+        });
+    }
+
+    /// Runs `work` on a worker thread as the pending operation, unless one
+    /// is running already.
+    fn start_operation<F>(&mut self, operation: OpKind, work: F)
+    where
+        F: FnOnce() -> OpResult + Send + 'static,
+    {
+        if let Some(running) = &self.pending_operation {
+            self.status = Some(Status::Info(format!(
+                "{} is still running",
+                running.label()
+            )));
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(work());
+        });
+        self.status = None;
+        self.pending_operation = Some(operation);
+        self.pending_result = Some(receiver);
+        self.spinner_frame = 0;
+    }
+
+    /// Picks up the result of a background operation once it is done.
+    /// Called on every pass of the event loop.
+    pub fn poll_pending(&mut self) {
+        let Some(receiver) = &self.pending_result else {
+            return;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => OpResult::Status(Err(anyhow::anyhow!(
+                "{} stopped unexpectedly",
+                self.pending_operation
+                    .as_ref()
+                    .map(OpKind::label)
+                    .unwrap_or_default()
+            ))),
+        };
+        self.pending_operation = None;
+        self.pending_result = None;
+        self.spinner_frame = 0;
+        match result {
+            OpResult::Status(result) => self.set_status(result),
+            OpResult::Resolved(key, ips) => {
+                self.resolved.insert(key, ips);
+            }
+        }
+    }
+
+    /// Moves the spinner on by one frame while an operation is pending.
+    pub fn advance_spinner(&mut self) {
+        if self.pending_operation.is_some() {
+            self.spinner_frame = self.spinner_frame.wrapping_add(1);
+        }
+    }
+
+    /// Resolves the selected host's HostName (or alias) on demand, on a
+    /// worker thread since lookups can take a while. Results are cached per
+    /// name and picked up by `poll_pending`.
+    pub fn resolve_selected(&mut self) {
+        let Some(key) = self.selected_host().map(resolve_key) else {
+            return;
+        };
+        if self.resolved.contains_key(&key) {
+            return;
+        }
+        let resolver = self.resolver;
+        let target = key.clone();
+        self.start_operation(OpKind::Resolve(key), move || {
+            let (hostname, port) = target.rsplit_once(':').unwrap_or((&target, "22"));
+            let ips = resolver(hostname, port.parse().unwrap_or(22));
+            OpResult::Resolved(target, ips)
+        });
+    }
+
+    /// Addresses found for the selected host, if it has been resolved.
+    pub fn selected_resolution(&self) -> Option<&[IpAddr]> {
+        let key = resolve_key(self.selected_host()?);
+        self.resolved.get(&key).map(Vec::as_slice)
+    }
+
+    /// Copies the selected host's HostName, or its alias when it has none,
+    /// for pasting into other tools.
+    pub fn copy_selected_hostname(&mut self) {
+        let Some(value) = self
+            .selected_host()
+            .map(|entry| entry.target_hostname().to_string())
+        else {
+            return;
+        };
+        self.status = Some(match clipboard::copy(&value) {
+            Ok(()) => Status::Success(format!("Copied '{}' to the clipboard", value)),
+            Err(err) => Status::Error(err.to_string()),
+        });
+    }
+
+    /// Marks or unmarks the selected host for a batch open.
+    pub fn toggle_marked(&mut self) {
+        let Some(&index) = self.visible.get(self.selected) else {
+            return;
+        };
+        if !self.marked.remove(&index) {
+            self.marked.insert(index);
+        }
+    }
+
+    /// Aliases of the marked hosts in config order, or the selected host
+    /// when nothing is marked. Disabled hosts are never opened.
+    pub fn batch_hosts(&self) -> Vec<String> {
+        self.batch_indices()
+            .into_iter()
+            .map(|index| self.hosts[index].host.clone())
+            .collect()
+    }
+
+    /// Indices into `hosts` behind [`batch_hosts`](Self::batch_hosts), in
+    /// file order.
+    fn batch_indices(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = if self.marked.is_empty() {
+            self.visible
+                .get(self.selected)
+                .copied()
+                .into_iter()
+                .collect()
+        } else {
+            self.marked.iter().copied().collect()
+        };
+        indices.sort_unstable();
+        indices.retain(|&index| self.hosts.get(index).is_some_and(|entry| !entry.disabled));
+        indices
+    }
+
+    /// Opens every marked host in its own tmux window. Only works from
+    /// inside a tmux session.
+    pub fn open_marked_in_tmux(&mut self) {
+        if std::env::var_os("TMUX").is_none() {
+            self.status = Some(Status::Error(
+                "opening hosts in windows requires running inside tmux".to_string(),
+            ));
+            return;
+        }
+        let hosts = self.batch_hosts();
+        if hosts.is_empty() {
+            return;
+        }
+        let result = self.ssh_connection.open_in_tmux(&hosts);
+        if result.is_ok() {
+            for host in &hosts {
+                self.record_usage(host);
+            }
+            self.marked.clear();
+        }
+        self.set_status(result);
+    }
+
+    /// Remembers the connection for the recent panel. Persisting is best
+    /// effort; a failed write never affects the session result.
+    fn record_usage(&mut self, host: &str) {
+        self.saved_state.record_connection(host, unix_now());
+        if let Some(path) = &self.state_path {
+            let _ = self.saved_state.save(path);
+        }
+    }
+
+    pub fn open_recent(&mut self) {
+        self.recent_hosts = self
+            .saved_state
+            .recent_hosts(usize::MAX)
+            .into_iter()
+            .filter(|host| {
+                self.hosts
+                    .iter()
+                    .any(|entry| entry.host == *host && !entry.disabled)
+            })
+            .take(self.config.recent_limit)
+            .map(str::to_string)
+            .collect();
+        if self.recent_hosts.is_empty() {
+            self.status = Some(Status::Info("No recent connections yet".to_string()));
+            return;
+        }
+        self.recent = FilteredList::new(self.recent_hosts.iter().map(String::as_str));
+        self.state = AppState::Recent;
+    }
+
+    pub fn close_recent(&mut self) {
+        self.recent = FilteredList::default();
+        self.state = AppState::Normal;
+    }
+
+    pub fn recent_input(&mut self, ch: char) {
+        if ch.is_control() {
+            return;
+        }
+        self.recent.query.push(ch);
+        self.recent
+            .refilter(self.recent_hosts.iter().map(String::as_str));
+    }
+
+    pub fn recent_backspace(&mut self) {
+        self.recent.query.pop();
+        self.recent
+            .refilter(self.recent_hosts.iter().map(String::as_str));
+    }
+
+    /// Closes the panel and moves the main selection to the chosen host,
+    /// clearing the search if it hides the host. `None` if the host could
+    /// not be selected.
+    pub fn take_recent_host(&mut self) -> Option<String> {
+        let host = self
+            .recent
+            .selected_index()
+            .and_then(|index| self.recent_hosts.get(index).cloned());
+        self.close_recent();
+        host.filter(|host| self.reveal_host(host))
+    }
+
+    /// Lists the other hosts to pick the second side of a comparison from.
+    pub fn open_compare_picker(&mut self) {
+        let Some(selected) = self.selected_host().map(|entry| entry.host.clone()) else {
+            return;
+        };
+        self.compare_candidates = self
+            .hosts
+            .iter()
+            .map(|entry| entry.host.clone())
+            .filter(|host| *host != selected)
+            .collect();
+        if self.compare_candidates.is_empty() {
+            self.status = Some(Status::Info("No other host to compare with".to_string()));
+            return;
+        }
+        self.compare_picker = FilteredList::new(self.compare_candidates.iter().map(String::as_str));
+        self.state = AppState::ComparePicker;
+    }
+
+    pub fn close_compare_picker(&mut self) {
+        self.compare_picker = FilteredList::default();
+        self.state = AppState::Normal;
+    }
+
+    pub fn compare_picker_input(&mut self, ch: char) {
+        if ch.is_control() {
+            return;
+        }
+        self.compare_picker.query.push(ch);
+        self.compare_picker
+            .refilter(self.compare_candidates.iter().map(String::as_str));
+    }
+
+    pub fn compare_picker_backspace(&mut self) {
+        self.compare_picker.query.pop();
+        self.compare_picker
+            .refilter(self.compare_candidates.iter().map(String::as_str));
+    }
+
+    /// Diffs the selected host against the picked one and shows the result.
+    pub fn compare_with_picked(&mut self) {
+        let picked = self
+            .compare_picker
+            .selected_index()
+            .and_then(|index| self.compare_candidates.get(index));
+        let (Some(left), Some(right)) = (
+            self.selected_host(),
+            picked.and_then(|host| self.hosts.iter().find(|entry| entry.host == *host)),
+        ) else {
+            self.close_compare_picker();
+            return;
+        };
+        self.comparison = Some(Comparison {
+            left: left.host.clone(),
+            right: right.host.clone(),
+            diffs: left.diff(right),
+        });
+        self.compare_picker = FilteredList::default();
+        self.state = AppState::Compare;
+    }
+
+    /// Lists `default` and the configured profiles to switch between.
+    pub fn open_profile_picker(&mut self) {
+        if self.config.profiles.is_empty() {
+            self.status = Some(Status::Info(
+                "No profiles configured; add a [profiles] table to config.toml".to_string(),
+            ));
+            return;
+        }
+        self.profile_candidates = Vec::new();
+        if !self.config.profiles.contains_key(DEFAULT_PROFILE) {
+            self.profile_candidates.push(DEFAULT_PROFILE.to_string());
+        }
+        self.profile_candidates
+            .extend(self.config.profiles.keys().cloned());
+        self.profile_picker = FilteredList::new(self.profile_candidates.iter().map(String::as_str));
+        self.state = AppState::ProfilePicker;
+    }
+
+    pub fn close_profile_picker(&mut self) {
+        self.profile_picker = FilteredList::default();
+        self.state = AppState::Normal;
+    }
+
+    pub fn profile_picker_input(&mut self, ch: char) {
+        if ch.is_control() {
+            return;
+        }
+        self.profile_picker.query.push(ch);
+        self.profile_picker
+            .refilter(self.profile_candidates.iter().map(String::as_str));
+    }
+
+    pub fn profile_picker_backspace(&mut self) {
+        self.profile_picker.query.pop();
+        self.profile_picker
+            .refilter(self.profile_candidates.iter().map(String::as_str));
+    }
+
+    pub fn switch_to_picked_profile(&mut self) {
+        let picked = self
+            .profile_picker
+            .selected_index()
+            .and_then(|index| self.profile_candidates.get(index))
+            .cloned();
+        self.close_profile_picker();
+        if let Some(name) = picked {
+            self.switch_profile(&name);
+        }
+    }
+
+    /// Points reads and writes at the profile's file and reloads the hosts
+    /// from it. `default` goes back to the config found at startup unless
+    /// `[profiles]` defines it.
+    pub fn switch_profile(&mut self, name: &str) {
+        let path = match self.config.profiles.get(name) {
+            Some(path) => keys::expand_tilde(path, &self.home_dir),
+            None if name == DEFAULT_PROFILE => self.default_config_path.clone(),
+            None => {
+                self.status = Some(Status::Error(format!("No profile named '{}'", name)));
+                return;
+            }
+        };
+        let previous = std::mem::replace(&mut self.ssh_config_path, path);
+        // Another file's stamp says nothing about this one.
+        self.config_stamp = None;
+        self.selected = 0;
+        match self.refresh_hosts(None) {
+            Ok(()) => {
+                self.active_profile = self
+                    .config
+                    .profiles
+                    .contains_key(name)
+                    .then(|| name.to_string());
+                self.status = Some(Status::Success(format!(
+                    "Switched to profile '{}' ({}, {} hosts)",
+                    name,
+                    self.ssh_config_path.display(),
+                    self.hosts.len()
+                )));
+            }
+            Err(err) => {
+                self.ssh_config_path = previous;
+                self.config_stamp = None;
+                let _ = self.refresh_hosts(None);
+                self.status = Some(Status::Error(err.to_string()));
+            }
+        }
+    }
+
+    pub fn close_comparison(&mut self) {
+        self.comparison = None;
+        self.state = AppState::Normal;
+    }
+
+    fn run_post_connect_hook(&self, host: &str) -> Option<String> {
+        let template = self.config.post_connect_command.as_deref()?;
+        if template.trim().is_empty() {
+            return None;
+        }
+        let hostname = self
+            .hosts
+            .iter()
+            .find(|entry| entry.host == host)
+            .map(|entry| entry.hostname())
+            .unwrap_or(host);
+        Some(hook::run_post_connect(template, host, hostname))
+    }
+
+    /// The enabled host `alias` names, matched per `alias_match` the same
+    /// way the editor finds blocks.
+    pub fn enabled_host(&self, alias: &str) -> Option<&HostEntry> {
+        self.hosts
+            .iter()
+            .find(|entry| !entry.disabled && self.config.alias_match.matches(alias, &entry.host))
+    }
+
+    pub fn preferred_tool(&self, host: &str) -> ConnectTool {
+        self.hosts
+            .iter()
+            .find(|entry| entry.host == host)
+            .and_then(HostEntry::connect_with)
+            .and_then(ConnectTool::from_name)
+            .unwrap_or(ConnectTool::Ssh)
+    }
+
+    /// Clears the status message once it has been shown for
+    /// `status_timeout` seconds, twice that for errors. Called on every tick
+    /// of the event loop; a new message restarts the clock.
+    pub fn expire_status(&mut self, now: Instant) {
+        let Some(status) = &self.status else {
+            self.status_since = None;
+            return;
+        };
+        match &self.status_since {
+            Some((seen, since)) if seen == status => {
+                let mut timeout = Duration::from_secs(self.config.status_timeout);
+                if matches!(status, Status::Error(_)) {
+                    timeout *= 2;
+                }
+                if !timeout.is_zero() && now.duration_since(*since) >= timeout {
+                    self.status = None;
+                    self.status_since = None;
+                }
+            }
+            _ => self.status_since = Some((status.clone(), now)),
+        }
+    }
+
+    pub fn set_status(&mut self, result: Result<String>) {
+        match result {
+            Ok(msg) => self.status = Some(Status::Success(msg)),
+            Err(e) => self.status = Some(Status::Error(e.to_string())),
+        }
+    }
+
+    pub fn is_form_active(&self) -> bool {
+        matches!(self.state, AppState::Edit | AppState::New)
+    }
+
+    pub fn open_search(&mut self) {
+        self.state = AppState::Search;
+    }
+
+    /// Leaves search mode, keeping the filter when `keep` is set and
+    /// clearing it otherwise.
+    pub fn close_search(&mut self, keep: bool) {
+        if !keep {
+            self.search_query.clear();
+            self.apply_search();
+        }
+        self.search_error = None;
+        self.state = AppState::Normal;
+    }
+
+    pub fn search_input(&mut self, ch: char) {
+        if ch.is_control() {
+            return;
+        }
+        self.search_query.push(ch);
+        self.apply_search();
+    }
+
+    pub fn search_backspace(&mut self) {
+        self.search_query.pop();
+        self.apply_search();
+    }
+
+    pub fn toggle_match_mode(&mut self) {
+        self.match_mode = self.match_mode.toggled();
+        self.apply_search();
+    }
+
+    /// Filters the host list by `host` and `hostname`. A query that does
+    /// not compile leaves the previous results in place.
+    fn apply_search(&mut self) {
+        let (tags, query) = split_tag_filters(&self.search_query);
+        if query.is_empty() {
+            self.host_matcher = None;
+            self.search_error = None;
+        } else {
+            match Matcher::new(&query, self.match_mode) {
+                Ok(matcher) => {
+                    self.host_matcher = Some(matcher);
+                    self.search_error = None;
+                }
+                Err(err) => {
+                    let reason = err.to_string();
+                    let reason = reason.lines().last().unwrap_or_default().trim();
+                    self.search_error = Some(format!("invalid regex: {}", reason));
+                    return;
+                }
+            }
+        }
+        self.tag_filter = tags;
+        self.selected = 0;
+        self.rebuild_view();
+    }
+
+    pub fn open_palette(&mut self) {
+        self.palette = FilteredList::default();
+        self.refilter_palette();
+        self.state = AppState::Palette;
+    }
+
+    /// Narrows the palette to actions whose label or id matches the query.
+    fn refilter_palette(&mut self) {
+        self.palette.refilter_any(
+            Action::ALL
+                .iter()
+                .map(|action| [action.label(), action.id()]),
+        );
+    }
+
+    pub fn close_palette(&mut self) {
+        self.palette = FilteredList::default();
+        self.state = AppState::Normal;
+    }
+
+    pub fn palette_input(&mut self, ch: char) {
+        if ch.is_control() {
+            return;
+        }
+        self.palette.query.push(ch);
+        self.refilter_palette();
+    }
+
+    pub fn palette_backspace(&mut self) {
+        self.palette.query.pop();
+        self.refilter_palette();
+    }
+
+    /// Closes the palette and returns the highlighted action, if any.
+    pub fn take_palette_action(&mut self) -> Option<Action> {
+        let action = self
+            .palette
+            .selected_index()
+            .and_then(|index| Action::ALL.get(index).copied());
+        self.close_palette();
+        action
+    }
+
+    pub fn open_quick_connect(&mut self) {
+        self.quick_connect.clear();
+        self.state = AppState::QuickConnect;
+    }
+
+    pub fn close_quick_connect(&mut self) {
+        self.quick_connect.clear();
+        self.state = AppState::Normal;
+    }
+
+    pub fn quick_connect_input(&mut self, ch: char) {
+        if !ch.is_control() {
+            self.quick_connect.push(ch);
+        }
+    }
+
+    pub fn quick_connect_backspace(&mut self) {
+        self.quick_connect.pop();
+    }
+
+    /// Fills the prompt in with the best match, like Tab in a shell.
+    pub fn complete_quick_connect(&mut self) {
+        if let Some(alias) = self.quick_connect_match() {
+            self.quick_connect = alias.to_string();
+        }
+    }
+
+    /// The enabled alias the current prompt would connect to.
+    pub fn quick_connect_match(&self) -> Option<&str> {
+        best_alias_match(
+            &self.quick_connect,
+            self.hosts
+                .iter()
+                .filter(|entry| !entry.disabled && !entry.host.contains(['*', '?']))
+                .map(|entry| entry.host.as_str()),
+        )
+    }
+
+    /// Prompts for a user to connect as this once, starting from the
+    /// host's own `User`.
+    pub fn open_connect_as(&mut self) {
+        let Some(user) = self.selected_host().map(|entry| entry.user().to_string()) else {
+            return;
+        };
+        if self.connectable_host_name().is_none() {
+            return;
+        }
+        self.connect_as_user = user;
+        self.state = AppState::ConnectAs;
+    }
+
+    pub fn close_connect_as(&mut self) {
+        self.connect_as_user.clear();
+        self.state = AppState::Normal;
+    }
+
+    pub fn connect_as_input(&mut self, ch: char) {
+        if !ch.is_control() {
+            self.connect_as_user.push(ch);
+        }
+    }
+
+    pub fn connect_as_backspace(&mut self) {
+        self.connect_as_user.pop();
+    }
+
+    /// Why the typed username can't be used, if it can't.
+    pub fn connect_as_problem(&self) -> Option<&'static str> {
+        if self.connect_as_user.is_empty() {
+            Some("enter a username")
+        } else if self.connect_as_user.contains(char::is_whitespace) {
+            Some("usernames can't contain spaces")
+        } else {
+            None
+        }
+    }
+
+    /// Accepts a valid username for the next connection and closes the
+    /// prompt. Returns whether the connection should go ahead.
+    pub fn accept_connect_as(&mut self) -> bool {
+        if self.connect_as_problem().is_some() {
+            return false;
+        }
+        self.connect_user_override = Some(std::mem::take(&mut self.connect_as_user));
+        self.state = AppState::Normal;
+        true
+    }
+
+    /// Prompts for extra ssh arguments for this connection, starting from
+    /// the ones last used with the host.
+    pub fn open_connect_args(&mut self) {
+        let Some(host) = self.connectable_host_name() else {
+            return;
+        };
+        self.connect_args = self
+            .saved_state
+            .last_args
+            .get(&host)
+            .cloned()
+            .unwrap_or_default();
+        self.args_history_position = None;
+        self.state = AppState::ConnectArgs;
+    }
+
+    pub fn close_connect_args(&mut self) {
+        self.connect_args.clear();
+        self.args_history_position = None;
+        self.state = AppState::Normal;
+    }
+
+    pub fn connect_args_input(&mut self, ch: char) {
+        if !ch.is_control() {
+            self.connect_args.push(ch);
+        }
+    }
+
+    pub fn connect_args_backspace(&mut self) {
+        self.connect_args.pop();
+    }
+
+    /// Whether Up/Down have earlier arguments to offer.
+    pub fn has_args_history(&self) -> bool {
+        !self.saved_state.args_history.is_empty()
+    }
+
+    /// Steps through `args_history`: `older` goes back in time and stops at
+    /// the oldest entry; going newer past the newest restores the host's
+    /// own last arguments.
+    pub fn cycle_args_history(&mut self, older: bool) {
+        let history = &self.saved_state.args_history;
+        if history.is_empty() {
+            return;
+        }
+        self.args_history_position = match (self.args_history_position, older) {
+            (None, true) => Some(0),
+            (Some(position), true) => Some((position + 1).min(history.len() - 1)),
+            (Some(0) | None, false) => None,
+            (Some(position), false) => Some(position - 1),
+        };
+        self.connect_args = match self.args_history_position {
+            Some(position) => history[position].clone(),
+            None => self
+                .connectable_host_name()
+                .and_then(|host| self.saved_state.last_args.get(&host).cloned())
+                .unwrap_or_default(),
+        };
+    }
+
+    /// Why the typed arguments can't be split into words, if they can't.
+    pub fn connect_args_problem(&self) -> Option<&'static str> {
+        split_shell_words(&self.connect_args)
+            .is_none()
+            .then_some("unbalanced quote")
+    }
+
+    /// Remembers the typed arguments for the host and sets them up for the
+    /// next connection, split into words the way a shell would. Empty
+    /// arguments connect as usual and make the host forget its last ones.
+    /// Returns whether the connection should go ahead.
+    pub fn accept_connect_args(&mut self) -> bool {
+        let Some(words) = split_shell_words(&self.connect_args) else {
+            return false;
+        };
+        let args = std::mem::take(&mut self.connect_args);
+        if let Some(host) = self.connectable_host_name() {
+            self.saved_state.record_args(&host, &args);
+            if let Some(path) = &self.state_path {
+                let _ = self.saved_state.save(path);
+            }
+        }
+        self.connect_args_override = Some(words).filter(|words| !words.is_empty());
+        self.args_history_position = None;
+        self.state = AppState::Normal;
+        true
+    }
+
+    pub fn open_save_as(&mut self) {
+        self.save_as_path.clear();
+        self.state = AppState::SaveAs;
+    }
+
+    pub fn close_save_as(&mut self) {
+        self.save_as_path.clear();
+        self.state = AppState::Normal;
+    }
+
+    pub fn save_as_input(&mut self, ch: char) {
+        if !ch.is_control() {
+            self.save_as_path.push(ch);
+        }
+    }
+
+    pub fn save_as_backspace(&mut self) {
+        self.save_as_path.pop();
+    }
+
+    /// Writes every user host, disabled ones included, to the typed path as
+    /// ssh config. The live config is never the destination; it is already
+    /// kept up to date by every edit.
+    pub fn save_as(&mut self) {
+        let typed = self.save_as_path.trim().to_string();
+        if typed.is_empty() {
+            return;
+        }
+        self.close_save_as();
+        let path = keys::expand_tilde(&typed, &self.home_dir);
+        if path == self.ssh_config_path {
+            self.status = Some(Status::Error(
+                "Choose a path other than the SSH config itself".to_string(),
+            ));
+            return;
+        }
+        let hosts: Vec<HostEntry> = self
+            .hosts
+            .iter()
+            .filter(|entry| !entry.is_read_only())
+            .cloned()
+            .collect();
+        let result = export_host_entries(&path, &hosts)
+            .map(|()| format!("Saved {} hosts to {}", hosts.len(), typed));
+        self.set_status(result);
+    }
+
+    /// Closes the prompt and selects the matched host, clearing a search
+    /// that hides it, so the regular connect flow picks it up.
+    pub fn take_quick_connect_host(&mut self) -> Option<String> {
+        let host = self.quick_connect_match().map(str::to_string);
+        self.close_quick_connect();
+        host.filter(|host| self.reveal_host(host))
+    }
+
+    /// Returns the prompt to show before connecting when the selected host
+    /// looks like production and `confirm_prod_connect` is enabled.
+    pub fn connect_confirmation_prompt(&self) -> Option<String> {
+        if !self.config.confirm_prod_connect {
+            return None;
+        }
+        let entry = self.selected_host()?;
+        let tagged = entry
+            .tags()
+            .iter()
+            .any(|tag| tag.eq_ignore_ascii_case("prod"));
+        let matched = self
+            .config
+            .prod_patterns
+            .iter()
+            .any(|pattern| pattern_matches(pattern, &entry.host));
+        (tagged || matched).then(|| {
+            format!(
+                "Connect to production host '{}' ({})? [y/N]",
+                entry.host,
+                entry.connection_target()
+            )
+        })
+    }
+
+    pub fn request_confirmation(&mut self, prompt: String, action: Action) {
+        self.confirmation = Some(Confirmation { prompt, action });
+        self.state = AppState::Confirm;
+    }
+
+    /// Accepts the pending confirmation and returns the action to run.
+    pub fn take_confirmation(&mut self) -> Option<Action> {
+        self.state = AppState::Normal;
+        self.confirmation
+            .take()
+            .map(|confirmation| confirmation.action)
+    }
+
+    /// Lists the config issues found on load, if there are any.
+    pub fn open_issues(&mut self) {
+        if self.lint_warnings.is_empty() {
+            self.status = Some(Status::Info("No config issues found".to_string()));
+        } else {
+            self.state = AppState::Issues;
+        }
+    }
+
+    pub fn close_issues(&mut self) {
+        self.state = AppState::Normal;
+    }
+
+    /// Shows the latest connection attempts from the history file.
+    pub fn open_history(&mut self) {
+        self.history = self
+            .history_path
+            .as_deref()
+            .map(|path| history::load_recent(path, HISTORY_LIMIT))
+            .unwrap_or_default();
+        if self.history.is_empty() {
+            self.status = Some(Status::Info("No connections recorded yet".to_string()));
+        } else {
+            self.state = AppState::History;
+        }
+    }
+
+    pub fn close_history(&mut self) {
+        self.state = AppState::Normal;
+        self.history.clear();
+    }
+
+    pub fn cancel_confirmation(&mut self) {
+        self.state = AppState::Normal;
+        self.confirmation = None;
+        self.connect_user_override = None;
+        self.connect_args_override = None;
+        self.pending_import.clear();
+        self.status = Some(Status::Info("Cancelled".to_string()));
+    }
+
+    pub fn enter_edit_mode(&mut self) {
+        if let Some(entry) = self.selected_host().cloned() {
+            if self.refuse_read_only(&entry.host) {
+                return;
+            }
+            if entry.disabled {
+                self.status = Some(Status::Info(format!(
+                    "Host '{}' is disabled; press # to enable it before editing",
+                    entry.host
+                )));
+                return;
+            }
+            self.original_occurrence = self.selected_occurrence();
+            self.form_tags = entry.tags().join(" ");
+            self.form_note = entry.note();
+            self.form_entry = entry.clone();
+            self.original_host_name = Some(entry.host);
+            self.form_field = FormField::Host;
+            self.form_error = None;
+            self.update_form_hint();
+            self.state = AppState::Edit;
+        }
+    }
+
+    /// Which block of its alias the selected host is, counting from zero,
+    /// so changes to a twice-defined alias land in the right place.
+    fn selected_occurrence(&self) -> usize {
+        self.occurrence(self.visible[self.selected])
+    }
+
+    /// Which active (or, for a disabled host, disabled) block of its alias
+    /// `hosts[index]` is, matching aliases per `alias_match` the way the
+    /// file is searched.
+    fn occurrence(&self, index: usize) -> usize {
+        let entry = &self.hosts[index];
+        self.hosts[..index]
+            .iter()
+            .filter(|other| {
+                other.disabled == entry.disabled
+                    && self.config.alias_match.matches(&other.host, &entry.host)
+            })
+            .count()
+    }
+
+    /// Loads the selected host's block, as written in the file, into a
+    /// full-screen text editor.
+    pub fn enter_raw_edit_mode(&mut self) {
+        let Some(entry) = self.selected_host().cloned() else {
+            return;
+        };
+        if self.refuse_read_only(&entry.host) {
+            return;
+        }
+        if entry.disabled {
+            self.status = Some(Status::Info(format!(
+                "Host '{}' is disabled; press # to enable it before editing",
+                entry.host
+            )));
+            return;
+        }
+        self.original_occurrence = self.selected_occurrence();
+        match read_host_block(
+            &self.ssh_config_path,
+            &entry.host,
+            self.original_occurrence,
+            self.config.alias_match,
+        ) {
+            Ok(lines) => {
+                self.raw_editor = TextEditor::new(lines);
+                self.original_host_name = Some(entry.host);
+                self.form_error = None;
+                self.state = AppState::RawEdit;
+            }
+            Err(err) => self.status = Some(Status::Error(err.to_string())),
+        }
+    }
+
+    /// Re-parses the edited text and writes it over the original block.
+    /// Anything that doesn't parse or validate keeps the editor open with
+    /// the error shown.
+    pub fn save_raw_edit(&mut self) {
+        if self.state != AppState::RawEdit {
+            return;
+        }
+        let entry = match parse_host_block(&self.raw_editor.text()) {
+            Ok(entry) => entry,
+            Err(err) => {
+                self.form_error = Some(err.to_string());
+                return;
+            }
+        };
+        if let Err(err) = entry.validate() {
+            self.form_error = Some(err.to_string());
+            return;
+        }
+
+        let original = self
+            .original_host_name
+            .clone()
+            .unwrap_or_else(|| entry.host.clone());
+        let result = update_host_entry_occurrence(
+            &self.ssh_config_path,
+            &original,
+            self.original_occurrence,
+            &entry,
+            self.config.alias_match,
+        )
+        .and_then(|warning| {
+            self.refresh_after_write(Some(entry.host.clone()))
+                .map(|()| warning)
+        });
+        match result {
+            Ok(warning) => {
+                self.cancel_raw_edit();
+                let mut status = format!("Host '{}' updated successfully", entry.host);
+                if let Some(warning) = warning {
+                    status.push_str(&format!("; warning: {}", warning));
+                }
+                self.status = Some(Status::Success(status));
+            }
+            Err(err) => self.form_error = Some(err.to_string()),
+        }
+    }
+
+    /// The alias whose block is in the raw editor.
+    pub fn raw_edit_host(&self) -> &str {
+        self.original_host_name.as_deref().unwrap_or_default()
+    }
+
+    pub fn cancel_raw_edit(&mut self) {
+        self.state = AppState::Normal;
+        self.raw_editor = TextEditor::default();
+        self.form_error = None;
+        self.original_host_name = None;
+    }
+
+    /// Opens an empty editor to paste ssh_config text into, or to type the
+    /// path of a file holding it.
+    pub fn open_import(&mut self) {
+        self.raw_editor = TextEditor::default();
+        self.form_error = None;
+        self.state = AppState::Import;
+    }
+
+    pub fn cancel_import(&mut self) {
+        self.state = AppState::Normal;
+        self.raw_editor = TextEditor::default();
+        self.form_error = None;
+    }
+
+    /// Parses the import text and asks before upserting the hosts found,
+    /// naming which are new and which replace a host of the same alias.
+    /// A single line without hosts is read as the path of a file to parse.
+    pub fn preview_import(&mut self) {
+        if self.state != AppState::Import {
+            return;
+        }
+        let text = self.raw_editor.text();
+        let mut entries = parse_host_entries(&text);
+        let path = text.trim();
+        if entries.is_empty() && !path.is_empty() && !path.contains('\n') {
+            let path = keys::expand_tilde(path, &self.home_dir);
+            match fs::read_to_string(&path) {
+                Ok(contents) => entries = parse_host_entries(&contents),
+                Err(err) => {
+                    self.form_error = Some(format!("Failed to read {}: {}", path.display(), err));
+                    return;
+                }
+            }
+        }
+        entries.retain(|entry| !entry.disabled);
+        if entries.is_empty() {
+            self.form_error = Some("No Host blocks found to import".to_string());
+            return;
+        }
+        if let Some(err) = entries.iter().find_map(|entry| {
+            entry
+                .validate()
+                .err()
+                .map(|err| format!("Host '{}': {}", entry.host, err))
+        }) {
+            self.form_error = Some(err);
+            return;
+        }
+
+        let listed: Vec<String> = entries
+            .iter()
+            .map(|entry| {
+                let exists = self
+                    .hosts
+                    .iter()
+                    .any(|other| other.host == entry.host && !other.is_read_only());
+                format!(
+                    "'{}' ({})",
+                    entry.host,
+                    if exists { "replaces existing" } else { "new" }
+                )
+            })
+            .collect();
+        let prompt = format!("Import {}? [y/N]", listed.join(", "));
+        self.cancel_import();
+        self.pending_import = entries;
+        self.request_confirmation(prompt, Action::Import);
+    }
+
+    /// Upserts the hosts of an accepted import preview.
+    pub fn import_pending(&mut self) {
+        let entries = std::mem::take(&mut self.pending_import);
+        if entries.is_empty() {
+            return;
+        }
+        let focus = entries.first().map(|entry| entry.host.clone());
+        let result = merge_host_entries(&self.ssh_config_path, &entries, |_| {
+            ConflictPolicy::Overwrite
+        })
+        .and_then(|summary| self.refresh_after_write(focus).map(|()| summary));
+        self.status = Some(match result {
+            Ok(summary) => Status::Success(format!("Imported hosts: {}", summary)),
+            Err(err) => Status::Error(format!("{:#}", err)),
+        });
+    }
+
+    /// Opens an empty form, pre-filled with the configured `[defaults]`.
+    pub fn enter_new_mode(&mut self) {
+        let defaults = &self.config.defaults;
+        self.form_entry = HostEntry::default();
+        self.form_tags.clear();
+        self.form_note.clear();
+        // Every form field is seeded, empty or not, so the directives keep
+        // the form's order whatever gets filled in later.
+        for (keyword, value) in [
+            ("HostName", ""),
+            ("User", defaults.user.as_str()),
+            ("Port", defaults.port.as_str()),
+            ("IdentityFile", defaults.identity_file.as_str()),
+        ] {
+            self.form_entry.set(keyword, value.trim());
+        }
+        self.original_host_name = None;
+        self.form_field = FormField::Host;
+        self.form_error = None;
+        self.update_form_hint();
+        self.state = AppState::New;
+    }
+
+    pub fn enter_duplicate_mode(&mut self) {
+        if let Some(entry) = self.selected_host().cloned() {
+            self.form_tags = entry.tags().join(" ");
+            self.form_note = entry.note();
+            self.form_entry = HostEntry {
+                host: format!("{}-copy", entry.host),
+                disabled: false,
+                origin: Origin::User,
+                ..entry
+            };
+            self.original_host_name = None;
+            self.form_field = FormField::Host;
+            self.form_error = None;
+            self.update_form_hint();
+            self.state = AppState::New;
+        }
+    }
+
+    /// Asks before deleting the marked hosts, or the selected one when
+    /// nothing is marked, naming every host that would go.
+    pub fn request_delete(&mut self) {
+        let hosts = self.batch_hosts();
+        if hosts.is_empty() || hosts.iter().any(|host| self.refuse_read_only(host)) {
+            return;
+        }
+        let prompt = format!("Delete {}? [y/N]", quoted_list(&hosts));
+        self.request_confirmation(prompt, Action::Delete);
+    }
+
+    /// Deletes the marked hosts, or the selected one, in a single rewrite.
+    /// Each goes by its own block, so deleting one of a twice-defined alias
+    /// leaves the other.
+    pub fn delete_selected(&mut self) {
+        let indices = self.batch_indices();
+        let hosts = self.batch_hosts();
+        if hosts.is_empty() || hosts.iter().any(|host| self.refuse_read_only(host)) {
+            return;
+        }
+        let blocks: Vec<(String, usize)> = indices
+            .into_iter()
+            .map(|index| (self.hosts[index].host.clone(), self.occurrence(index)))
+            .collect();
+        match delete_host_entry_occurrences(&self.ssh_config_path, &blocks, self.config.alias_match)
+            .and_then(|()| self.refresh_after_write(None))
+        {
+            Ok(()) if hosts.len() == 1 => {
+                self.status = Some(Status::Success(format!("Host '{}' deleted", hosts[0])))
+            }
+            Ok(()) => {
+                self.status = Some(Status::Success(format!(
+                    "Deleted {} hosts: {}",
+                    hosts.len(),
+                    quoted_list(&hosts)
+                )))
+            }
+            Err(err) => self.status = Some(Status::Error(err.to_string())),
+        }
+    }
+
+    /// Marks every host in the current view, or clears the marks when they
+    /// are all marked already.
+    pub fn toggle_mark_all(&mut self) {
+        if self.visible.iter().all(|index| self.marked.contains(index)) {
+            self.marked.clear();
+        } else {
+            self.marked.extend(self.visible.iter().copied());
+        }
+    }
+
+    /// Moves the selected host's block past its neighbour in the file.
+    pub fn move_selected(&mut self, direction: MoveDirection) {
+        let Some(entry) = self.selected_host() else {
+            return;
+        };
+        let host = entry.host.clone();
+        if entry.disabled {
+            self.status = Some(Status::Info(
+                "Restore the host before moving it".to_string(),
+            ));
+            return;
+        }
+        if self.refuse_read_only(&host) {
+            return;
+        }
+        match move_host_block_occurrence(
+            &self.ssh_config_path,
+            &host,
+            self.selected_occurrence(),
+            self.config.alias_match,
+            direction,
+        )
+        .and_then(|()| self.refresh_after_write(Some(host.clone())))
+        {
+            Ok(()) => {
+                let way = match direction {
+                    MoveDirection::Up => "up",
+                    MoveDirection::Down => "down",
+                };
+                self.status = Some(Status::Success(format!("Moved '{}' {}", host, way)));
+            }
+            Err(err) => self.status = Some(Status::Error(err.to_string())),
+        }
+    }
+
+    pub fn reload(&mut self) {
+        let focus = self.selected_host_name().map(str::to_string);
+        match self.refresh_hosts(focus) {
+            Ok(()) => {
+                self.status = Some(Status::Success(format!(
+                    "Reloaded {} hosts",
+                    self.hosts.len()
+                )))
+            }
+            Err(err) => self.status = Some(Status::Error(err.to_string())),
+        }
+    }
+
+    pub fn cancel_form(&mut self) {
+        self.state = AppState::Normal;
+        self.form_entry = HostEntry::default();
+        self.form_error = None;
+        self.form_hint = None;
+        self.field_hint = None;
+        self.original_host_name = None;
+    }
+
+    /// Opens a popup of key pairs found in `~/.ssh` while the IdentityFile
+    /// field is focused.
+    pub fn open_key_picker(&mut self) {
+        if !self.is_form_active() || self.form_field != FormField::IdentityFile {
+            return;
+        }
+        self.key_candidates = self.identity_candidates();
+        if self.key_candidates.is_empty() {
+            self.form_error = Some(format!("No key pairs found in {}", self.ssh_dir.display()));
+            return;
+        }
+        self.key_picker = FilteredList::new(self.key_candidates.iter().map(String::as_str));
+        self.picker_return = self.state;
+        self.state = AppState::KeyPicker;
+    }
+
+    /// The form mode underneath the key picker popup.
+    pub fn form_return_mode(&self) -> AppState {
+        self.picker_return
+    }
+
+    pub fn close_key_picker(&mut self) {
+        self.key_picker = FilteredList::default();
+        self.state = self.picker_return;
+    }
+
+    pub fn key_picker_input(&mut self, ch: char) {
+        if ch.is_control() {
+            return;
+        }
+        self.key_picker.query.push(ch);
+        self.key_picker
+            .refilter(self.key_candidates.iter().map(String::as_str));
+    }
+
+    pub fn key_picker_backspace(&mut self) {
+        self.key_picker.query.pop();
+        self.key_picker
+            .refilter(self.key_candidates.iter().map(String::as_str));
+    }
+
+    /// Fills the IdentityFile field with the highlighted key.
+    pub fn pick_key(&mut self) {
+        if let Some(key) = self
+            .key_picker
+            .selected_index()
+            .and_then(|index| self.key_candidates.get(index).cloned())
+        {
+            self.form_entry.set("IdentityFile", &key);
+            self.form_error = None;
+        }
+        self.close_key_picker();
+    }
+
+    /// Replaces the IdentityFile value with the next key pair in `~/.ssh`,
+    /// wrapping around, so repeated presses cycle through the suggestions.
+    pub fn cycle_identity_suggestion(&mut self) {
+        if !self.is_form_active() || self.form_field != FormField::IdentityFile {
+            return;
+        }
+        let candidates = self.identity_candidates();
+        if candidates.is_empty() {
+            self.form_error = Some(format!("No key pairs found in {}", self.ssh_dir.display()));
+            return;
+        }
+        let next = candidates
+            .iter()
+            .position(|candidate| *candidate == self.form_entry.identity_file())
+            .map_or(0, |index| (index + 1) % candidates.len());
+        self.form_entry
+            .set("IdentityFile", &candidates[next].clone());
+        self.form_error = None;
+    }
+
+    /// Tab on the IdentityFile field completes the path typed so far, and
+    /// repeated presses cycle through the matches. Anywhere else, or when
+    /// nothing matches, Tab moves to the next field.
+    pub fn complete_or_focus_next(&mut self) {
+        if self.is_form_active()
+            && self.form_field == FormField::IdentityFile
+            && self.complete_identity_path()
+        {
+            return;
+        }
+        self.focus_next_field();
+    }
+
+    fn complete_identity_path(&mut self) -> bool {
+        let current = self.form_entry.identity_file().to_string();
+        let cycling = self.path_completions.len() > 1
+            && self.path_completions.get(self.completion_index) == Some(&current);
+        if cycling {
+            self.completion_index = (self.completion_index + 1) % self.path_completions.len();
+        } else {
+            if current.is_empty() {
+                return false;
+            }
+            self.path_completions = keys::complete_path(&current, &self.home_dir);
+            self.completion_index = 0;
+            if self.path_completions.is_empty() || self.path_completions == [current.clone()] {
+                self.path_completions.clear();
+                return false;
+            }
+        }
+        self.form_entry.set(
+            "IdentityFile",
+            &self.path_completions[self.completion_index].clone(),
+        );
+        true
+    }
+
+    fn identity_candidates(&self) -> Vec<String> {
+        keys::list_identity_files(&self.ssh_dir)
+            .into_iter()
+            .map(|name| self.key_reference(&name))
+            .collect()
+    }
+
+    /// Spells keys under `~/.ssh` the way ssh configs usually do.
+    fn key_reference(&self, name: &str) -> String {
+        match Config::ssh_dir() {
+            Ok(default_dir) if default_dir == self.ssh_dir => format!("~/.ssh/{}", name),
+            _ => self.ssh_dir.join(name).display().to_string(),
+        }
+    }
+
+    pub fn focus_next_field(&mut self) {
+        if self.is_form_active() {
+            self.form_field = self.form_field.next();
+            self.update_field_hint();
+        }
+    }
+
+    pub fn focus_previous_field(&mut self) {
+        if self.is_form_active() {
+            self.form_field = self.form_field.previous();
+            self.update_field_hint();
+        }
+    }
+
+    pub fn handle_form_input(&mut self, ch: char) {
+        if !self.is_form_active() {
+            return;
+        }
+        if matches!(ch, '\n' | '\r' | '\t') {
+            self.form_error = Some(SINGLE_LINE_ERROR.to_string());
+            return;
+        }
+        if ch.is_control() {
+            return;
+        }
+        self.form_error = None;
+        let field = self.current_field_mut();
+        field.push(ch);
+        self.update_form_hint();
+    }
+
+    /// Inserts pasted text into the focused field. Text spanning several
+    /// lines (or containing tabs) is refused as a whole rather than being
+    /// silently flattened. The raw block editor takes pastes line by line.
+    pub fn handle_form_paste(&mut self, text: &str) {
+        if matches!(self.state, AppState::RawEdit | AppState::Import) {
+            for c in text.chars().filter(|&c| c != '\r') {
+                match c {
+                    '\n' => self.raw_editor.newline(),
+                    c => self.raw_editor.insert(c),
+                }
+            }
+            return;
+        }
+        if !self.is_form_active() {
+            return;
+        }
+        let text = text.trim_end_matches(['\r', '\n']);
+        if text.contains(['\n', '\r', '\t']) {
+            self.form_error = Some(SINGLE_LINE_ERROR.to_string());
+            return;
+        }
+        self.form_error = None;
+        let field = self.current_field_mut();
+        field.extend(text.chars().filter(|ch| !ch.is_control()));
+        self.update_form_hint();
+    }
+
+    pub fn handle_form_backspace(&mut self) {
+        if !self.is_form_active() {
+            return;
+        }
+        self.form_error = None;
+        let field = self.current_field_mut();
+        field.pop();
+        self.update_form_hint();
+    }
+
+    /// Warns while typing when the alias is already taken by another host.
+    /// Unlike `form_error` this never blocks; saving decides.
+    fn update_form_hint(&mut self) {
+        let alias = self.form_entry.host.trim();
+        let original = self.original_host_name.as_deref();
+        let taken = !alias.is_empty()
+            && self.hosts.iter().any(|entry| {
+                Some(entry.host.as_str()) != original
+                    && self.config.alias_match.matches(&entry.host, alias)
+            });
+        self.form_hint = taken.then(|| format!("Host '{}' already exists", alias));
+        if self.form_hint.is_none() {
+            self.form_hint = original.and_then(|original| self.duplicate_hint(original));
+        }
+        self.update_field_hint();
+    }
+
+    /// Runs the focused field's own check on what has been typed so far.
+    fn update_field_hint(&mut self) {
+        self.field_hint = match self.form_field {
+            FormField::Host => field_host_hint(&self.form_entry.host),
+            FormField::Port => field_port_hint(self.form_entry.port()),
+            _ => None,
+        };
+    }
+
+    /// Tells which block an edit of a duplicated alias will replace.
+    fn duplicate_hint(&self, original: &str) -> Option<String> {
+        let count = self
+            .hosts
+            .iter()
+            .filter(|entry| {
+                !entry.disabled && self.config.alias_match.matches(&entry.host, original)
+            })
+            .count();
+        (count > 1).then(|| {
+            format!(
+                "'{}' is defined {} times; saving updates block {} of {}",
+                original,
+                count,
+                self.original_occurrence + 1,
+                count
+            )
+        })
+    }
+
+    pub fn save_form(&mut self) {
+        if !self.is_form_active() {
+            return;
+        }
+
+        let mode = self.state;
+        let mut entry = self.form_entry.trimmed();
+        entry.set_tags(&self.form_tags.split_whitespace().collect::<Vec<_>>());
+        entry.set_note(&self.form_note);
+
+        if let Err(err) = entry.validate() {
+            self.form_error = Some(err.to_string());
+            return;
+        }
+
+        let result = match mode {
+            AppState::Edit => {
+                let original = self
+                    .original_host_name
+                    .clone()
+                    .unwrap_or_else(|| entry.host.clone());
+                update_host_entry_occurrence(
+                    &self.ssh_config_path,
+                    &original,
+                    self.original_occurrence,
+                    &entry,
+                    self.config.alias_match,
+                )
+            }
+            AppState::New => {
+                add_host_entry_matching(&self.ssh_config_path, &entry, self.config.alias_match)
+                    .map(|()| None)
+            }
+            _ => Ok(None),
+        };
+
+        let renamed_from = self
+            .original_host_name
+            .clone()
+            .filter(|original| *original != entry.host);
+
+        match result {
+            Ok(shadowed) => {
+                if let Err(err) = self.refresh_after_write(Some(entry.host.clone())) {
+                    self.form_error = Some(err.to_string());
+                    return;
+                }
+                self.state = AppState::Normal;
+                self.form_entry = HostEntry::default();
+                self.form_tags.clear();
+                self.form_note.clear();
+                self.form_error = None;
+                self.form_hint = None;
+                self.field_hint = None;
+                self.original_host_name = None;
+                let action = match mode {
+                    AppState::Edit => "updated",
+                    AppState::New => "created",
+                    _ => "saved",
+                };
+                let mut status = format!("Host '{}' {} successfully", entry.host, action);
+                if let Some(shadowed) = shadowed {
+                    status.push_str(&format!("; warning: {}", shadowed));
+                }
+                if let Some(original) = renamed_from {
+                    let stale = find_alias_references(&self.hosts, &original);
+                    if !stale.is_empty() {
+                        status.push_str(&format!(
+                            "; warning: {} host(s) still reference '{}' via ProxyJump/ProxyCommand ({})",
+                            stale.len(),
+                            original,
+                            stale.join(", ")
+                        ));
+                    }
+                }
+                let variants = find_case_variants(&self.hosts, &entry.host);
+                if !variants.is_empty() {
+                    status.push_str(&format!(
+                        "; warning: '{}' differs only in case from {}",
+                        entry.host,
+                        quoted_list(&variants)
+                    ));
+                }
+                self.status = Some(Status::Success(status));
+            }
+            Err(err) => {
+                self.form_error = Some(err.to_string());
+            }
+        }
+    }
+
+    /// Re-reads the config unless its stamp shows it has not changed since
+    /// the last parse. Unchanged entries are kept in place.
+    fn refresh_hosts(&mut self, focus: Option<String>) -> Result<()> {
+        let stamp = config_stamp(&self.ssh_config_path);
+        if stamp.is_none() || stamp != self.config_stamp {
+            let parsed = load_config(&self.ssh_config_path)?;
+            self.lint_warnings = config_issues(parsed.warnings, &parsed.hosts);
+            merge_hosts(
+                &mut self.hosts,
+                with_system_hosts(parsed.hosts, &self.system_hosts),
+            );
+            self.pattern_blocks = parsed.patterns;
+            self.config_stamp = stamp;
+            self.marked.clear();
+        }
+        self.missing_identity.clear();
+        self.encrypted_identity.clear();
+        self.scan_permissions();
+        self.rebuild_view();
+        self.focus_host(focus.as_deref());
+        Ok(())
+    }
+
+    /// Refreshes after the app itself wrote the config. The stamp is
+    /// dropped first: a same-size write within the filesystem's timestamp
+    /// granularity would otherwise look unchanged.
+    fn refresh_after_write(&mut self, focus: Option<String>) -> Result<()> {
+        self.config_stamp = None;
+        self.refresh_hosts(focus)
+    }
+
+    /// Selects `host`, clearing the search first if it hides the host.
+    /// Returns whether `host` ended up selected, so a caller about to
+    /// connect never falls back on whatever was selected before.
+    fn reveal_host(&mut self, host: &str) -> bool {
+        if !self.visible_hosts().any(|entry| entry.host == host) {
+            self.search_query.clear();
+            self.apply_search();
+        }
+        self.focus_host(Some(host));
+        self.selected_host_name() == Some(host)
+    }
+
+    fn focus_host(&mut self, host: Option<&str>) {
+        if let Some(position) =
+            host.and_then(|host| self.visible_hosts().position(|entry| entry.host == host))
+        {
+            self.selected = position;
+            self.inspect_selected();
+        }
+    }
+
+    fn current_field_mut(&mut self) -> &mut String {
+        match self.form_field {
+            FormField::Host => &mut self.form_entry.host,
+            FormField::HostName => self.form_entry.directive_mut("HostName"),
+            FormField::User => self.form_entry.directive_mut("User"),
+            FormField::Port => self.form_entry.directive_mut("Port"),
+            FormField::IdentityFile => self.form_entry.directive_mut("IdentityFile"),
+            FormField::Tags => &mut self.form_tags,
+            FormField::Note => &mut self.form_note,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn test_with_hosts(hosts: Vec<HostEntry>) -> Self {
+        let mut app = Self {
+            hosts,
+            pattern_blocks: Vec::new(),
+            visible: Vec::new(),
+            selected: 0,
+            show_disabled: false,
+            sort: HostSort::default(),
+            mask_sensitive: false,
+            jump_pending: false,
+            motion: MotionState::default(),
+            marked: HashSet::new(),
+            status: None,
+            status_since: None,
+            pending_operation: None,
+            pending_result: None,
+            spinner_frame: 0,
+            state: AppState::Normal,
+            form_entry: HostEntry::default(),
+            form_field: FormField::Host,
+            form_tags: String::new(),
+            form_note: String::new(),
+            raw_editor: TextEditor::default(),
+            pending_import: Vec::new(),
+            form_error: None,
+            form_hint: None,
+            field_hint: None,
+            lint_warnings: Vec::new(),
+            palette: FilteredList::default(),
+            quick_connect: String::new(),
+            save_as_path: String::new(),
+            connect_as_user: String::new(),
+            connect_user_override: None,
+            connect_args: String::new(),
+            args_history_position: None,
+            connect_args_override: None,
+            confirmation: None,
+            recent: FilteredList::default(),
+            recent_hosts: Vec::new(),
+            compare_picker: FilteredList::default(),
+            compare_candidates: Vec::new(),
+            profile_picker: FilteredList::default(),
+            profile_candidates: Vec::new(),
+            active_profile: None,
+            comparison: None,
+            key_picker: FilteredList::default(),
+            key_candidates: Vec::new(),
+            print_only: false,
+            chosen_host: None,
+            search_query: String::new(),
+            match_mode: MatchMode::default(),
+            search_error: None,
+            host_matcher: None,
+            tag_filter: Vec::new(),
+            config: Config::default(),
+            saved_state: State::default(),
+            state_path: None,
+            history_path: None,
+            history: Vec::new(),
+            ssh_connection: SshConnection::new("ssh".to_string(), Duration::from_secs(30)),
+            ssh_config_path: PathBuf::new(),
+            default_config_path: PathBuf::new(),
+            system_config_path: PathBuf::from("/etc/ssh/ssh_config"),
+            system_hosts: Vec::new(),
+            config_stamp: None,
+            ssh_dir: PathBuf::new(),
+            home_dir: PathBuf::new(),
+            missing_identity: HashMap::new(),
+            encrypted_identity: HashMap::new(),
+            loose_permissions: Vec::new(),
+            control_socket_active: false,
+            resolved: HashMap::new(),
+            resolver: ssh::resolve_host,
+            path_completions: Vec::new(),
+            completion_index: 0,
+            picker_return: AppState::New,
+            original_host_name: None,
+            original_occurrence: 0,
+        };
+        app.lint_warnings = lint_hosts(&app.hosts);
+        app.rebuild_view();
+        app
+    }
+
+    #[cfg(test)]
+    pub fn test_with_config_path(path: PathBuf) -> Result<Self> {
+        let parsed = load_config(&path)?;
+        let app = Self::test_with_hosts(parsed.hosts);
+        Ok(Self {
+            config_stamp: config_stamp(&path),
+            default_config_path: path.clone(),
+            ssh_config_path: path,
+            lint_warnings: config_issues(parsed.warnings, &app.hosts),
+            pattern_blocks: parsed.patterns,
+            ..app
+        })
+    }
+}
+
+/// Splits `input` into words on unquoted whitespace. Single quotes keep
+/// everything literally; inside double quotes and outside quotes a
+/// backslash escapes the next character. `None` if a quote is left open.
+fn split_shell_words(input: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = input.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        ch => word.push(ch),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            ch @ ('"' | '\\' | '$' | '`') => word.push(ch),
+                            ch => {
+                                word.push('\\');
+                                word.push(ch);
+                            }
+                        },
+                        ch => word.push(ch),
+                    }
+                }
+            }
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                if let Some(ch) = chars.next() {
+                    word.push(ch);
+                }
+            }
+            ch if ch.is_whitespace() => words.extend(word.take()),
+            ch => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    words.extend(word);
+    Some(words)
+}
+
+/// Orders two hosts for `sort`, ignoring case. Hosts whose keys tie
+/// compare equal in either direction, so a stable sort leaves them in file
+/// order.
+fn compare_hosts(a: &HostEntry, b: &HostEntry, sort: HostSort) -> Ordering {
+    let alias = |entry: &HostEntry| entry.host.to_lowercase();
+    let hostname = |entry: &HostEntry| entry.target_hostname().to_lowercase();
+    let ordering = match sort.key {
+        SortKey::File => Ordering::Equal,
+        SortKey::Alias => alias(a)
+            .cmp(&alias(b))
+            .then_with(|| hostname(a).cmp(&hostname(b))),
+        SortKey::Hostname => hostname(a)
+            .cmp(&hostname(b))
+            .then_with(|| alias(a).cmp(&alias(b))),
+    };
+    if sort.descending {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+fn quoted_list(hosts: &[String]) -> String {
+    hosts
+        .iter()
+        .map(|host| format!("'{}'", host))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The user's hosts followed by the system hosts they do not shadow. As
+/// with ssh, the user config is read first, so its alias wins.
+fn with_system_hosts(mut hosts: Vec<HostEntry>, system: &[HostEntry]) -> Vec<HostEntry> {
+    let user_aliases: HashSet<String> = hosts.iter().map(|entry| entry.host.clone()).collect();
+    hosts.extend(
+        system
+            .iter()
+            .filter(|entry| !user_aliases.contains(&entry.host))
+            .cloned(),
+    );
+    hosts
+}
+
+/// Replaces `hosts` with `fresh`, reusing the vector and leaving entries
+/// that did not change untouched.
+fn merge_hosts(hosts: &mut Vec<HostEntry>, fresh: Vec<HostEntry>) {
+    hosts.truncate(fresh.len());
+    for (index, entry) in fresh.into_iter().enumerate() {
+        match hosts.get_mut(index) {
+            Some(existing) if *existing == entry => {}
+            Some(existing) => *existing = entry,
+            None => hosts.push(entry),
+        }
+    }
+}
+
+fn resolve_key(entry: &HostEntry) -> String {
+    let hostname = match entry.hostname().trim() {
+        "" => entry.host.trim(),
+        hostname => hostname,
+    };
+    let port = match entry.port().trim() {
+        "" => "22",
+        port => port,
+    };
+    format!("{}:{}", hostname, port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh_config::AliasMatch;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    use tempfile::NamedTempFile;
+
+    fn host(name: &str) -> HostEntry {
+        HostEntry::new(name)
+            .with("HostName", "example.com")
+            .with("User", "user")
+    }
+
+    fn test_app() -> App {
+        App::test_with_hosts(vec![host("a"), host("b"), host("c")])
+    }
+
+    #[test]
+    fn next_wraps() {
+        let mut app = test_app();
+        app.selected = 2;
+        app.next();
+        assert_eq!(app.selected, 0);
+    }
+
+    #[test]
+    fn previous_wraps() {
+        let mut app = test_app();
+        app.previous();
+        assert_eq!(app.selected, 2);
+    }
+
+    #[test]
+    fn selected_host_returns_entry() {
+        let app = test_app();
+        assert_eq!(app.selected_host_name(), Some("a"));
+    }
+
+    #[test]
+    fn focus_navigation_cycles_fields() {
+        let mut app = test_app();
+        app.enter_new_mode();
+        app.focus_next_field();
+        assert_eq!(app.form_field, FormField::HostName);
+        app.focus_previous_field();
+        assert_eq!(app.form_field, FormField::Host);
+    }
+
+    #[test]
+    fn handle_form_input_updates_field() {
+        let mut app = test_app();
+        app.enter_new_mode();
+        app.handle_form_input('s');
+        app.handle_form_input('1');
+        assert_eq!(app.form_entry.host, "s1");
+        app.handle_form_backspace();
+        assert_eq!(app.form_entry.host, "s");
+    }
+
+    #[test]
+    fn new_host_form_starts_from_configured_defaults() {
+        let mut app = test_app();
+        app.config.defaults.user = "deploy".to_string();
+        app.config.defaults.port = "2222".to_string();
+        app.enter_new_mode();
+        assert_eq!(app.form_entry.host, "");
+        assert_eq!(app.form_entry.user(), "deploy");
+        assert_eq!(app.form_entry.port(), "2222");
+        assert_eq!(app.form_entry.identity_file(), "");
+
+        app.cancel_form();
+        app.enter_edit_mode();
+        assert_eq!(app.form_entry.user(), "user");
+        assert_eq!(app.form_entry.port(), "");
+    }
+
+    #[test]
+    fn cleared_defaults_are_left_out_of_the_new_host() {
+        let temp = NamedTempFile::new().unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        app.config.defaults.user = "deploy".to_string();
+        app.config.defaults.port = "2222".to_string();
+
+        app.enter_new_mode();
+        app.form_entry.host = "web".to_string();
+        app.form_entry.set("HostName", "web.internal");
+        app.form_field = FormField::User;
+        for _ in 0.."deploy".len() {
+            app.handle_form_backspace();
+        }
+        app.save_form();
+
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n  HostName web.internal\n  Port 2222\n\n"
+        );
+    }
+
+    #[test]
+    fn typing_an_existing_alias_sets_a_hint() {
+        let mut app = test_app();
+        app.enter_new_mode();
+        app.handle_form_input('b');
+        assert_eq!(app.form_hint.as_deref(), Some("Host 'b' already exists"));
+        assert_eq!(app.form_error, None);
+        app.handle_form_input('2');
+        assert_eq!(app.form_hint, None);
+
+        app.cancel_form();
+        app.enter_edit_mode();
+        assert_eq!(app.form_entry.host, "a");
+        assert_eq!(app.form_hint, None);
+    }
+
+    #[test]
+    fn issues_include_parse_warnings_before_lint() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(
+            temp.path(),
+            "Host *\n  ServerAliveInterval 30\n\nHost web\n  HostNmae web.example.com\n",
+        )
+        .unwrap();
+        let app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        assert_eq!(
+            app.lint_warnings,
+            vec![
+                "line 5: unknown keyword 'HostNmae' kept as is",
+                "'web' has no HostName; ssh connects to the alias itself",
+            ]
+        );
+    }
+
+    #[test]
+    fn editing_a_duplicate_alias_updates_the_selected_block() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(
+            temp.path(),
+            "Host web\n  HostName one.example.com\n\nHost web\n  HostName two.example.com\n",
+        )
+        .unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        assert_eq!(
+            duplicate_status(&duplicate_aliases(&app.hosts)),
+            "duplicate alias 'web' (2 occurrences)"
+        );
+
+        app.next();
+        app.enter_edit_mode();
+        assert_eq!(
+            app.form_hint.as_deref(),
+            Some("'web' is defined 2 times; saving updates block 2 of 2")
+        );
+        app.form_entry.set("HostName", "new.example.com");
+        app.save_form();
+
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n  HostName one.example.com\n\nHost web\n  HostName new.example.com\n\n"
+        );
+    }
+
+    #[test]
+    fn case_variant_duplicates_are_changed_block_by_block() {
+        let temp = NamedTempFile::new().unwrap();
+        let config =
+            "Host Web\n  HostName one.example.com\n\nHost web\n  HostName two.example.com\n";
+        fs::write(temp.path(), config).unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        app.config.alias_match = AliasMatch::IgnoreCase;
+
+        app.next();
+        app.enter_edit_mode();
+        app.form_entry.set("HostName", "new.example.com");
+        app.save_form();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host Web\n  HostName one.example.com\n\nHost web\n  HostName new.example.com\n\n"
+        );
+
+        app.show_disabled = true;
+        app.rebuild_view();
+        app.selected = 1;
+        app.toggle_selected_disabled();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host Web\n  HostName one.example.com\n\n# Host web\n#   HostName new.example.com\n\n"
+        );
+        app.selected = 1;
+        app.toggle_selected_disabled();
+
+        app.selected = 1;
+        app.move_selected(MoveDirection::Up);
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n  HostName new.example.com\n\nHost Web\n  HostName one.example.com\n\n"
+        );
+
+        app.selected = 1;
+        app.delete_selected();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n  HostName new.example.com\n"
+        );
+    }
+
+    #[test]
+    fn issues_panel_lists_lint_warnings() {
+        let mut app = App::test_with_hosts(vec![host("a"), host("a"), HostEntry::new("bare")]);
+        assert_eq!(app.lint_warnings.len(), 2);
+        assert!(app.lint_warnings[0].contains("'a' is defined more than once"));
+        assert!(app.lint_warnings[1].contains("'bare' has no HostName"));
+        app.open_issues();
+        assert_eq!(app.state, AppState::Issues);
+        app.close_issues();
+
+        let mut clean = test_app();
+        clean.open_issues();
+        assert_eq!(clean.state, AppState::Normal);
+        assert_eq!(
+            clean.status,
+            Some(Status::Info("No config issues found".to_string()))
+        );
+    }
+
+    #[test]
+    fn palette_filters_and_returns_action() {
+        let mut app = test_app();
+        app.open_palette();
+        assert_eq!(app.state, AppState::Palette);
+        assert!(!app.is_form_active());
+
+        for ch in "dup".chars() {
+            app.palette_input(ch);
+        }
+        assert_eq!(app.take_palette_action(), Some(Action::Duplicate));
+        assert_eq!(app.state, AppState::Normal);
+
+        app.open_palette();
+        for ch in "edit-raw".chars() {
+            app.palette_input(ch);
+        }
+        assert_eq!(app.take_palette_action(), Some(Action::EditRaw));
+    }
+
+    #[test]
+    fn tag_search_keeps_hosts_with_every_tag() {
+        let mut app = App::test_with_hosts(vec![
+            host("web").with_line("  # tags: prod web"),
+            host("db").with_line("  # tags: Prod db"),
+            host("dev"),
+        ]);
+        app.search_query = "tag:prod".to_string();
+        app.apply_search();
+        assert_eq!(app.visible, vec![0, 1]);
+
+        app.search_query = "tag:prod tag:db".to_string();
+        app.apply_search();
+        assert_eq!(app.visible, vec![1]);
+
+        app.search_query = "tag:prod w".to_string();
+        app.apply_search();
+        assert_eq!(app.visible, vec![0]);
+    }
+
+    #[test]
+    fn form_tags_are_saved_as_a_comment() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(temp.path(), "Host web\n  HostName web.example.com\n").unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+
+        app.enter_edit_mode();
+        assert!(app.form_tags.is_empty());
+        app.form_field = FormField::Tags;
+        for ch in "prod db".chars() {
+            app.handle_form_input(ch);
+        }
+        app.save_form();
+
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n  HostName web.example.com\n  # tags: prod db\n\n"
+        );
+        assert_eq!(app.hosts[0].tags(), vec!["prod", "db"]);
+    }
+
+    #[test]
+    fn status_messages_expire_errors_last_longer() {
+        let mut app = test_app();
+        let start = Instant::now();
+        app.status = Some(Status::Success("Host 'a' updated successfully".to_string()));
+        app.expire_status(start);
+        app.expire_status(start + Duration::from_secs(4));
+        assert!(app.status.is_some());
+        app.expire_status(start + Duration::from_secs(5));
+        assert!(app.status.is_none());
+
+        app.set_status(Err(anyhow::anyhow!("boom")));
+        app.expire_status(start);
+        app.expire_status(start + Duration::from_secs(9));
+        assert!(app.status.is_some());
+        app.status = Some(Status::Success("Reloaded".to_string()));
+        app.expire_status(start + Duration::from_secs(9));
+        app.expire_status(start + Duration::from_secs(13));
+        assert!(app.status.is_some(), "a new message restarts the clock");
+
+        app.config.status_timeout = 0;
+        app.expire_status(start + Duration::from_secs(3600));
+        assert!(app.status.is_some());
+    }
+
+    #[test]
+    fn best_alias_match_prefers_exact_then_prefix_then_fuzzy() {
+        let aliases = ["web-prod", "web", "db-staging", "Database"];
+        let best = |query| best_alias_match(query, aliases.into_iter());
+
+        assert_eq!(best("web"), Some("web"));
+        assert_eq!(best("we"), Some("web-prod"));
+        assert_eq!(best("DAT"), Some("Database"));
+        assert_eq!(best("dbs"), Some("db-staging"));
+        assert_eq!(best("xyz"), None);
+        assert_eq!(best(""), None);
+    }
+
+    #[test]
+    fn quick_connect_selects_host_hidden_by_search() {
+        let mut app = test_app();
+        app.search_query = "b".to_string();
+        app.apply_search();
+        assert_eq!(app.visible.len(), 1);
+        app.open_quick_connect();
+        app.quick_connect_input('c');
+        assert_eq!(app.quick_connect_match(), Some("c"));
+
+        assert_eq!(app.take_quick_connect_host().as_deref(), Some("c"));
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(app.selected_host_name(), Some("c"));
+        assert!(app.search_query.is_empty());
+    }
+
+    #[test]
+    fn palette_with_no_match_returns_none() {
+        let mut app = test_app();
+        app.open_palette();
+        for ch in "zzz".chars() {
+            app.palette_input(ch);
+        }
+        assert_eq!(app.take_palette_action(), None);
+    }
+
+    #[test]
+    fn duplicate_prefills_new_form() {
+        let mut app = test_app();
+        app.enter_duplicate_mode();
+        assert_eq!(app.state, AppState::New);
+        assert_eq!(app.form_entry.host, "a-copy");
+        assert_eq!(app.form_entry.hostname(), "example.com");
+    }
+
+    #[test]
+    fn renaming_jump_host_warns_about_stale_references() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(
+            temp.path(),
+            "Host bastion\n  HostName bastion.example.com\n\n\
+             Host inner\n  HostName 10.0.0.5\n  ProxyJump bastion\n\n\
+             Host legacy\n  HostName 10.0.0.6\n  ProxyCommand ssh -W %h:%p bastion\n",
+        )
+        .unwrap();
+
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        app.enter_edit_mode();
+        app.form_entry.host = "jump".to_string();
+        app.save_form();
+
+        assert_eq!(app.form_error, None);
+        let status = app.status.unwrap().text();
+        assert!(status.contains("2 host(s) still reference 'bastion'"));
+        assert!(status.contains("inner"));
+        assert!(status.contains("legacy"));
+    }
+
+    #[test]
+    fn preferred_tool_honors_connect_with_comment() {
+        let mut app = test_app();
+        app.hosts[1] = app.hosts[1].clone().with_line("  # connect-with: mosh");
+        assert_eq!(app.preferred_tool("a"), ConnectTool::Ssh);
+        assert_eq!(app.preferred_tool("b"), ConnectTool::Mosh);
+    }
+
+    #[test]
+    fn prod_hosts_require_confirmation_when_enabled() {
+        let mut app = test_app();
+        app.hosts[0] = app.hosts[0].clone().with_line("  # tags: prod");
+        app.hosts[0].set("Port", "2222");
+        assert_eq!(app.connect_confirmation_prompt(), None);
+
+        app.config.confirm_prod_connect = true;
+        let prompt = app.connect_confirmation_prompt().unwrap();
+        assert!(prompt.contains("user@example.com:2222"));
+
+        app.selected = 1;
+        assert_eq!(app.connect_confirmation_prompt(), None);
+
+        app.config.prod_patterns = vec!["b*".to_string()];
+        assert!(app.connect_confirmation_prompt().is_some());
+    }
+
+    #[test]
+    fn confirmation_round_trip() {
+        let mut app = test_app();
+        app.request_confirmation("Sure?".to_string(), Action::Connect);
+        assert_eq!(app.state, AppState::Confirm);
+        assert_eq!(app.take_confirmation(), Some(Action::Connect));
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(app.confirmation, None);
+    }
+
+    #[test]
+    fn reload_skips_the_parse_while_the_stamp_is_unchanged() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(temp.path(), "Host a\n  HostName a.example.com\n").unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        let modified = fs::metadata(temp.path()).unwrap().modified().unwrap();
+
+        // Same length and mtime: the old entry stays, proving no reparse.
+        fs::write(temp.path(), "Host a\n  HostName b.example.com\n").unwrap();
+        let file = fs::File::options().write(true).open(temp.path()).unwrap();
+        file.set_modified(modified).unwrap();
+        app.reload();
+        assert_eq!(app.hosts[0].hostname(), "a.example.com");
+
+        file.set_modified(modified + Duration::from_secs(5))
+            .unwrap();
+        app.reload();
+        assert_eq!(app.hosts[0].hostname(), "b.example.com");
+    }
+
+    #[test]
+    fn merge_hosts_keeps_unchanged_entries() {
+        let mut hosts = vec![host("a"), host("b"), host("c")];
+        let buffer = hosts.as_ptr();
+        merge_hosts(&mut hosts, vec![host("a"), host("b").with("Port", "2222")]);
+
+        assert_eq!(hosts, vec![host("a"), host("b").with("Port", "2222")]);
+        assert_eq!(hosts.as_ptr(), buffer);
+    }
+
+    #[test]
+    fn disabled_hosts_are_hidden_until_toggled() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(
+            temp.path(),
+            "Host a\n  HostName a.example.com\n\nHost b\n  HostName b.example.com\n",
+        )
+        .unwrap();
+
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        app.toggle_selected_disabled();
+        assert_eq!(
+            app.status,
+            Some(Status::Success("Host 'a' disabled".to_string()))
+        );
+        assert_eq!(app.visible_hosts().count(), 1);
+        assert_eq!(app.selected_host_name(), Some("b"));
+
+        app.toggle_show_disabled();
+        assert_eq!(app.visible_hosts().count(), 2);
+        app.selected = 0;
+        assert_eq!(app.connectable_host_name(), None);
+
+        app.toggle_selected_disabled();
+        assert_eq!(
+            app.status,
+            Some(Status::Success("Host 'a' enabled".to_string()))
+        );
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host a\n  HostName a.example.com\n\nHost b\n  HostName b.example.com\n"
+        );
+    }
+
+    #[test]
+    fn toggling_a_host_keeps_its_block_in_place() {
+        let temp = NamedTempFile::new().unwrap();
+        let original = "Host a\n  HostName a.example.com\n\nHost b\n  HostName b.example.com\n  # note\n\nHost c\n  HostName c.example.com\n";
+        fs::write(temp.path(), original).unwrap();
+
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        app.toggle_show_disabled();
+        app.selected = 1;
+        app.toggle_selected_disabled();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host a\n  HostName a.example.com\n\n# Host b\n#   HostName b.example.com\n#   # note\n\nHost c\n  HostName c.example.com\n"
+        );
+        assert_eq!(app.selected_host_name(), Some("b"));
+        assert!(app.selected_host().unwrap().disabled);
+
+        app.toggle_selected_disabled();
+        assert_eq!(app.selected_host_name(), Some("b"));
+        assert_eq!(fs::read_to_string(temp.path()).unwrap(), original);
+    }
+
+    #[test]
+    fn recent_panel_lists_known_hosts_by_recency() {
+        let mut app = test_app();
+        app.open_recent();
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(
+            app.status,
+            Some(Status::Info("No recent connections yet".to_string()))
+        );
+
+        app.config.recent_limit = 2;
+        app.saved_state.record_connection("b", 10);
+        app.saved_state.record_connection("gone", 30);
+        app.saved_state.record_connection("c", 20);
+        app.saved_state.record_connection("a", 5);
+        app.open_recent();
+        assert_eq!(app.state, AppState::Recent);
+        assert_eq!(app.recent_hosts, vec!["c", "b"]);
+
+        app.recent.next();
+        assert_eq!(app.take_recent_host().as_deref(), Some("b"));
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(app.selected_host_name(), Some("b"));
+    }
+
+    #[test]
+    fn recent_host_hidden_by_the_search_is_revealed() {
+        let mut app = App::test_with_hosts(vec![HostEntry::new("web"), HostEntry::new("db")]);
+        app.saved_state.record_connection("web", 10);
+        app.search_query = "db".to_string();
+        app.apply_search();
+        assert_eq!(app.selected_host_name(), Some("db"));
+
+        app.open_recent();
+        assert_eq!(app.take_recent_host().as_deref(), Some("web"));
+        assert_eq!(app.search_query, "");
+        assert_eq!(app.selected_host_name(), Some("web"));
+    }
+
+    #[test]
+    fn new_host_with_existing_alias_sets_form_error() {
+        let temp = NamedTempFile::new().unwrap();
+        let original = "Host a\n  HostName a.example.com\n";
+        fs::write(temp.path(), original).unwrap();
+
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        app.enter_new_mode();
+        app.form_entry.host = "a".to_string();
+        app.form_entry.set("HostName", "other.example.com");
+        app.save_form();
+
+        assert_eq!(app.form_error.as_deref(), Some("Host 'a' already exists"));
+        assert_eq!(app.state, AppState::New);
+        assert_eq!(fs::read_to_string(temp.path()).unwrap(), original);
+    }
+
+    #[test]
+    fn new_host_differing_only_in_case_warns_or_is_refused() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(temp.path(), "Host WebProd\n  HostName web.example.com\n").unwrap();
+
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        app.enter_new_mode();
+        app.form_entry.host = "webprod".to_string();
+        app.form_entry.set("HostName", "other.example.com");
+        app.save_form();
+        let status = app.status.clone().unwrap().text();
+        assert!(status.contains("'webprod' differs only in case from 'WebProd'"));
+        assert_eq!(app.hosts.len(), 2);
+
+        app.config.alias_match = AliasMatch::IgnoreCase;
+        app.enter_new_mode();
+        app.form_entry.host = "WEBPROD".to_string();
+        app.form_entry.set("HostName", "third.example.com");
+        app.save_form();
+        assert_eq!(
+            app.form_error.as_deref(),
+            Some("Host 'WEBPROD' already exists")
+        );
+    }
+
+    #[test]
+    fn key_picker_fills_identity_file() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["id_ed25519", "id_ed25519.pub", "work", "work.pub"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let mut app = test_app();
+        app.ssh_dir = dir.path().to_path_buf();
+        app.enter_new_mode();
+        app.open_key_picker();
+        assert_eq!(app.state, AppState::New);
+
+        app.form_field = FormField::IdentityFile;
+        app.open_key_picker();
+        assert_eq!(app.state, AppState::KeyPicker);
+        assert_eq!(app.key_candidates.len(), 2);
+
+        for ch in "work".chars() {
+            app.key_picker_input(ch);
+        }
+        app.pick_key();
+        assert_eq!(app.state, AppState::New);
+        assert_eq!(
+            app.form_entry.identity_file(),
+            dir.path().join("work").display().to_string()
+        );
+    }
+
+    #[test]
+    fn identity_suggestions_cycle_through_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["id_ed25519", "id_ed25519.pub", "work", "work.pub"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let key = |name: &str| dir.path().join(name).display().to_string();
+
+        let mut app = test_app();
+        app.ssh_dir = dir.path().to_path_buf();
+        app.enter_new_mode();
+        app.cycle_identity_suggestion();
+        assert!(app.form_entry.identity_file().is_empty());
+
+        app.form_field = FormField::IdentityFile;
+        app.cycle_identity_suggestion();
+        assert_eq!(app.form_entry.identity_file(), key("id_ed25519"));
+        app.cycle_identity_suggestion();
+        assert_eq!(app.form_entry.identity_file(), key("work"));
+        app.cycle_identity_suggestion();
+        assert_eq!(app.form_entry.identity_file(), key("id_ed25519"));
+    }
+
+    #[test]
+    fn tab_completes_identity_paths_and_cycles() {
+        let home = tempfile::tempdir().unwrap();
+        fs::create_dir_all(home.path().join("keys")).unwrap();
+        for name in ["deploy", "dev"] {
+            fs::write(home.path().join("keys").join(name), "").unwrap();
+        }
+
+        let mut app = test_app();
+        app.home_dir = home.path().to_path_buf();
+        app.enter_new_mode();
+        app.form_entry.set("IdentityFile", "~/ke");
+        app.complete_or_focus_next();
+        assert_eq!(app.form_field, FormField::HostName);
+
+        app.form_field = FormField::IdentityFile;
+        app.complete_or_focus_next();
+        assert_eq!(app.form_entry.identity_file(), "~/keys/");
+
+        app.complete_or_focus_next();
+        assert_eq!(app.form_entry.identity_file(), "~/keys/deploy");
+        app.complete_or_focus_next();
+        assert_eq!(app.form_entry.identity_file(), "~/keys/dev");
+        app.complete_or_focus_next();
+        assert_eq!(app.form_entry.identity_file(), "~/keys/deploy");
+        assert_eq!(app.form_field, FormField::IdentityFile);
+
+        app.form_entry.set("IdentityFile", "~/keys/dev");
+        app.path_completions.clear();
+        app.complete_or_focus_next();
+        assert_eq!(app.form_field, FormField::Tags);
+    }
+
+    #[test]
+    fn marked_hosts_become_tmux_windows() {
+        let mut app = test_app();
+        assert_eq!(app.batch_hosts(), vec!["a"]);
+
+        app.selected = 2;
+        app.toggle_marked();
+        app.selected = 0;
+        app.toggle_marked();
+        app.selected = 1;
+        app.toggle_marked();
+        app.toggle_marked();
+        assert_eq!(app.batch_hosts(), vec!["a", "c"]);
+
+        let commands = app.ssh_connection.tmux_commands(&app.batch_hosts());
+        let windows: Vec<&str> = commands.iter().map(|(_, args)| args[2].as_str()).collect();
+        assert_eq!(windows, vec!["a", "c"]);
+        assert!(commands.iter().all(|(program, _)| *program == "tmux"));
+    }
+
+    #[test]
+    fn marked_hosts_are_deleted_after_one_confirmation() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(
+            temp.path(),
+            "Host a\n  HostName a.example.com\n\nHost b\n  HostName b.example.com\n\nHost c\n  HostName c.example.com\n",
+        )
+        .unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+
+        app.toggle_mark_all();
+        assert_eq!(app.marked.len(), 3);
+        app.selected = 1;
+        app.toggle_marked();
+        app.request_delete();
+        assert_eq!(app.state, AppState::Confirm);
+        assert_eq!(
+            app.confirmation.as_ref().unwrap().prompt,
+            "Delete 'a', 'c'? [y/N]"
+        );
+
+        assert_eq!(app.take_confirmation(), Some(Action::Delete));
+        app.delete_selected();
+        assert_eq!(
+            app.status,
+            Some(Status::Success("Deleted 2 hosts: 'a', 'c'".to_string()))
+        );
+        assert!(app.marked.is_empty());
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host b\n  HostName b.example.com\n"
+        );
+    }
+
+    #[test]
+    fn regex_search_filters_on_host_and_hostname() {
+        let mut app = App::test_with_hosts(vec![
+            HostEntry::new("web-1").with("HostName", "10.0.0.1"),
+            HostEntry::new("web-2").with("HostName", "10.0.0.2"),
+            HostEntry::new("db").with("HostName", "10.0.1.1"),
+        ]);
+        app.open_search();
+        app.toggle_match_mode();
+        for ch in r"10\.0\.0\.".chars() {
+            app.search_input(ch);
+        }
+        let hosts: Vec<&str> = app
+            .visible_hosts()
+            .map(|entry| entry.host.as_str())
+            .collect();
+        assert_eq!(hosts, vec!["web-1", "web-2"]);
+        assert!(app.search_error.is_none());
+
+        app.search_input('(');
+        assert!(app
+            .search_error
+            .as_deref()
+            .unwrap()
+            .starts_with("invalid regex"));
+        assert_eq!(app.visible.len(), 2);
+
+        app.close_search(false);
+        assert_eq!(app.visible.len(), 3);
+        assert!(app.search_error.is_none());
+        assert_eq!(app.state, AppState::Normal);
+    }
+
+    #[test]
+    fn missing_identity_file_is_checked_after_tilde_expansion() {
+        let home = tempfile::tempdir().unwrap();
+        fs::create_dir_all(home.path().join(".ssh")).unwrap();
+        fs::write(home.path().join(".ssh/id_ed25519"), "").unwrap();
+
+        let mut app = App::test_with_hosts(vec![
+            HostEntry::new("present").with("IdentityFile", "~/.ssh/id_ed25519"),
+            HostEntry::new("gone").with("IdentityFile", "~/.ssh/id_rsa"),
+        ]);
+        app.home_dir = home.path().to_path_buf();
+        app.missing_identity.clear();
+        app.rebuild_view();
+        assert!(!app.selected_identity_missing());
+        assert_eq!(
+            app.selected_key_encrypted(),
+            None,
+            "empty file is not a key"
+        );
+        assert!(!app.identity_missing(1));
+        app.next();
+        assert!(app.selected_identity_missing());
+        assert!(app.identity_missing(1));
+
+        fs::write(home.path().join(".ssh/id_rsa"), "").unwrap();
+        app.previous();
+        app.next();
+        assert!(app.selected_identity_missing(), "result stays cached");
+    }
+
+    #[test]
+    fn control_socket_marks_host_multiplexed() {
+        let home = tempfile::tempdir().unwrap();
+        fs::create_dir_all(home.path().join(".ssh")).unwrap();
+        fs::write(home.path().join(".ssh/cm-deploy@web.example.com:22"), "").unwrap();
+
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(
+            temp.path(),
+            "Host web\n  HostName web.example.com\n  User deploy\n\n\
+             Host db\n  User deploy\n\n\
+             Host *\n  ControlPath ~/.ssh/cm-%r@%h:%p\n",
+        )
+        .unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        assert_eq!(app.hosts.len(), 2);
+        app.home_dir = home.path().to_path_buf();
+        app.rebuild_view();
+        assert!(app.selected_multiplexed());
+        app.next();
+        assert!(!app.selected_multiplexed());
+    }
+
+    /// Polls until the background operation finishes, failing after 5s.
+    fn finish_pending(app: &mut App) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while app.pending_operation.is_some() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+            app.poll_pending();
+        }
+        assert_eq!(app.pending_operation, None);
+    }
+
+    #[test]
+    fn resolution_runs_in_background_and_is_cached_per_hostname() {
+        static LOOKUPS: AtomicUsize = AtomicUsize::new(0);
+        fn fake_resolver(hostname: &str, port: u16) -> Vec<IpAddr> {
+            LOOKUPS.fetch_add(1, AtomicOrdering::SeqCst);
+            assert_eq!(port, 22);
+            match hostname {
+                "localhost" => vec![IpAddr::from([127, 0, 0, 1])],
+                _ => Vec::new(),
+            }
+        }
+
+        let mut app = App::test_with_hosts(vec![
+            HostEntry::new("local").with("HostName", "localhost"),
+            HostEntry::new("broken").with("HostName", "host.invalid"),
+        ]);
+        app.resolver = fake_resolver;
+        assert!(app.selected_resolution().is_none());
+        app.resolve_selected();
+        assert_eq!(
+            app.pending_operation,
+            Some(OpKind::Resolve("localhost:22".to_string()))
+        );
+        finish_pending(&mut app);
+        assert_eq!(
+            app.selected_resolution(),
+            Some(&[IpAddr::from([127, 0, 0, 1])][..])
+        );
+        app.resolve_selected();
+        assert_eq!(app.pending_operation, None);
+
+        app.next();
+        app.resolve_selected();
+        finish_pending(&mut app);
+        assert_eq!(app.selected_resolution(), Some(&[][..]));
+        assert_eq!(app.resolved.len(), 2);
+        assert_eq!(LOOKUPS.load(AtomicOrdering::SeqCst), 2);
+    }
+
+    #[test]
+    fn moving_a_host_keeps_it_selected() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(
+            temp.path(),
+            "Host a\n  HostName a\n\nHost b\n  HostName b\n\nHost c\n  HostName c\n",
+        )
+        .unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        app.next();
+
+        app.move_selected(MoveDirection::Up);
+        let order: Vec<&str> = app
+            .visible_hosts()
+            .map(|entry| entry.host.as_str())
+            .collect();
+        assert_eq!(order, vec!["b", "a", "c"]);
+        assert_eq!(app.selected_host_name(), Some("b"));
+
+        app.move_selected(MoveDirection::Up);
+        assert_eq!(
+            app.status,
+            Some(Status::Error("Host 'b' is already first".to_string()))
+        );
+    }
+
+    #[test]
+    fn print_mode_picks_enabled_hosts_only() {
+        let mut app = App::test_with_hosts(vec![
+            HostEntry {
+                disabled: true,
+                ..HostEntry::new("old")
+            },
+            host("web"),
+        ]);
+        app.show_disabled = true;
+        app.rebuild_view();
+
+        assert!(!app.choose_selected());
+        assert!(app.chosen_host.is_none());
+        app.next();
+        assert!(app.choose_selected());
+        assert_eq!(app.chosen_host.as_deref(), Some("web"));
+    }
+
+    #[test]
+    fn pasted_values_are_trimmed_and_must_be_single_line() {
+        let temp = NamedTempFile::new().unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        app.enter_new_mode();
+        app.handle_form_paste("web\n");
+        assert_eq!(app.form_entry.host, "web");
+
+        app.focus_next_field();
+        app.handle_form_paste("example.com\nrm -rf");
+        assert_eq!(app.form_error.as_deref(), Some(SINGLE_LINE_ERROR));
+        assert!(app.form_entry.hostname().is_empty());
+        app.handle_form_input('\t');
+        assert_eq!(app.form_error.as_deref(), Some(SINGLE_LINE_ERROR));
+
+        app.handle_form_paste(" example.com ");
+        assert!(app.form_error.is_none());
+        app.save_form();
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(app.hosts[0].hostname(), "example.com");
+        assert!(fs::read_to_string(temp.path())
+            .unwrap()
+            .contains("  HostName example.com\n"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn fix_permissions_restricts_config_and_keys() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let home = tempfile::tempdir().unwrap();
+        let config = home.path().join("config");
+        let key = home.path().join("id_web");
+        fs::write(&config, "Host web\n  IdentityFile ~/id_web\n").unwrap();
+        fs::write(&key, "").unwrap();
+        for path in [&config, &key] {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o644)).unwrap();
+        }
+        let mut app = App::test_with_config_path(config.clone()).unwrap();
+        app.home_dir = home.path().to_path_buf();
+        app.scan_permissions();
+        assert_eq!(
+            permission_status(&app.loose_permissions),
+            "config is mode 0644, id_web is mode 0644; press 'F' to chmod 0600"
+        );
+
+        app.fix_permissions();
+        assert_eq!(
+            app.status,
+            Some(Status::Success("Set 2 file(s) to mode 0600".to_string()))
+        );
+        assert!(app.loose_permissions.is_empty());
+        let mode = fs::metadata(&key).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o600);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn connection_test_runs_in_background_with_spinner() {
+        let mut app = test_app();
+        // `true` ignores the ssh arguments and succeeds.
+        app.ssh_connection = SshConnection::new("true".to_string(), Duration::from_secs(5));
+        app.test_selected_connection();
+        assert_eq!(
+            app.pending_operation,
+            Some(OpKind::TestConnection("a".to_string()))
+        );
+        app.advance_spinner();
+        app.advance_spinner();
+        assert_eq!(app.spinner_frame, 2);
+
+        finish_pending(&mut app);
+        assert_eq!(app.spinner_frame, 0);
+        assert_eq!(
+            app.status,
+            Some(Status::Success("Connection test to a passed".to_string()))
+        );
+        app.advance_spinner();
+        assert_eq!(app.spinner_frame, 0);
+    }
+
+    #[test]
+    fn save_as_writes_hosts_elsewhere_and_spares_the_live_config() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(temp.path(), "Host web\n  HostName web.example.com\n").unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("snapshot");
+
+        app.open_save_as();
+        destination
+            .to_string_lossy()
+            .chars()
+            .for_each(|c| app.save_as_input(c));
+        app.save_as();
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(
+            app.status,
+            Some(Status::Success(format!(
+                "Saved 1 hosts to {}",
+                destination.display()
+            )))
+        );
+        assert_eq!(
+            fs::read_to_string(&destination).unwrap(),
+            "Host web\n  HostName web.example.com\n\n"
+        );
+
+        app.open_save_as();
+        temp.path()
+            .to_string_lossy()
+            .chars()
+            .for_each(|c| app.save_as_input(c));
+        app.save_as();
+        assert!(matches!(app.status, Some(Status::Error(_))));
+    }
+
+    #[test]
+    fn connect_as_prompt_validates_and_overrides_user_once() {
+        let mut app = test_app();
+        app.hosts[0].set("User", "admin");
+        app.rebuild_view();
+        app.open_connect_as();
+        assert_eq!(app.state, AppState::ConnectAs);
+        assert_eq!(app.connect_as_user, "admin");
+
+        app.connect_as_user.clear();
+        assert!(!app.accept_connect_as());
+        "de ploy".chars().for_each(|c| app.connect_as_input(c));
+        assert_eq!(
+            app.connect_as_problem(),
+            Some("usernames can't contain spaces")
+        );
+        assert!(!app.accept_connect_as());
+        assert_eq!(app.state, AppState::ConnectAs);
+
+        app.connect_as_user = "deploy".to_string();
+        assert!(app.accept_connect_as());
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(app.connect_user_override.as_deref(), Some("deploy"));
+
+        app.request_confirmation("Connect?".to_string(), Action::Connect);
+        app.cancel_confirmation();
+        assert_eq!(app.connect_user_override, None);
+    }
+
+    #[test]
+    fn compare_picks_another_host_and_diffs_it() {
+        let mut app = test_app();
+        app.hosts[2].set("HostName", "other.example.com");
+        app.open_compare_picker();
+        assert_eq!(app.state, AppState::ComparePicker);
+        assert_eq!(app.compare_candidates, vec!["b", "c"]);
+
+        app.compare_picker_input('c');
+        app.compare_with_picked();
+        assert_eq!(app.state, AppState::Compare);
+        let comparison = app.comparison.clone().unwrap();
+        assert_eq!(
+            (comparison.left.as_str(), comparison.right.as_str()),
+            ("a", "c")
+        );
+        assert_eq!(comparison.diffs.len(), 1);
+        assert_eq!(comparison.diffs[0].right, "other.example.com");
+
+        app.close_comparison();
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(app.comparison, None);
+    }
+
+    #[test]
+    fn port_field_hint_flags_non_numeric_input_while_focused() {
+        let mut app = test_app();
+        app.enter_new_mode();
+        app.form_field = FormField::Port;
+        "22".chars().for_each(|c| app.handle_form_input(c));
+        assert_eq!(app.field_hint, None);
+        app.handle_form_input('x');
+        assert_eq!(app.field_hint.as_deref(), Some("Port must be a number"));
+        app.handle_form_backspace();
+        assert_eq!(app.field_hint, None);
+        "0000".chars().for_each(|c| app.handle_form_input(c));
+        assert_eq!(
+            app.field_hint.as_deref(),
+            Some("Port must be between 1 and 65535")
+        );
+
+        app.focus_next_field();
+        assert_eq!(app.field_hint, None);
+    }
+
+    #[test]
+    fn host_field_hint_warns_about_wildcards() {
+        let mut app = test_app();
+        app.enter_new_mode();
+        "web".chars().for_each(|c| app.handle_form_input(c));
+        assert_eq!(app.field_hint, None);
+        app.handle_form_input('*');
+        assert_eq!(
+            app.field_hint.as_deref(),
+            Some("Wildcards make this a pattern, not a host alias")
+        );
+        app.cancel_form();
+        assert_eq!(app.field_hint, None);
+    }
+
+    #[test]
+    fn exits_after_a_clean_session_only_when_not_staying_open() {
+        let mut app = test_app();
+        let ended = Ok("Session to a via ssh ended after 1s".to_string());
+        let failed = Err(anyhow::anyhow!(
+            "Connection to a via ssh exited with code 255"
+        ));
+        assert!(app.config.stay_open);
+        assert!(!app.exit_after_session(&ended));
+
+        app.config.stay_open = false;
+        assert!(app.exit_after_session(&ended));
+        assert!(!app.exit_after_session(&failed));
+
+        app.config.stay_open = true;
+        app.config.exit_after_connect = true;
+        assert!(app.exit_after_session(&ended));
+        assert!(!app.exit_after_session(&failed));
+    }
+
+    #[test]
+    fn system_hosts_follow_user_hosts_and_are_read_only() {
+        let system = |name: &str| HostEntry {
+            origin: Origin::System,
+            ..host(name)
+        };
+        let hosts = with_system_hosts(
+            vec![host("a"), host("b")],
+            &[system("b"), system("bastion")],
+        );
+        assert_eq!(hosts, vec![host("a"), host("b"), system("bastion")]);
+
+        let mut app = App::test_with_hosts(hosts);
+        app.selected = 2;
+        app.enter_edit_mode();
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(
+            app.status,
+            Some(Status::Info(
+                "'bastion' comes from /etc/ssh/ssh_config and is read-only".to_string()
+            ))
+        );
+
+        app.status = None;
+        app.request_delete();
+        assert!(app.confirmation.is_none());
+        assert!(app.status.is_some());
+
+        app.enter_duplicate_mode();
+        assert_eq!(app.state, AppState::New);
+        assert_eq!(app.form_entry.origin, Origin::User);
+    }
+
+    #[test]
+    fn form_note_is_edited_without_moving_its_comment() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(
+            temp.path(),
+            "Host web\n  # note: migrate off\n  HostName web.example.com\n  #note: by Q3\n",
+        )
+        .unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+
+        app.enter_edit_mode();
+        assert_eq!(app.form_note, "migrate off by Q3");
+        app.form_field = FormField::User;
+        for ch in "deploy".chars() {
+            app.handle_form_input(ch);
+        }
+        app.save_form();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n  # note: migrate off\n  HostName web.example.com\n  User deploy\n  #note: by Q3\n\n"
+        );
+
+        app.enter_edit_mode();
+        app.form_field = FormField::Note;
+        app.form_note = "retired".to_string();
+        app.save_form();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n  # note: retired\n  HostName web.example.com\n  User deploy\n\n"
+        );
+        assert_eq!(app.hosts[0].note(), "retired");
+    }
+
+    #[test]
+    fn jump_to_prefix_cycles_through_matching_aliases() {
+        let mut app = App::test_with_hosts(vec![
+            host("alpha"),
+            host("bravo"),
+            host("Beta"),
+            host("charlie"),
+        ]);
+
+        assert!(app.jump_to_prefix('b'));
+        assert_eq!(app.selected_host_name(), Some("bravo"));
+        assert!(app.jump_to_prefix('b'));
+        assert_eq!(app.selected_host_name(), Some("Beta"));
+        assert!(app.jump_to_prefix('b'));
+        assert_eq!(app.selected_host_name(), Some("bravo"));
+
+        assert!(app.jump_to_prefix('a'));
+        assert_eq!(app.selected_host_name(), Some("alpha"));
+        assert!(!app.jump_to_prefix('z'));
+        assert_eq!(app.selected_host_name(), Some("alpha"));
+        assert_eq!(
+            app.status,
+            Some(Status::Info("No host starts with 'z'".to_string()))
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn connections_are_appended_to_the_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = test_app();
+        app.history_path = Some(dir.path().join("history.jsonl"));
+        app.open_history();
+        assert_eq!(app.state, AppState::Normal);
+
+        app.ssh_connection = SshConnection::new("true".to_string(), Duration::from_secs(5));
+        app.connect_to_host("a").unwrap();
+        app.ssh_connection = SshConnection::new("false".to_string(), Duration::from_secs(5));
+        app.connect_user_override = Some("root".to_string());
+        let err = app.connect_to_host("b").unwrap_err();
+
+        app.open_history();
+        assert_eq!(app.state, AppState::History);
+        let newest = &app.history[0];
+        assert_eq!(
+            (newest.alias.as_str(), newest.target.as_str()),
+            ("b", "root@example.com:22")
+        );
+        assert!(!newest.success);
+        assert_eq!(newest.result, err.to_string());
+        assert_eq!(app.history[1].target, "user@example.com:22");
+        assert!(app.history[1].success);
+
+        app.close_history();
+        assert_eq!(app.state, AppState::Normal);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn warns_when_the_config_links_outside_home() {
+        let home = tempfile::tempdir().unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        let config = home.path().join("config");
+        assert_eq!(symlink_status(&config, home.path()), None);
+
+        let inside = home.path().join("dotfiles_config");
+        fs::write(&inside, "").unwrap();
+        std::os::unix::fs::symlink(&inside, &config).unwrap();
+        assert_eq!(symlink_status(&config, home.path()), None);
+
+        let outside = elsewhere.path().join("ssh_config");
+        fs::write(&outside, "").unwrap();
+        fs::remove_file(&config).unwrap();
+        std::os::unix::fs::symlink(&outside, &config).unwrap();
+        assert_eq!(
+            symlink_status(&config, home.path()),
+            Some(format!(
+                "SSH config links to {} outside your home directory; edits are written there",
+                fs::canonicalize(&outside).unwrap().display()
+            ))
+        );
+    }
+
+    #[test]
+    fn pattern_blocks_supply_defaults_without_being_listed() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(
+            temp.path(),
+            "Host * !badhost\n  ServerAliveInterval 30\n  User ops\n\nHost web\n  HostName web.example.com\n  User deploy\n\nHost badhost\n  HostName 10.0.0.9\n",
+        )
+        .unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+
+        assert_eq!(app.visible_hosts().count(), 2);
+        assert_eq!(
+            app.inherited_directives(),
+            vec![("* !badhost", "ServerAliveInterval", "30")]
+        );
+        app.next();
+        assert_eq!(app.selected_host_name(), Some("badhost"));
+        assert!(app.inherited_directives().is_empty());
+    }
+
+    #[test]
+    fn raw_block_edits_are_parsed_and_written_back() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(
+            temp.path(),
+            "Host web\n    HostName web.example.com\n\nHost db\n    HostName db.example.com\n",
+        )
+        .unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+
+        app.enter_raw_edit_mode();
+        assert_eq!(app.state, AppState::RawEdit);
+        assert_eq!(
+            app.raw_editor.lines(),
+            ["Host web", "    HostName web.example.com"]
+        );
+
+        // Splitting the Host line leaves a block ssh wouldn't recognize.
+        for _ in 0..4 {
+            app.raw_editor.right();
+        }
+        app.raw_editor.newline();
+        app.save_raw_edit();
+        assert_eq!(app.state, AppState::RawEdit);
+        assert!(app.form_error.as_deref().unwrap().contains("Host line"));
+
+        app.raw_editor.backspace();
+        app.raw_editor.down();
+        app.raw_editor.end();
+        app.raw_editor.newline();
+        for ch in "    ForwardAgent yes".chars() {
+            app.raw_editor.insert(ch);
+        }
+        app.save_raw_edit();
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n    HostName web.example.com\n    ForwardAgent yes\n\nHost db\n    HostName db.example.com\n"
+        );
+        assert_eq!(app.hosts[0].get("ForwardAgent"), "yes");
+    }
+
+    #[test]
+    fn switching_profiles_changes_the_config_read_and_written() {
+        let work = NamedTempFile::new().unwrap();
+        let personal = NamedTempFile::new().unwrap();
+        fs::write(work.path(), "Host office\n  HostName office.example.com\n").unwrap();
+        fs::write(personal.path(), "Host nas\n  HostName 192.168.1.5\n").unwrap();
+        let mut app = App::test_with_config_path(work.path().to_path_buf()).unwrap();
+        app.open_profile_picker();
+        assert_eq!(app.state, AppState::Normal);
+
+        app.config.profiles.insert(
+            "personal".to_string(),
+            personal.path().display().to_string(),
+        );
+        app.open_profile_picker();
+        assert_eq!(app.profile_candidates, vec!["default", "personal"]);
+        app.profile_picker_input('p');
+        app.switch_to_picked_profile();
+        assert_eq!(app.active_profile.as_deref(), Some("personal"));
+        assert_eq!(app.selected_host_name(), Some("nas"));
+
+        // Reloads and writes both follow the active profile.
+        fs::write(
+            personal.path(),
+            "Host nas\n  HostName 192.168.1.5\n\nHost pi\n  HostName 192.168.1.6\n",
+        )
+        .unwrap();
+        app.reload();
+        assert_eq!(app.hosts.len(), 2);
+        app.toggle_selected_disabled();
+        assert!(fs::read_to_string(personal.path())
+            .unwrap()
+            .starts_with("# Host nas"));
+        assert_eq!(
+            fs::read_to_string(work.path()).unwrap(),
+            "Host office\n  HostName office.example.com\n"
+        );
+
+        app.switch_profile("default");
+        assert_eq!(app.active_profile, None);
+        assert_eq!(app.selected_host_name(), Some("office"));
+    }
+
+    #[test]
+    fn motions_move_by_counts_and_positions() {
+        let mut app = App::test_with_hosts(vec![host("a"), host("b"), host("c"), host("d")]);
+        let selected = |app: &App| app.selected_host_name().unwrap().to_string();
+
+        app.apply_motion(Motion::Bottom);
+        assert_eq!(selected(&app), "d");
+        app.apply_motion(Motion::Down(1));
+        assert_eq!(selected(&app), "a");
+        app.apply_motion(Motion::Down(2));
+        assert_eq!(selected(&app), "c");
+        app.apply_motion(Motion::Down(5));
+        assert_eq!(selected(&app), "d");
+        app.apply_motion(Motion::Up(2));
+        assert_eq!(selected(&app), "b");
+        app.apply_motion(Motion::Position(3));
+        assert_eq!(selected(&app), "c");
+        app.apply_motion(Motion::Position(9));
+        assert_eq!(selected(&app), "d");
+        app.apply_motion(Motion::Top);
+        assert_eq!(selected(&app), "a");
+        app.apply_motion(Motion::Up(1));
+        assert_eq!(selected(&app), "d");
+    }
+
+    #[test]
+    fn imported_snippets_are_previewed_then_upserted() {
+        let temp = NamedTempFile::new().unwrap();
+        fs::write(temp.path(), "Host web\n    HostName old.example.com\n").unwrap();
+        let mut app = App::test_with_config_path(temp.path().to_path_buf()).unwrap();
+
+        app.open_import();
+        app.handle_form_paste("# shared by the team\n  HostName web.example.com\n");
+        app.preview_import();
+        assert_eq!(app.state, AppState::Import);
+        assert!(app
+            .form_error
+            .as_deref()
+            .unwrap()
+            .contains("No Host blocks"));
+
+        app.cancel_import();
+        app.open_import();
+        app.handle_form_paste(
+            "Host web\r\n  HostName web.example.com\r\n\r\nHost db\r\n  HostName db.example.com\r\n  Port 2222\r\n",
+        );
+        app.preview_import();
+        assert_eq!(app.state, AppState::Confirm);
+        assert_eq!(
+            app.confirmation.as_ref().unwrap().prompt,
+            "Import 'web' (replaces existing), 'db' (new)? [y/N]"
+        );
+        assert_eq!(app.take_confirmation(), Some(Action::Import));
+        app.import_pending();
+
+        assert_eq!(
+            app.status,
+            Some(Status::Success(
+                "Imported hosts: 1 added, 1 updated, 0 skipped".to_string()
+            ))
+        );
+        let hosts: Vec<_> = app.hosts.iter().map(|entry| entry.host.as_str()).collect();
+        assert_eq!(hosts, ["web", "db"]);
+        assert_eq!(app.hosts[0].hostname(), "web.example.com");
+        assert_eq!(app.hosts[1].port(), "2222");
+
+        let snippet = NamedTempFile::new().unwrap();
+        fs::write(snippet.path(), "Host cache\n  HostName cache.example.com\n").unwrap();
+        app.open_import();
+        app.handle_form_paste(&format!("{}\n", snippet.path().display()));
+        app.preview_import();
+        assert_eq!(
+            app.confirmation.as_ref().unwrap().prompt,
+            "Import 'cache' (new)? [y/N]"
+        );
+    }
+
+    #[test]
+    fn alias_sort_runs_both_ways_and_keeps_ties_in_file_order() {
+        let hosts = [
+            host("web").with("HostName", "b.example.com"),
+            host("db").with("HostName", "z.example.com"),
+            host("Web").with("HostName", "a.example.com"),
+            host("cache").with("HostName", "z.example.com"),
+            host("db")
+                .with("HostName", "z.example.com")
+                .with("Port", "2222"),
+        ];
+        let sorted = |descending| {
+            let mut entries: Vec<&HostEntry> = hosts.iter().collect();
+            let sort = HostSort {
+                key: SortKey::Alias,
+                descending,
+            };
+            entries.sort_by(|a, b| compare_hosts(a, b, sort));
+            entries
+                .iter()
+                .map(|entry| format!("{}:{}", entry.host, entry.port()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(sorted(false), ["cache:", "db:", "db:2222", "Web:", "web:"]);
+        assert_eq!(sorted(true), ["web:", "Web:", "db:", "db:2222", "cache:"]);
+    }
+
+    #[test]
+    fn hostname_sort_falls_back_to_the_alias() {
+        let mut app = App::test_with_hosts(vec![
+            host("web").with("HostName", "10.0.0.9"),
+            host("db").with("HostName", "10.0.0.2"),
+            host("bastion"),
+            host("api").with("HostName", "10.0.0.9"),
+        ]);
+        let listed = |app: &App| {
+            app.visible_hosts()
+                .map(|entry| entry.host.clone())
+                .collect::<Vec<_>>()
+        };
+        app.next();
+
+        app.cycle_sort_key();
+        assert_eq!(listed(&app), ["api", "bastion", "db", "web"]);
+        app.cycle_sort_key();
+        assert_eq!(app.sort.key, SortKey::Hostname);
+        assert_eq!(listed(&app), ["db", "api", "web", "bastion"]);
+        assert_eq!(app.selected_host_name(), Some("db"));
+
+        app.toggle_sort_direction();
+        assert_eq!(listed(&app), ["bastion", "web", "api", "db"]);
+        app.cycle_sort_key();
+        assert_eq!(listed(&app), ["api", "bastion", "db", "web"]);
+        app.toggle_sort_direction();
+        assert_eq!(listed(&app), ["web", "db", "bastion", "api"]);
+    }
+
+    #[test]
+    fn connect_args_prompt_remembers_args_per_host() {
+        let mut app = test_app();
+        app.open_connect_args();
+        assert_eq!(app.state, AppState::ConnectArgs);
+        assert_eq!(app.connect_args, "");
+        "-v  -L 8080:localhost:80"
+            .chars()
+            .for_each(|c| app.connect_args_input(c));
+        app.accept_connect_args();
+        assert_eq!(app.state, AppState::Normal);
+        assert_eq!(
+            app.connect_args_override,
+            Some(
+                ["-v", "-L", "8080:localhost:80"]
+                    .map(str::to_string)
+                    .to_vec()
+            )
+        );
+        app.request_confirmation("Connect?".to_string(), Action::Connect);
+        app.cancel_confirmation();
+        assert_eq!(app.connect_args_override, None);
+
+        app.next();
+        app.open_connect_args();
+        assert_eq!(app.connect_args, "");
+        "-A".chars().for_each(|c| app.connect_args_input(c));
+        app.accept_connect_args();
+
+        app.previous();
+        app.open_connect_args();
+        assert_eq!(app.connect_args, "-v  -L 8080:localhost:80");
+        app.cycle_args_history(true);
+        assert_eq!(app.connect_args, "-A");
+        app.cycle_args_history(true);
+        app.cycle_args_history(true);
+        assert_eq!(app.connect_args, "-v  -L 8080:localhost:80");
+        app.cycle_args_history(false);
+        assert_eq!(app.connect_args, "-A");
+        app.cycle_args_history(false);
+        assert_eq!(app.connect_args, "-v  -L 8080:localhost:80");
+        app.close_connect_args();
+        assert_eq!(app.state, AppState::Normal);
+    }
+
+    #[test]
+    fn connect_args_split_like_a_shell() {
+        assert_eq!(
+            split_shell_words(r#"-o "ProxyCommand ssh -W %h:%p bastion" -L 80:a:80"#).unwrap(),
+            ["-o", "ProxyCommand ssh -W %h:%p bastion", "-L", "80:a:80"]
+        );
+        assert_eq!(
+            split_shell_words(r#"  'it''s' a\ b "q\"x" '' "#).unwrap(),
+            ["its", "a b", "q\"x", ""]
+        );
+        assert_eq!(split_shell_words("").unwrap(), Vec::<String>::new());
+        assert_eq!(split_shell_words("-o 'open"), None);
+        assert_eq!(split_shell_words(r#"-o "open"#), None);
+
+        let mut app = test_app();
+        app.open_connect_args();
+        r#"-o 'ProxyCommand ssh bastion"#
+            .chars()
+            .for_each(|c| app.connect_args_input(c));
+        assert_eq!(app.connect_args_problem(), Some("unbalanced quote"));
+        assert!(!app.accept_connect_args());
+        assert_eq!(app.state, AppState::ConnectArgs);
+        assert_eq!(app.connect_args_override, None);
+
+        app.connect_args_input('\'');
+        assert!(app.accept_connect_args());
+        assert_eq!(
+            app.connect_args_override,
+            Some(
+                ["-o", "ProxyCommand ssh bastion"]
+                    .map(str::to_string)
+                    .to_vec()
+            )
+        );
     }
 }
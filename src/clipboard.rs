@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// Clipboard helpers, tried in order until one starts: macOS, Wayland,
+/// then the two common X11 tools.
+const TOOLS: &[(&str, &[&str])] = &[
+    ("pbcopy", &[]),
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+];
+
+/// Puts `text` on the system clipboard by piping it into the first
+/// available helper.
+pub fn copy(text: &str) -> Result<()> {
+    for (program, args) in TOOLS {
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .with_context(|| format!("Failed to write to {}", program))?;
+        }
+        let status = child
+            .wait()
+            .with_context(|| format!("Failed to wait for {}", program))?;
+        if !status.success() {
+            anyhow::bail!("{} exited with {}", program, status);
+        }
+        return Ok(());
+    }
+    anyhow::bail!("No clipboard tool found (install wl-copy, xclip or xsel)")
+}
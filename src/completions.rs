@@ -0,0 +1,48 @@
+/// Shell completion for `ssh <TAB>` backed by `ssh-tui --list`, so the
+/// aliases always match the current config.
+pub fn completion_script(shell: &str) -> Option<&'static str> {
+    match shell {
+        "bash" => Some(BASH),
+        "zsh" => Some(ZSH),
+        "fish" => Some(FISH),
+        _ => None,
+    }
+}
+
+const BASH: &str = r#"_ssh_tui_hosts() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    COMPREPLY=($(compgen -W "$(ssh-tui --list 2>/dev/null)" -- "$cur"))
+}
+complete -F _ssh_tui_hosts ssh
+"#;
+
+const ZSH: &str = r#"_ssh_tui_hosts() {
+    local -a hosts
+    hosts=(${(f)"$(ssh-tui --list 2>/dev/null)"})
+    compadd -a hosts
+}
+compdef _ssh_tui_hosts ssh
+"#;
+
+const FISH: &str = r#"complete -c ssh -f -a '(ssh-tui --list 2>/dev/null)'
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripts_list_hosts_through_ssh_tui() {
+        for shell in ["bash", "zsh", "fish"] {
+            let script = completion_script(shell).unwrap();
+            assert!(script.contains("ssh-tui --list"), "{}", shell);
+        }
+        assert!(completion_script("bash")
+            .unwrap()
+            .contains("complete -F _ssh_tui_hosts ssh"));
+        assert!(completion_script("zsh")
+            .unwrap()
+            .contains("compdef _ssh_tui_hosts ssh"));
+        assert!(completion_script("powershell").is_none());
+    }
+}
@@ -1,16 +1,94 @@
+use crate::{keys::expand_tilde, ssh_config::AliasMatch};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::{
-    fs,
+    collections::BTreeMap,
+    env, fs,
     path::{Path, PathBuf},
 };
 
+/// Environment variable that relocates the home directory used for both the
+/// app config and the SSH config, e.g. for tests or a portable setup.
+pub const HOME_OVERRIDE_VAR: &str = "SSH_TUI_HOME";
+
+const XDG_CONFIG_HOME_VAR: &str = "XDG_CONFIG_HOME";
+
+/// Variables naming the SSH config to edit instead of `~/.ssh/config`, in
+/// order of precedence: the app's own first, then the generic one other
+/// tools may share.
+const SSH_CONFIG_VARS: [&str; 2] = ["SSH_TUI_CONFIG", "SSH_CONFIG"];
+
+const SYSTEM_SSH_CONFIG_RELATIVE: &str = "etc/ssh/ssh_config";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     #[serde(default = "default_ssh_binary")]
     pub ssh_binary: String,
     #[serde(default = "default_timeout")]
     pub timeout: u64,
+    #[serde(default = "default_mosh_binary")]
+    pub mosh_binary: String,
+    #[serde(default = "default_sftp_binary")]
+    pub sftp_binary: String,
+    #[serde(default = "default_ssh_copy_id_binary")]
+    pub ssh_copy_id_binary: String,
+    /// Times to retry an ssh connection that fails with exit code 255,
+    /// waiting 1s, 2s, 4s, ... in between.
+    #[serde(default)]
+    pub connect_retries: u32,
+    /// Local shell command run after each session, with `{host}` and
+    /// `{hostname}` placeholders.
+    #[serde(default)]
+    pub post_connect_command: Option<String>,
+    /// Ask before connecting to hosts tagged `prod` or matching
+    /// `prod_patterns`.
+    #[serde(default)]
+    pub confirm_prod_connect: bool,
+    #[serde(default)]
+    pub prod_patterns: Vec<String>,
+    /// Seconds a status message stays in the footer; errors stay twice as
+    /// long, and `0` keeps messages until the next one.
+    #[serde(default = "default_status_timeout")]
+    pub status_timeout: u64,
+    /// Return to the host list when a session ends; `false` exits instead.
+    #[serde(default = "default_stay_open")]
+    pub stay_open: bool,
+    /// The same switch the other way round: `true` exits after a clean
+    /// session even while `stay_open` is left at its default.
+    #[serde(default)]
+    pub exit_after_connect: bool,
+    /// Capture the mouse while the TUI runs; `false` leaves clicks and
+    /// drags to the terminal, so text can be selected as usual.
+    #[serde(default = "default_mouse")]
+    pub mouse: bool,
+    /// How many hosts the recent-connections panel lists.
+    #[serde(default = "default_recent_limit")]
+    pub recent_limit: usize,
+    /// How the editor finds the block of the alias it updates or deletes.
+    #[serde(default)]
+    pub alias_match: AliasMatch,
+    /// Record parse errors, config writes and connections in `log_path`.
+    #[serde(default)]
+    pub log: bool,
+    /// Named SSH configs to switch between at runtime, e.g.
+    /// `personal = "~/.ssh/config.personal"`.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, String>,
+    /// Values pre-filled when creating a host. The tables are kept last:
+    /// TOML tables must follow plain keys when the config is written back.
+    #[serde(default)]
+    pub defaults: HostDefaults,
+}
+
+/// The `[defaults]` table; empty strings leave a field blank.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostDefaults {
+    #[serde(default)]
+    pub user: String,
+    #[serde(default)]
+    pub port: String,
+    #[serde(default)]
+    pub identity_file: String,
 }
 
 fn default_ssh_binary() -> String {
@@ -21,11 +99,55 @@ fn default_timeout() -> u64 {
     30
 }
 
+fn default_recent_limit() -> usize {
+    5
+}
+
+fn default_status_timeout() -> u64 {
+    5
+}
+
+fn default_stay_open() -> bool {
+    true
+}
+
+fn default_mouse() -> bool {
+    true
+}
+
+fn default_mosh_binary() -> String {
+    "mosh".to_string()
+}
+
+fn default_sftp_binary() -> String {
+    "sftp".to_string()
+}
+
+fn default_ssh_copy_id_binary() -> String {
+    "ssh-copy-id".to_string()
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             ssh_binary: default_ssh_binary(),
             timeout: default_timeout(),
+            mosh_binary: default_mosh_binary(),
+            sftp_binary: default_sftp_binary(),
+            ssh_copy_id_binary: default_ssh_copy_id_binary(),
+            connect_retries: 0,
+            post_connect_command: None,
+            confirm_prod_connect: false,
+            prod_patterns: Vec::new(),
+            status_timeout: default_status_timeout(),
+            stay_open: default_stay_open(),
+            exit_after_connect: false,
+            mouse: default_mouse(),
+            recent_limit: default_recent_limit(),
+            alias_match: AliasMatch::default(),
+            log: false,
+            profiles: BTreeMap::new(),
+            defaults: HostDefaults::default(),
         }
     }
 }
@@ -45,15 +167,76 @@ impl Config {
     }
 
     fn config_path() -> Result<PathBuf> {
-        let home = dirs::home_dir().context("Failed to get home directory")?;
-        Ok(home.join(".config").join("ssh-tui").join("config.toml"))
+        Ok(Self::config_dir()?.join("config.toml"))
     }
 
+    /// Where remembered state such as recent connections is kept.
+    pub fn state_path() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("state.toml"))
+    }
+
+    /// Every connection attempt, one JSON object per line.
+    pub fn history_path() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("history.jsonl"))
+    }
+
+    /// The troubleshooting log written when `log` is enabled.
+    pub fn log_path() -> Result<PathBuf> {
+        Ok(Self::config_dir()?.join("ssh-tui.log"))
+    }
+
+    /// `$XDG_CONFIG_HOME/ssh-tui`, falling back to `~/.config/ssh-tui`.
+    /// `SSH_TUI_HOME` takes precedence so a relocated home stays
+    /// self-contained.
+    fn config_dir() -> Result<PathBuf> {
+        if env::var_os(HOME_OVERRIDE_VAR).is_none_or(|home| home.is_empty()) {
+            if let Some(base) = env::var_os(XDG_CONFIG_HOME_VAR).filter(|base| !base.is_empty()) {
+                return Ok(PathBuf::from(base).join("ssh-tui"));
+            }
+        }
+        let home = Self::home_dir()?;
+        Ok(home.join(".config").join("ssh-tui"))
+    }
+
+    /// The SSH config to read and edit. `SSH_TUI_CONFIG` wins, then
+    /// `SSH_CONFIG`, then `config` in the ssh directory (which follows
+    /// `SSH_TUI_HOME`). An empty variable counts as unset. The value may
+    /// use `~` and `$VAR` or `${VAR}`, so a script can pass
+    /// `$SSH_CONFIG_DIR/config` through unexpanded.
     pub fn ssh_config_path() -> Result<PathBuf> {
-        let home = dirs::home_dir().context("Failed to get home directory")?;
+        let home = Self::home_dir()?;
+        for var in SSH_CONFIG_VARS {
+            if let Some(value) = env::var(var).ok().filter(|value| !value.is_empty()) {
+                return Ok(expand_tilde(&expand_env_vars(&value), &home));
+            }
+        }
         Ok(home.join(".ssh").join("config"))
     }
 
+    /// The system-wide client config, `/etc/ssh/ssh_config`. Under
+    /// `SSH_TUI_HOME` it is looked up inside that directory instead, so a
+    /// relocated home stays self-contained.
+    pub fn system_ssh_config_path() -> PathBuf {
+        match env::var_os(HOME_OVERRIDE_VAR) {
+            Some(home) if !home.is_empty() => PathBuf::from(home).join(SYSTEM_SSH_CONFIG_RELATIVE),
+            _ => Path::new("/").join(SYSTEM_SSH_CONFIG_RELATIVE),
+        }
+    }
+
+    /// The directory holding the user's keys, `~/.ssh`.
+    pub fn ssh_dir() -> Result<PathBuf> {
+        let home = Self::home_dir()?;
+        Ok(home.join(".ssh"))
+    }
+
+    /// The user's home directory, unless `SSH_TUI_HOME` points elsewhere.
+    pub fn home_dir() -> Result<PathBuf> {
+        match env::var_os(HOME_OVERRIDE_VAR) {
+            Some(home) if !home.is_empty() => Ok(PathBuf::from(home)),
+            _ => dirs::home_dir().context("Failed to get home directory"),
+        }
+    }
+
     #[allow(dead_code)]
     pub fn save(&self, path: &Path) -> Result<()> {
         if let Some(parent) = path.parent() {
@@ -65,14 +248,171 @@ impl Config {
     }
 }
 
+/// Replaces `$VAR` and `${VAR}` with the variable's value, or nothing when
+/// it is unset, as a shell would. A `$` not followed by a name is kept.
+fn expand_env_vars(value: &str) -> String {
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], &braced[end + 1..]),
+                None => ("", after),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        if name.is_empty() {
+            expanded.push('$');
+            rest = after;
+        } else {
+            expanded.push_str(&env::var(name).unwrap_or_default());
+            rest = remainder;
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use std::sync::{Mutex, MutexGuard};
+
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Serializes tests that mutate process-wide environment variables.
+    pub(crate) fn lock_env() -> MutexGuard<'static, ()> {
+        ENV_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
 
     #[test]
     fn test_default_config() {
         let config = Config::default();
         assert_eq!(config.ssh_binary, "ssh");
         assert_eq!(config.timeout, 30);
+        assert_eq!(config.mosh_binary, "mosh");
+        assert_eq!(config.sftp_binary, "sftp");
+        assert_eq!(config.ssh_copy_id_binary, "ssh-copy-id");
+        assert_eq!(config.connect_retries, 0);
+        assert_eq!(config.recent_limit, 5);
+        assert_eq!(config.status_timeout, 5);
+        assert_eq!(config.alias_match, AliasMatch::Exact);
+        assert!(config.mouse);
+        assert!(config.stay_open && !config.exit_after_connect);
+    }
+
+    #[test]
+    fn test_defaults_table_round_trips() {
+        let config: Config =
+            toml::from_str("timeout = 5\n\n[defaults]\nuser = \"deploy\"\nport = \"2222\"\n")
+                .unwrap();
+        assert_eq!(config.defaults.user, "deploy");
+        assert_eq!(config.defaults.port, "2222");
+        assert_eq!(config.defaults.identity_file, "");
+
+        let written = toml::to_string_pretty(&config).unwrap();
+        let reread: Config = toml::from_str(&written).unwrap();
+        assert_eq!(reread.defaults, config.defaults);
+    }
+
+    #[test]
+    fn test_xdg_config_home_is_honored() {
+        let _guard = lock_env();
+        let base = tempfile::tempdir().unwrap();
+        let previous = env::var_os(XDG_CONFIG_HOME_VAR);
+        env::remove_var(HOME_OVERRIDE_VAR);
+        env::set_var(XDG_CONFIG_HOME_VAR, base.path());
+        let config_path = Config::config_path().unwrap();
+        env::set_var(XDG_CONFIG_HOME_VAR, "");
+        let fallback = Config::config_path().unwrap();
+        match previous {
+            Some(previous) => env::set_var(XDG_CONFIG_HOME_VAR, previous),
+            None => env::remove_var(XDG_CONFIG_HOME_VAR),
+        }
+
+        assert_eq!(config_path, base.path().join("ssh-tui").join("config.toml"));
+        assert_eq!(
+            fallback,
+            dirs::home_dir()
+                .unwrap()
+                .join(".config")
+                .join("ssh-tui")
+                .join("config.toml")
+        );
+    }
+
+    #[test]
+    fn test_home_override_redirects_paths() {
+        let _guard = lock_env();
+        let home = tempfile::tempdir().unwrap();
+        env::set_var(HOME_OVERRIDE_VAR, home.path());
+
+        let config_path = Config::config_path().unwrap();
+        let ssh_config_path = Config::ssh_config_path().unwrap();
+        env::remove_var(HOME_OVERRIDE_VAR);
+
+        assert_eq!(
+            config_path,
+            home.path()
+                .join(".config")
+                .join("ssh-tui")
+                .join("config.toml")
+        );
+        assert_eq!(ssh_config_path, home.path().join(".ssh").join("config"));
+    }
+
+    #[test]
+    fn test_ssh_config_env_vars_take_precedence() {
+        let _guard = lock_env();
+        let home = tempfile::tempdir().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let previous: Vec<_> = SSH_CONFIG_VARS.map(env::var_os).into();
+        env::set_var(HOME_OVERRIDE_VAR, home.path());
+        env::set_var("SSH_TUI_TEST_CONFIG_DIR", dir.path());
+
+        env::set_var("SSH_CONFIG", "~/work/config");
+        let generic = Config::ssh_config_path().unwrap();
+        env::set_var("SSH_TUI_CONFIG", "${SSH_TUI_TEST_CONFIG_DIR}/config");
+        let specific = Config::ssh_config_path().unwrap();
+        env::set_var(
+            "SSH_TUI_CONFIG",
+            "$SSH_TUI_TEST_CONFIG_DIR/$SSH_TUI_TEST_UNSET.d/config",
+        );
+        let unset = Config::ssh_config_path().unwrap();
+        env::set_var("SSH_TUI_CONFIG", "");
+        env::set_var("SSH_CONFIG", "");
+        let fallback = Config::ssh_config_path().unwrap();
+
+        env::remove_var(HOME_OVERRIDE_VAR);
+        env::remove_var("SSH_TUI_TEST_CONFIG_DIR");
+        for (var, value) in SSH_CONFIG_VARS.iter().zip(previous) {
+            match value {
+                Some(value) => env::set_var(var, value),
+                None => env::remove_var(var),
+            }
+        }
+
+        assert_eq!(generic, home.path().join("work").join("config"));
+        assert_eq!(specific, dir.path().join("config"));
+        assert_eq!(unset, dir.path().join(".d").join("config"));
+        assert_eq!(fallback, home.path().join(".ssh").join("config"));
+    }
+
+    #[test]
+    fn test_expand_env_vars_keeps_bare_dollars() {
+        let _guard = lock_env();
+        env::set_var("SSH_TUI_TEST_VAR", "x");
+        let expanded = expand_env_vars("$SSH_TUI_TEST_VAR-${SSH_TUI_TEST_VAR}/$/${unclosed");
+        env::remove_var("SSH_TUI_TEST_VAR");
+        assert_eq!(expanded, "x-x/$/${unclosed");
     }
 }
@@ -0,0 +1,168 @@
+/// A small multi-line text buffer with a cursor, enough to edit a host
+/// block in place. Columns count characters, not bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEditor {
+    lines: Vec<String>,
+    row: usize,
+    col: usize,
+}
+
+impl Default for TextEditor {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl TextEditor {
+    /// Starts with the cursor at the top left. An empty buffer still has
+    /// one line to type into.
+    pub fn new(mut lines: Vec<String>) -> Self {
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        Self {
+            lines,
+            row: 0,
+            col: 0,
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// The cursor as (line, column).
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    pub fn insert(&mut self, c: char) {
+        let index = self.byte_index();
+        self.lines[self.row].insert(index, c);
+        self.col += 1;
+    }
+
+    /// Splits the line at the cursor.
+    pub fn newline(&mut self) {
+        let index = self.byte_index();
+        let rest = self.lines[self.row].split_off(index);
+        self.row += 1;
+        self.col = 0;
+        self.lines.insert(self.row, rest);
+    }
+
+    /// Deletes the character before the cursor, joining with the previous
+    /// line at the start of one.
+    pub fn backspace(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+            let index = self.byte_index();
+            self.lines[self.row].remove(index);
+        } else if self.row > 0 {
+            let line = self.lines.remove(self.row);
+            self.row -= 1;
+            self.col = self.line_len();
+            self.lines[self.row].push_str(&line);
+        }
+    }
+
+    /// Deletes the character under the cursor, joining with the next line
+    /// at the end of one.
+    pub fn delete(&mut self) {
+        if self.col < self.line_len() {
+            let index = self.byte_index();
+            self.lines[self.row].remove(index);
+        } else if self.row + 1 < self.lines.len() {
+            let next = self.lines.remove(self.row + 1);
+            self.lines[self.row].push_str(&next);
+        }
+    }
+
+    pub fn left(&mut self) {
+        if self.col > 0 {
+            self.col -= 1;
+        } else if self.row > 0 {
+            self.row -= 1;
+            self.col = self.line_len();
+        }
+    }
+
+    pub fn right(&mut self) {
+        if self.col < self.line_len() {
+            self.col += 1;
+        } else if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = 0;
+        }
+    }
+
+    pub fn up(&mut self) {
+        if self.row > 0 {
+            self.row -= 1;
+            self.col = self.col.min(self.line_len());
+        }
+    }
+
+    pub fn down(&mut self) {
+        if self.row + 1 < self.lines.len() {
+            self.row += 1;
+            self.col = self.col.min(self.line_len());
+        }
+    }
+
+    pub fn home(&mut self) {
+        self.col = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.col = self.line_len();
+    }
+
+    fn line_len(&self) -> usize {
+        self.lines[self.row].chars().count()
+    }
+
+    fn byte_index(&self) -> usize {
+        let line = &self.lines[self.row];
+        line.char_indices()
+            .nth(self.col)
+            .map_or(line.len(), |(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_split_and_join_lines() {
+        let mut editor = TextEditor::new(vec!["Host wéb".to_string()]);
+        editor.end();
+        editor.left();
+        editor.backspace();
+        assert_eq!(editor.text(), "Host wb");
+
+        editor.end();
+        editor.newline();
+        for c in "  Port 22".chars() {
+            editor.insert(c);
+        }
+        assert_eq!(editor.lines(), ["Host wb", "  Port 22"]);
+        assert_eq!(editor.cursor(), (1, 9));
+
+        editor.home();
+        editor.backspace();
+        assert_eq!(editor.text(), "Host wb  Port 22");
+        assert_eq!(editor.cursor(), (0, 7));
+
+        editor.newline();
+        editor.up();
+        editor.end();
+        editor.delete();
+        assert_eq!(editor.text(), "Host wb  Port 22");
+    }
+}
@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+/// One connection attempt, stored as a line of `history.jsonl`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Unix time the session ended.
+    pub timestamp: u64,
+    pub alias: String,
+    /// `user@hostname:port` as the config resolves it.
+    pub target: String,
+    pub success: bool,
+    /// What the connection reported: the exit summary, or the error.
+    pub result: String,
+}
+
+/// Appends `entry` as one JSON line. History must never get in the way of
+/// a connection, so failures to write are ignored.
+pub fn append(path: &Path, entry: &HistoryEntry) {
+    let Ok(line) = serde_json::to_string(entry) else {
+        return;
+    };
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(file, "{}", line);
+    }
+}
+
+/// The last `limit` entries, newest first. A missing file has none, and
+/// lines that don't parse are skipped.
+pub fn load_recent(path: &Path, limit: usize) -> Vec<HistoryEntry> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    contents
+        .lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .take(limit)
+        .collect()
+}
+
+/// `YYYY-MM-DD HH:MM` in UTC for a Unix timestamp.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64;
+    let minutes = timestamp % 86_400 / 60;
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(alias: &str, timestamp: u64) -> HistoryEntry {
+        HistoryEntry {
+            timestamp,
+            alias: alias.to_string(),
+            target: format!("{}.example.com:22", alias),
+            success: true,
+            result: format!("Connection to {} closed", alias),
+        }
+    }
+
+    #[test]
+    fn appended_entries_load_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("history.jsonl");
+        assert!(load_recent(&path, 10).is_empty());
+
+        append(&path, &entry("web", 1));
+        append(&path, &entry("db", 2));
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+        append(&path, &entry("cache", 3));
+
+        assert_eq!(
+            load_recent(&path, 2),
+            vec![entry("cache", 3), entry("db", 2)]
+        );
+        assert_eq!(load_recent(&path, 10).len(), 3);
+    }
+
+    #[test]
+    fn formats_timestamps_as_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00");
+        assert_eq!(format_timestamp(951_825_600), "2000-02-29 12:00");
+        assert_eq!(format_timestamp(1_767_225_599), "2025-12-31 23:59");
+    }
+}
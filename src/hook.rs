@@ -0,0 +1,92 @@
+use std::process::Command;
+
+/// Substitutes `{host}` and `{hostname}` in a hook template. Values are
+/// single-quoted so aliases can never inject extra shell syntax, and the
+/// template is scanned once so a value that looks like a placeholder is
+/// left alone.
+pub fn render_hook_command(template: &str, host: &str, hostname: &str) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(tail) = rest.strip_prefix("{hostname}") {
+            rendered.push_str(&shell_quote(hostname));
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix("{host}") {
+            rendered.push_str(&shell_quote(host));
+            rest = tail;
+        } else {
+            rendered.push('{');
+            rest = &rest[1..];
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Runs the rendered hook through `sh -c` and summarizes its outcome for
+/// the status line. Failures are reported, never propagated.
+pub fn run_post_connect(template: &str, host: &str, hostname: &str) -> String {
+    let command = render_hook_command(template, host, hostname);
+    match Command::new("sh").arg("-c").arg(&command).output() {
+        Ok(output) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let first_line = stdout.lines().next().unwrap_or("").trim();
+            let mut summary = match output.status.code() {
+                Some(0) => "post-connect hook ok".to_string(),
+                Some(code) => format!("post-connect hook exited with code {}", code),
+                None => "post-connect hook terminated by signal".to_string(),
+            };
+            if !first_line.is_empty() {
+                summary.push_str(&format!(": {}", first_line));
+            }
+            summary
+        }
+        Err(err) => format!("post-connect hook failed to start: {}", err),
+    }
+}
+
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_substitutes_placeholders() {
+        let rendered = render_hook_command(
+            "tmux rename-window {host} && echo {hostname}",
+            "web",
+            "web.example.com",
+        );
+        assert_eq!(
+            rendered,
+            "tmux rename-window 'web' && echo 'web.example.com'"
+        );
+    }
+
+    #[test]
+    fn render_quotes_shell_metacharacters() {
+        let rendered = render_hook_command("echo {host}", "a'; rm -rf ~; '", "");
+        assert_eq!(rendered, "echo 'a'\\''; rm -rf ~; '\\'''");
+
+        let rendered = render_hook_command("echo {host} {hostname}", "{hostname}", "$(id)");
+        assert_eq!(rendered, "echo '{hostname}' '$(id)'");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_captures_stdout_and_exit_code() {
+        assert_eq!(
+            run_post_connect("echo connected {host}", "web", "web.example.com"),
+            "post-connect hook ok: connected web"
+        );
+        assert_eq!(
+            run_post_connect("exit 3", "web", "web.example.com"),
+            "post-connect hook exited with code 3"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use crate::state::unix_now;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+
+/// Where events go, or `None` while logging is off. The TUI owns the
+/// terminal, so a file is the only place that can take them.
+static LOG_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Starts appending events to `path`; `None` turns logging off again.
+pub fn init(path: Option<PathBuf>) {
+    *LOG_PATH.lock().unwrap_or_else(PoisonError::into_inner) = path;
+}
+
+pub fn info(message: &str) {
+    record("INFO", message);
+}
+
+pub fn warn(message: &str) {
+    record("WARN", message);
+}
+
+/// Appends `<unix time> <level> <message>`. Logging must never get in the
+/// way, so failures to write are ignored.
+fn record(level: &str, message: &str) {
+    let path = LOG_PATH.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(path) = path.as_ref() else {
+        return;
+    };
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(file, "{} {} {}", unix_now(), level, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enabled_logging_appends_timestamped_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("ssh-tui.log");
+
+        init(Some(path.clone()));
+        info("wrote config");
+        warn("failed to parse");
+        init(None);
+        info("not recorded");
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert!(lines
+            .iter()
+            .any(|line| line.ends_with(" INFO wrote config")));
+        assert!(lines
+            .iter()
+            .any(|line| line.ends_with(" WARN failed to parse")));
+        assert!(!contents.contains("not recorded"));
+    }
+}
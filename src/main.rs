@@ -1,59 +1,453 @@
+mod action;
 mod app;
+mod clipboard;
+mod completions;
 mod config;
+mod editor;
+mod history;
+mod hook;
+mod keys;
+mod log;
+mod motion;
+#[cfg(unix)]
+mod permissions;
+mod search;
 mod ssh;
 mod ssh_config;
+mod state;
 mod ui;
 
-use anyhow::Result;
-use app::App;
+use action::Action;
+use anyhow::{Context, Result};
+use app::{App, AppState};
 use crossterm::{
+    cursor,
     event::{
-        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyEventKind, KeyModifiers,
     },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use motion::Step;
 use ratatui::{backend::CrosstermBackend, Terminal};
-use std::io;
+use ssh::ConnectTool;
+use ssh_config::{ConflictPolicy, MoveDirection};
+use std::{
+    io::{self, IsTerminal},
+    path::Path,
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
+
+/// How often the event loop redraws while no input arrives.
+const TICK_RATE: Duration = Duration::from_millis(100);
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("--version") => {
+            println!("{}", version());
+            return Ok(());
+        }
+        Some("--list") => return print_hosts(false),
+        Some("--list-detailed") => return print_hosts(true),
+        Some("--list-json") => return print_hosts_json(),
+        Some("--connect") => std::process::exit(connect_directly(args.get(1))),
+        Some("--add") => std::process::exit(add_directly(&args[1..])),
+        Some("--merge") => std::process::exit(merge_directly(&args[1..])),
+        Some("--generate-completions") => {
+            let shell = args.get(1).map(String::as_str).unwrap_or_default();
+            match completions::completion_script(shell) {
+                Some(script) => {
+                    print!("{}", script);
+                    return Ok(());
+                }
+                None => {
+                    eprintln!(
+                        "Error: unsupported shell '{}' (expected bash, zsh or fish)",
+                        shell
+                    );
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => {}
+    }
+    let print_only = args.iter().any(|arg| arg == "--print");
+
     let mut app = App::new()?;
+    app.print_only = print_only;
+    install_panic_hook(print_only, app.mouse_capture());
 
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
+    // With --print, stdout belongs to the caller's `$(...)`, so the UI is
+    // drawn on stderr and only the chosen alias reaches stdout.
+    let result = if print_only {
+        run_tui(io::stderr(), &mut app)
+    } else {
+        run_tui(io::stdout(), &mut app)
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+    }
+
+    if let Some(host) = app.chosen_host.take() {
+        println!("{}", host);
+    }
+
+    Ok(())
+}
+
+/// `ssh-tui <version>`, followed by the commit it was built from when the
+/// build could ask git.
+fn version() -> String {
+    let version = format!("ssh-tui {}", env!("CARGO_PKG_VERSION"));
+    match option_env!("SSH_TUI_GIT_COMMIT") {
+        Some(commit) => format!("{} ({})", version, commit),
+        None => version,
+    }
+}
+
+fn run_tui<W: io::Write>(mut writer: W, app: &mut App) -> Result<()> {
+    let mouse = app.mouse_capture();
+    setup_terminal(&mut writer, mouse)?;
+    let backend = CrosstermBackend::new(writer);
     let mut terminal = Terminal::new(backend)?;
 
-    let result = run_app(&mut terminal, &mut app);
+    let result = run_app(&mut terminal, app);
+
+    restore_terminal(terminal.backend_mut(), mouse)?;
+    result
+}
 
+/// Enters raw mode and the alternate screen. Mouse capture is only turned
+/// on when `mouse` is set, following the `mouse` config option.
+fn setup_terminal<W: io::Write>(writer: &mut W, mouse: bool) -> io::Result<()> {
+    enable_raw_mode()?;
+    enter_screen(writer, mouse)
+}
+
+/// The escape sequences `setup_terminal` writes once raw mode is on.
+fn enter_screen<W: io::Write>(writer: &mut W, mouse: bool) -> io::Result<()> {
+    execute!(writer, EnterAlternateScreen, EnableBracketedPaste)?;
+    if mouse {
+        execute!(writer, EnableMouseCapture)?;
+    }
+    Ok(())
+}
+
+/// Undoes `setup_terminal`: leaves raw mode and the alternate screen and
+/// shows the cursor again. Shared by the normal exit path, foreground
+/// sessions and the panic hook.
+fn restore_terminal<W: io::Write>(writer: &mut W, mouse: bool) -> io::Result<()> {
     disable_raw_mode()?;
+    if mouse {
+        execute!(writer, DisableMouseCapture)?;
+    }
     execute!(
-        terminal.backend_mut(),
+        writer,
         LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+        DisableBracketedPaste,
+        cursor::Show
+    )
+}
 
-    if let Err(e) = result {
-        eprintln!("Error: {}", e);
+/// Restores the terminal before the default hook prints the panic, so the
+/// message lands on a usable screen. `print_only` picks the stream the UI
+/// was drawn on.
+fn install_panic_hook(print_only: bool, mouse: bool) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = if print_only {
+            restore_terminal(&mut io::stderr(), mouse)
+        } else {
+            restore_terminal(&mut io::stdout(), mouse)
+        };
+        default_hook(info);
+    }));
+}
+
+/// Prints active hosts for scripts and shell completion, without starting
+/// the TUI.
+fn print_hosts(detailed: bool) -> Result<()> {
+    let path = config::Config::ssh_config_path()?;
+    for entry in ssh_config::iter_host_entries(&path) {
+        if let Some(line) = host_list_line(&entry?, detailed) {
+            println!("{}", line);
+        }
     }
+    Ok(())
+}
 
+/// Prints enabled hosts as a JSON array of entries.
+fn print_hosts_json() -> Result<()> {
+    let hosts: Vec<ssh_config::HostEntry> = ssh_config::load_host_entries()?
+        .into_iter()
+        .filter(|entry| !entry.disabled)
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&hosts)?);
     Ok(())
 }
 
+/// Connects to `alias` straight from the command line, skipping the TUI.
+/// Returns the process exit code: the client's own, or 1 when there is nothing
+/// to connect to.
+fn connect_directly(alias: Option<&String>) -> i32 {
+    let Some(alias) = alias else {
+        eprintln!("Error: --connect needs a host alias");
+        return 1;
+    };
+    match run_ssh_for_alias(alias) {
+        Ok(Some(code)) => code,
+        Ok(None) => {
+            eprintln!("Error: no host named '{}' in the SSH config", alias);
+            1
+        }
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            1
+        }
+    }
+}
+
+/// Adds or updates a host from `key=value` arguments without starting the
+/// TUI. Returns the process exit code.
+fn add_directly(args: &[String]) -> i32 {
+    let result = host_from_add_args(args).and_then(|entry| {
+        ssh_config::upsert_host_entry(&entry)?;
+        Ok(entry.host)
+    });
+    match result {
+        Ok(host) => {
+            println!("Saved host '{}'", host);
+            0
+        }
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            1
+        }
+    }
+}
+
+/// Builds the entry for `--add host=web hostname=... user=... port=...
+/// identity=...`. Only `host` is required; empty values are left out.
+fn host_from_add_args(args: &[String]) -> Result<ssh_config::HostEntry> {
+    let mut host = None;
+    let mut directives = Vec::new();
+    for arg in args {
+        let (key, value) = arg
+            .split_once('=')
+            .with_context(|| format!("expected key=value, got '{}'", arg))?;
+        let keyword = match key {
+            "host" => {
+                host = Some(value.to_string());
+                continue;
+            }
+            "hostname" => "HostName",
+            "user" => "User",
+            "port" => "Port",
+            "identity" => "IdentityFile",
+            _ => anyhow::bail!(
+                "unknown field '{}' (expected host, hostname, user, port or identity)",
+                key
+            ),
+        };
+        if !value.is_empty() {
+            directives.push((keyword, value));
+        }
+    }
+    let host = host.context("--add needs host=<alias>")?;
+    let mut entry = ssh_config::HostEntry::new(&host);
+    for (keyword, value) in directives {
+        entry.set(keyword, value);
+    }
+    Ok(entry)
+}
+
+/// Merges the hosts of another config into the user's, for
+/// `--merge <path> [--on-conflict skip|overwrite|rename]`. Without a policy
+/// each collision is asked about on a terminal and skipped otherwise.
+/// Returns the process exit code.
+fn merge_directly(args: &[String]) -> i32 {
+    let result = merge_args(args).and_then(|(source, policy)| {
+        if !Path::new(source).exists() {
+            anyhow::bail!("{} does not exist", source);
+        }
+        let incoming = ssh_config::load_host_entries_from_path(Path::new(source))?;
+        let target = config::Config::ssh_config_path()?;
+        let interactive = io::stdin().is_terminal();
+        let summary = ssh_config::merge_host_entries(&target, &incoming, |entry| match policy {
+            Some(policy) => policy,
+            None if interactive => ask_conflict_policy(&entry.host),
+            None => ConflictPolicy::Skip,
+        })?;
+        Ok(format!("Merged {}: {}", source, summary))
+    });
+    match result {
+        Ok(summary) => {
+            println!("{}", summary);
+            0
+        }
+        Err(err) => {
+            eprintln!("Error: {:#}", err);
+            1
+        }
+    }
+}
+
+/// The source path and the `--on-conflict` policy, if one was given.
+fn merge_args(args: &[String]) -> Result<(&str, Option<ConflictPolicy>)> {
+    let mut source = None;
+    let mut policy = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--on-conflict" {
+            let name = args
+                .next()
+                .context("--on-conflict needs skip, overwrite or rename")?;
+            policy = Some(ConflictPolicy::from_name(name).with_context(|| {
+                format!(
+                    "unknown conflict policy '{}' (expected skip, overwrite or rename)",
+                    name
+                )
+            })?);
+        } else if source.is_none() {
+            source = Some(arg.as_str());
+        } else {
+            anyhow::bail!("unexpected argument '{}'", arg);
+        }
+    }
+    let source = source.context("--merge needs the path of a config to import")?;
+    Ok((source, policy))
+}
+
+/// Asks on stderr what to do with an incoming host whose alias is taken;
+/// anything unrecognised, or a closed stdin, skips it.
+fn ask_conflict_policy(host: &str) -> ConflictPolicy {
+    eprint!(
+        "Host '{}' already exists: [s]kip, [o]verwrite or [r]ename? ",
+        host
+    );
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return ConflictPolicy::Skip;
+    }
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "o" | "overwrite" => ConflictPolicy::Overwrite,
+        "r" | "rename" => ConflictPolicy::Rename,
+        _ => ConflictPolicy::Skip,
+    }
+}
+
+/// Connects the way Enter in the TUI would, so the host's tool, retries,
+/// history and post-connect hook all apply. `None` when the alias is
+/// unknown or commented out.
+fn run_ssh_for_alias(alias: &str) -> Result<Option<i32>> {
+    let mut app = App::new()?;
+    let Some(host) = app.enabled_host(alias).map(|entry| entry.host.clone()) else {
+        return Ok(None);
+    };
+    match app.connect_to_host(&host) {
+        Ok(_) => Ok(Some(0)),
+        Err(err) => match err.downcast_ref::<ssh::SessionFailed>() {
+            Some(failed) => Ok(Some(failed.code.unwrap_or(1))),
+            None => Err(err),
+        },
+    }
+}
+
+/// The `--list` line for an enabled host: the alias, or tab-separated
+/// `alias, hostname, user, port` when `detailed` is set.
+fn host_list_line(entry: &ssh_config::HostEntry, detailed: bool) -> Option<String> {
+    if entry.disabled {
+        None
+    } else if detailed {
+        Some(format!(
+            "{}\t{}\t{}\t{}",
+            entry.host,
+            entry.hostname(),
+            entry.user(),
+            entry.port()
+        ))
+    } else {
+        Some(entry.host.clone())
+    }
+}
+
 fn run_app<B: ratatui::backend::Backend + std::io::Write>(
     terminal: &mut Terminal<B>,
     app: &mut App,
 ) -> Result<()> {
     loop {
+        app.expire_status(Instant::now());
+        app.poll_pending();
         terminal.draw(|f| ui::draw(f, app))?;
 
-        if let Event::Key(key) = event::read()? {
+        // Wake up now and then so expired status messages get cleared and
+        // the spinner keeps turning.
+        if !event::poll(TICK_RATE)? {
+            app.advance_spinner();
+            continue;
+        }
+        let event = event::read()?;
+        if let Event::Paste(text) = &event {
+            app.handle_form_paste(text);
+        }
+        if let Event::Key(key) = event {
             if key.kind == KeyEventKind::Press {
-                if app.is_form_active() {
-                    handle_form_input(app, key.code, key.modifiers)?;
-                } else if handle_normal_input(terminal, app, key.code)? {
+                let quit = match app.state {
+                    AppState::Edit | AppState::New => {
+                        handle_form_input(app, key.code, key.modifiers)?;
+                        false
+                    }
+                    AppState::Palette => handle_palette_input(terminal, app, key.code)?,
+                    AppState::Confirm => handle_confirm_input(terminal, app, key.code)?,
+                    AppState::Issues => {
+                        app.close_issues();
+                        false
+                    }
+                    AppState::History => {
+                        app.close_history();
+                        false
+                    }
+                    AppState::Recent => handle_recent_input(terminal, app, key.code)?,
+                    AppState::QuickConnect => handle_quick_connect_input(terminal, app, key.code)?,
+                    AppState::ConnectAs => handle_connect_as_input(terminal, app, key.code)?,
+                    AppState::ConnectArgs => handle_connect_args_input(terminal, app, key.code)?,
+                    AppState::ComparePicker => {
+                        handle_compare_picker_input(app, key.code);
+                        false
+                    }
+                    AppState::Compare => {
+                        app.close_comparison();
+                        false
+                    }
+                    AppState::ProfilePicker => {
+                        handle_profile_picker_input(app, key.code);
+                        false
+                    }
+                    AppState::SaveAs => {
+                        handle_save_as_input(app, key.code);
+                        false
+                    }
+                    AppState::KeyPicker => {
+                        handle_key_picker_input(app, key.code);
+                        false
+                    }
+                    AppState::Search => {
+                        handle_search_input(app, key.code, key.modifiers);
+                        false
+                    }
+                    AppState::RawEdit | AppState::Import => {
+                        handle_raw_edit_input(app, key.code, key.modifiers);
+                        false
+                    }
+                    AppState::Normal => {
+                        handle_normal_input(terminal, app, key.code, key.modifiers)?
+                    }
+                };
+                if quit {
                     return Ok(());
                 }
             }
@@ -65,48 +459,466 @@ fn handle_normal_input<B: ratatui::backend::Backend + std::io::Write>(
     terminal: &mut Terminal<B>,
     app: &mut App,
     code: KeyCode,
+    modifiers: KeyModifiers,
 ) -> Result<bool> {
-    match code {
-        KeyCode::Char('q') | KeyCode::Esc => {
-            return Ok(true);
+    if app.jump_pending {
+        match code {
+            KeyCode::Char(c) => {
+                app.jump_to_prefix(c);
+            }
+            _ => {
+                app.jump_pending = false;
+                app.status = None;
+            }
+        }
+        return Ok(false);
+    }
+    let motion_key = match code {
+        KeyCode::Char(c) if !modifiers.contains(KeyModifiers::CONTROL) => Some(c),
+        KeyCode::Down if !modifiers.contains(KeyModifiers::CONTROL) => Some('j'),
+        KeyCode::Up if !modifiers.contains(KeyModifiers::CONTROL) => Some('k'),
+        // Anything else, Esc included, only abandons a pending sequence.
+        _ if app.motion.is_pending() => {
+            app.motion.reset();
+            return Ok(false);
+        }
+        _ => None,
+    };
+    if let Some(key) = motion_key {
+        match app.motion.feed(key) {
+            Step::Pending | Step::Cancelled => return Ok(false),
+            Step::Move(motion) => {
+                app.apply_motion(motion);
+                return Ok(false);
+            }
+            Step::Other => {}
+        }
+    }
+    let action = match code {
+        KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+        KeyCode::Down if modifiers.contains(KeyModifiers::CONTROL) => {
+            app.move_selected(MoveDirection::Down);
+            return Ok(false);
+        }
+        KeyCode::Up if modifiers.contains(KeyModifiers::CONTROL) => {
+            app.move_selected(MoveDirection::Up);
+            return Ok(false);
         }
-        KeyCode::Down | KeyCode::Char('j') => app.next(),
-        KeyCode::Up | KeyCode::Char('k') => app.previous(),
-        KeyCode::Char('i') => {
-            app.enter_edit_mode();
+        KeyCode::Char('J') => {
+            app.move_selected(MoveDirection::Down);
+            return Ok(false);
         }
-        KeyCode::Char('n') => {
-            app.enter_new_mode();
+        KeyCode::Char('K') => {
+            app.move_selected(MoveDirection::Up);
+            return Ok(false);
         }
+        KeyCode::Char(':') => {
+            app.open_quick_connect();
+            return Ok(false);
+        }
+        KeyCode::Char('\'') => {
+            app.start_jump();
+            return Ok(false);
+        }
+        KeyCode::Char('/') => {
+            app.open_search();
+            return Ok(false);
+        }
+        KeyCode::Char('p') if modifiers.contains(KeyModifiers::CONTROL) => {
+            app.open_palette();
+            return Ok(false);
+        }
+        KeyCode::Char('y') if modifiers.contains(KeyModifiers::CONTROL) => Action::CopyHostname,
+        KeyCode::Char('W') => {
+            app.open_issues();
+            return Ok(false);
+        }
+        KeyCode::Char('i') => Action::Edit,
+        KeyCode::Char('R') => Action::EditRaw,
+        KeyCode::Char('n') => Action::New,
+        KeyCode::Char('A') => Action::Import,
+        KeyCode::Char('m') => Action::ConnectMosh,
+        KeyCode::Char('s') => Action::Sftp,
+        KeyCode::Char('I') => Action::CopyId,
+        KeyCode::Char('f') => Action::Tunnel,
+        KeyCode::Char('r') => Action::Recent,
+        KeyCode::Char('h') => Action::History,
+        KeyCode::Char(' ') => {
+            app.toggle_marked();
+            app.next();
+            return Ok(false);
+        }
+        KeyCode::Char('v') => {
+            app.toggle_marked();
+            return Ok(false);
+        }
+        KeyCode::Char('V') => {
+            app.toggle_mark_all();
+            return Ok(false);
+        }
+        KeyCode::Char('D') => Action::Delete,
+        KeyCode::Char('F') => Action::FixPermissions,
+        KeyCode::Char('S') => Action::SaveAs,
+        KeyCode::Char('P') => Action::SwitchProfile,
+        KeyCode::Char('u') => Action::ConnectAs,
+        KeyCode::Char('e') => Action::ConnectWithArgs,
+        KeyCode::Char('c') => Action::Compare,
+        KeyCode::Char('t') => Action::TestConnection,
+        KeyCode::Char('w') => Action::OpenInTmux,
+        KeyCode::Char('d') => Action::Resolve,
+        KeyCode::Char('#') => Action::ToggleDisabled,
+        KeyCode::Char('H') => Action::ToggleShowDisabled,
+        KeyCode::Char('M') => Action::ToggleMask,
+        KeyCode::Char('o') => Action::CycleSort,
+        KeyCode::Char('O') => Action::ToggleSortDirection,
+        KeyCode::Enter => Action::Connect,
+        _ => return Ok(false),
+    };
+    perform_action(terminal, app, action)
+}
+
+fn handle_palette_input<B: ratatui::backend::Backend + std::io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    code: KeyCode,
+) -> Result<bool> {
+    match code {
+        KeyCode::Esc => app.close_palette(),
         KeyCode::Enter => {
-            if let Some(host) = app.selected_host_name() {
-                let host = host.to_string();
-                disable_raw_mode()?;
-                execute!(
-                    terminal.backend_mut(),
-                    LeaveAlternateScreen,
-                    DisableMouseCapture
-                )?;
-                terminal.show_cursor()?;
-
-                let result = app.connect_to_host(&host);
-
-                enable_raw_mode()?;
-                execute!(
-                    terminal.backend_mut(),
-                    EnterAlternateScreen,
-                    EnableMouseCapture
-                )?;
-                terminal.clear()?;
+            if let Some(action) = app.take_palette_action() {
+                return perform_action(terminal, app, action);
+            }
+        }
+        KeyCode::Down => app.palette.next(),
+        KeyCode::Up => app.palette.previous(),
+        KeyCode::Backspace => app.palette_backspace(),
+        KeyCode::Char(c) => app.palette_input(c),
+        _ => {}
+    }
+    Ok(false)
+}
 
+/// Runs an action from either a direct keybinding or the palette. Returns
+/// `true` when the application should exit.
+fn perform_action<B: ratatui::backend::Backend + std::io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    action: Action,
+) -> Result<bool> {
+    match action {
+        Action::Quit => return Ok(true),
+        Action::Edit => app.enter_edit_mode(),
+        Action::EditRaw => app.enter_raw_edit_mode(),
+        Action::New => app.enter_new_mode(),
+        Action::Duplicate => app.enter_duplicate_mode(),
+        Action::Import => app.open_import(),
+        Action::Delete => app.request_delete(),
+        Action::ToggleDisabled => app.toggle_selected_disabled(),
+        Action::ToggleShowDisabled => app.toggle_show_disabled(),
+        Action::CycleSort => app.cycle_sort_key(),
+        Action::ToggleSortDirection => app.toggle_sort_direction(),
+        Action::ToggleMask => app.toggle_mask_sensitive(),
+        Action::Reload => app.reload(),
+        Action::SaveAs => app.open_save_as(),
+        Action::SwitchProfile => app.open_profile_picker(),
+        Action::ConnectAs => app.open_connect_as(),
+        Action::ConnectWithArgs => app.open_connect_args(),
+        Action::Recent => app.open_recent(),
+        Action::History => app.open_history(),
+        Action::QuickConnect => app.open_quick_connect(),
+        Action::OpenInTmux => app.open_marked_in_tmux(),
+        Action::TestConnection => app.test_selected_connection(),
+        Action::Resolve => app.resolve_selected(),
+        Action::Compare => app.open_compare_picker(),
+        Action::CopyHostname => app.copy_selected_hostname(),
+        Action::FixPermissions => app.fix_permissions(),
+        Action::Sftp => {
+            if let Some(host) = app.connectable_host_name() {
+                let mouse = app.mouse_capture();
+                let result = run_in_foreground(terminal, mouse, || app.open_sftp(&host))?;
+                app.set_status(result);
+            }
+        }
+        Action::CopyId => {
+            if let Some(host) = app.connectable_host_name() {
+                let mouse = app.mouse_capture();
+                let result = run_in_foreground(terminal, mouse, || app.copy_public_key(&host))?;
+                app.set_status(result);
+            }
+        }
+        Action::Tunnel => {
+            if let Some(host) = app.connectable_host_name() {
+                let mouse = app.mouse_capture();
+                let result = run_in_foreground(terminal, mouse, || {
+                    eprintln!(
+                        "Forwarding ports for {} (ssh -N, no shell). Press Ctrl+C to close the tunnel and return.",
+                        host
+                    );
+                    surviving_interrupt(|| app.open_tunnel(&host))
+                })?;
                 app.set_status(result);
             }
         }
+        Action::Connect | Action::ConnectMosh if app.print_only => return Ok(app.choose_selected()),
+        Action::Connect | Action::ConnectMosh => match app.connect_confirmation_prompt() {
+            Some(prompt) => app.request_confirmation(prompt, action),
+            None => return connect_selected(terminal, app, action),
+        },
+    }
+    Ok(false)
+}
+
+fn handle_recent_input<B: ratatui::backend::Backend + std::io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    code: KeyCode,
+) -> Result<bool> {
+    match code {
+        KeyCode::Esc => app.close_recent(),
+        KeyCode::Enter => {
+            if let Some(_host) = app.take_recent_host() {
+                return perform_action(terminal, app, Action::Connect);
+            }
+        }
+        KeyCode::Down => app.recent.next(),
+        KeyCode::Up => app.recent.previous(),
+        KeyCode::Backspace => app.recent_backspace(),
+        KeyCode::Char(c) => app.recent_input(c),
         _ => {}
     }
     Ok(false)
 }
 
+fn handle_quick_connect_input<B: ratatui::backend::Backend + std::io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    code: KeyCode,
+) -> Result<bool> {
+    match code {
+        KeyCode::Esc => app.close_quick_connect(),
+        KeyCode::Enter => {
+            if let Some(_host) = app.take_quick_connect_host() {
+                return perform_action(terminal, app, Action::Connect);
+            }
+        }
+        KeyCode::Tab => app.complete_quick_connect(),
+        KeyCode::Backspace => app.quick_connect_backspace(),
+        KeyCode::Char(c) => app.quick_connect_input(c),
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn handle_connect_as_input<B: ratatui::backend::Backend + std::io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    code: KeyCode,
+) -> Result<bool> {
+    match code {
+        KeyCode::Esc => app.close_connect_as(),
+        KeyCode::Enter if app.accept_connect_as() => {
+            return perform_action(terminal, app, Action::Connect);
+        }
+        KeyCode::Backspace => app.connect_as_backspace(),
+        KeyCode::Char(c) => app.connect_as_input(c),
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn handle_connect_args_input<B: ratatui::backend::Backend + std::io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    code: KeyCode,
+) -> Result<bool> {
+    match code {
+        KeyCode::Esc => app.close_connect_args(),
+        KeyCode::Enter if app.accept_connect_args() => {
+            return perform_action(terminal, app, Action::Connect);
+        }
+        KeyCode::Up => app.cycle_args_history(true),
+        KeyCode::Down => app.cycle_args_history(false),
+        KeyCode::Backspace => app.connect_args_backspace(),
+        KeyCode::Char(c) => app.connect_args_input(c),
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn handle_compare_picker_input(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Esc => app.close_compare_picker(),
+        KeyCode::Enter => app.compare_with_picked(),
+        KeyCode::Down => app.compare_picker.next(),
+        KeyCode::Up => app.compare_picker.previous(),
+        KeyCode::Backspace => app.compare_picker_backspace(),
+        KeyCode::Char(c) => app.compare_picker_input(c),
+        _ => {}
+    }
+}
+
+fn handle_profile_picker_input(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Esc => app.close_profile_picker(),
+        KeyCode::Enter => app.switch_to_picked_profile(),
+        KeyCode::Down => app.profile_picker.next(),
+        KeyCode::Up => app.profile_picker.previous(),
+        KeyCode::Backspace => app.profile_picker_backspace(),
+        KeyCode::Char(c) => app.profile_picker_input(c),
+        _ => {}
+    }
+}
+
+fn handle_save_as_input(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Esc => app.close_save_as(),
+        KeyCode::Enter => app.save_as(),
+        KeyCode::Backspace => app.save_as_backspace(),
+        KeyCode::Char(c) => app.save_as_input(c),
+        _ => {}
+    }
+}
+
+fn handle_key_picker_input(app: &mut App, code: KeyCode) {
+    match code {
+        KeyCode::Esc => app.close_key_picker(),
+        KeyCode::Enter => app.pick_key(),
+        KeyCode::Down => app.key_picker.next(),
+        KeyCode::Up => app.key_picker.previous(),
+        KeyCode::Backspace => app.key_picker_backspace(),
+        KeyCode::Char(c) => app.key_picker_input(c),
+        _ => {}
+    }
+}
+
+fn handle_search_input(app: &mut App, code: KeyCode, modifiers: KeyModifiers) {
+    match code {
+        KeyCode::Esc => app.close_search(false),
+        KeyCode::Enter => app.close_search(true),
+        KeyCode::Down => app.next(),
+        KeyCode::Up => app.previous(),
+        KeyCode::Backspace => app.search_backspace(),
+        KeyCode::Char('r') if modifiers.contains(KeyModifiers::CONTROL) => {
+            app.toggle_match_mode();
+        }
+        KeyCode::Char(c) if !modifiers.contains(KeyModifiers::CONTROL) => app.search_input(c),
+        _ => {}
+    }
+}
+
+/// Keys for the raw block editor, which also takes the text of an import.
+fn handle_raw_edit_input(app: &mut App, code: KeyCode, modifiers: KeyModifiers) {
+    let importing = app.state == AppState::Import;
+    let editor = &mut app.raw_editor;
+    match code {
+        KeyCode::Esc if importing => app.cancel_import(),
+        KeyCode::Esc => app.cancel_raw_edit(),
+        KeyCode::Char('s') if modifiers.contains(KeyModifiers::CONTROL) && importing => {
+            app.preview_import()
+        }
+        KeyCode::Char('s') if modifiers.contains(KeyModifiers::CONTROL) => app.save_raw_edit(),
+        KeyCode::Char(_) if modifiers.contains(KeyModifiers::CONTROL) => {}
+        KeyCode::Char(c) => editor.insert(c),
+        KeyCode::Enter => editor.newline(),
+        KeyCode::Backspace => editor.backspace(),
+        KeyCode::Delete => editor.delete(),
+        KeyCode::Left => editor.left(),
+        KeyCode::Right => editor.right(),
+        KeyCode::Up => editor.up(),
+        KeyCode::Down => editor.down(),
+        KeyCode::Home => editor.home(),
+        KeyCode::End => editor.end(),
+        _ => {}
+    }
+}
+
+fn handle_confirm_input<B: ratatui::backend::Backend + std::io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    code: KeyCode,
+) -> Result<bool> {
+    match code {
+        KeyCode::Char('y') | KeyCode::Char('Y') => match app.take_confirmation() {
+            Some(action @ (Action::Connect | Action::ConnectMosh)) => {
+                return connect_selected(terminal, app, action)
+            }
+            Some(Action::Delete) => app.delete_selected(),
+            Some(Action::Import) => app.import_pending(),
+            Some(action) => return perform_action(terminal, app, action),
+            None => {}
+        },
+        _ => app.cancel_confirmation(),
+    }
+    Ok(false)
+}
+
+/// Runs the session for the selected host. Returns `true` when the app
+/// should exit now that it is over.
+fn connect_selected<B: ratatui::backend::Backend + std::io::Write>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    action: Action,
+) -> Result<bool> {
+    let Some(host) = app.connectable_host_name() else {
+        return Ok(false);
+    };
+    let mouse = app.mouse_capture();
+    let warning = app.agent_key_warning(&host);
+    let result = run_in_foreground(terminal, mouse, || {
+        if let Some(warning) = &warning {
+            eprintln!("Note: {}.", warning);
+        }
+        match action {
+            Action::ConnectMosh => app.connect_to_host_with(&host, ConnectTool::Mosh),
+            _ => app.connect_to_host(&host),
+        }
+    })?;
+    let exit = app.exit_after_session(&result);
+    let result = match warning {
+        None => result,
+        Some(warning) => match result {
+            Ok(msg) => Ok(format!("{}; warning: {}", msg, warning)),
+            Err(err) => Err(anyhow::anyhow!("{}; warning: {}", err, warning)),
+        },
+    };
+    app.set_status(result);
+    Ok(exit)
+}
+
+/// Leaves the TUI while `run` owns the terminal, then restores it.
+fn run_in_foreground<B, T>(
+    terminal: &mut Terminal<B>,
+    mouse: bool,
+    run: impl FnOnce() -> T,
+) -> Result<T>
+where
+    B: ratatui::backend::Backend + std::io::Write,
+{
+    restore_terminal(terminal.backend_mut(), mouse)?;
+
+    let result = run();
+
+    setup_terminal(terminal.backend_mut(), mouse)?;
+    terminal.clear()?;
+
+    Ok(result)
+}
+
+/// Runs `run` with SIGINT caught instead of fatal, so the Ctrl+C that ends
+/// a foreground ssh does not take the TUI down with it.
+fn surviving_interrupt<T>(run: impl FnOnce() -> T) -> T {
+    let handler = signal_hook::flag::register(
+        signal_hook::consts::SIGINT,
+        Arc::new(AtomicBool::new(false)),
+    )
+    .ok();
+    let result = run();
+    if let Some(handler) = handler {
+        signal_hook::low_level::unregister(handler);
+    }
+    result
+}
+
 fn handle_form_input(app: &mut App, code: KeyCode, modifiers: KeyModifiers) -> Result<()> {
     match code {
         KeyCode::Esc => {
@@ -119,7 +931,7 @@ fn handle_form_input(app: &mut App, code: KeyCode, modifiers: KeyModifiers) -> R
             if modifiers.contains(KeyModifiers::SHIFT) {
                 app.focus_previous_field();
             } else {
-                app.focus_next_field();
+                app.complete_or_focus_next();
             }
         }
         KeyCode::BackTab => {
@@ -130,6 +942,12 @@ fn handle_form_input(app: &mut App, code: KeyCode, modifiers: KeyModifiers) -> R
         KeyCode::Backspace | KeyCode::Delete => {
             app.handle_form_backspace();
         }
+        KeyCode::Char('o') if modifiers.contains(KeyModifiers::CONTROL) => {
+            app.open_key_picker();
+        }
+        KeyCode::Char(' ') if modifiers.contains(KeyModifiers::CONTROL) => {
+            app.cycle_identity_suggestion();
+        }
         KeyCode::Char(c) => {
             if modifiers.contains(KeyModifiers::CONTROL) {
                 return Ok(());
@@ -140,3 +958,86 @@ fn handle_form_input(app: &mut App, code: KeyCode, modifiers: KeyModifiers) -> R
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ssh_config::HostEntry;
+
+    #[test]
+    fn add_args_map_to_directives() {
+        let args: Vec<String> = ["port=2222", "host=web", "user=", "identity=~/.ssh/web"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let entry = host_from_add_args(&args).unwrap();
+        assert_eq!(entry.host, "web");
+        assert_eq!(entry.port(), "2222");
+        assert_eq!(entry.identity_file(), "~/.ssh/web");
+        assert_eq!(entry.directives.len(), 2);
+
+        assert!(host_from_add_args(&["user=me".to_string()]).is_err());
+        assert!(host_from_add_args(&["host".to_string()]).is_err());
+        assert!(host_from_add_args(&["host=web".to_string(), "proxy=x".to_string()]).is_err());
+    }
+
+    #[test]
+    fn restore_terminal_leaves_alternate_screen_and_shows_cursor() {
+        let mut out = Vec::new();
+        restore_terminal(&mut out, true).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\x1b[?1049l"), "{:?}", out);
+        assert!(out.contains("\x1b[?25h"), "{:?}", out);
+    }
+
+    #[test]
+    fn mouse_capture_follows_the_config() {
+        // Mouse capture starts with `?1000h` and ends with `?1000l`.
+        let sequences = |mouse| {
+            let (mut setup, mut restore) = (Vec::new(), Vec::new());
+            enter_screen(&mut setup, mouse).unwrap();
+            restore_terminal(&mut restore, mouse).unwrap();
+            (
+                String::from_utf8(setup).unwrap(),
+                String::from_utf8(restore).unwrap(),
+            )
+        };
+
+        let (setup, restore) = sequences(true);
+        assert!(setup.contains("\x1b[?1049h"), "{:?}", setup);
+        assert!(setup.contains("\x1b[?1000h"), "{:?}", setup);
+        assert!(restore.contains("\x1b[?1000l"), "{:?}", restore);
+
+        let (setup, restore) = sequences(false);
+        assert!(setup.contains("\x1b[?1049h"), "{:?}", setup);
+        assert!(!setup.contains("\x1b[?1000"), "{:?}", setup);
+        assert!(!restore.contains("\x1b[?1000"), "{:?}", restore);
+    }
+
+    #[test]
+    fn host_list_skips_disabled_hosts() {
+        let hosts = [
+            HostEntry::new("web")
+                .with("HostName", "web.example.com")
+                .with("User", "deploy")
+                .with("Port", "2222"),
+            HostEntry {
+                disabled: true,
+                ..HostEntry::new("old")
+            },
+            HostEntry::new("db").with("HostName", "10.0.0.5"),
+        ];
+
+        let lines = |detailed| -> Vec<String> {
+            hosts
+                .iter()
+                .filter_map(|entry| host_list_line(entry, detailed))
+                .collect()
+        };
+        assert_eq!(lines(false), vec!["web", "db"]);
+        assert_eq!(
+            lines(true),
+            vec!["web\tweb.example.com\tdeploy\t2222", "db\t10.0.0.5\t\t"]
+        );
+    }
+}
@@ -0,0 +1,121 @@
+/// A vim-style selection move, resolved from one or more keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    Down(usize),
+    Up(usize),
+    Top,
+    Bottom,
+    /// The host at this 1-based position in the list.
+    Position(usize),
+}
+
+/// What a key did to the pending sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Part of a sequence (a count digit or the first `g`); wait for more.
+    Pending,
+    /// A sequence was in progress and this key abandoned it.
+    Cancelled,
+    Move(Motion),
+    /// Not a motion key and nothing was pending: handle it as usual.
+    Other,
+}
+
+/// Collects numeric prefixes and `g` between keys in the host list, so
+/// `gg`, `G`, `5j` and `3G` work alongside the single-key bindings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MotionState {
+    count: Option<usize>,
+    g_pending: bool,
+}
+
+impl MotionState {
+    pub fn is_pending(&self) -> bool {
+        self.count.is_some() || self.g_pending
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Feeds one key. `0` only counts after another digit, and a count
+    /// before a key that isn't a motion is dropped.
+    pub fn feed(&mut self, key: char) -> Step {
+        if let Some(digit) = key.to_digit(10) {
+            if !self.g_pending && (digit != 0 || self.count.is_some()) {
+                let count = self.count.unwrap_or(0);
+                self.count = Some(count.saturating_mul(10).saturating_add(digit as usize));
+                return Step::Pending;
+            }
+        }
+        let count = self.count;
+        let g_pending = self.g_pending;
+        self.reset();
+        match key {
+            'g' if g_pending => Step::Move(count.map_or(Motion::Top, Motion::Position)),
+            'g' => {
+                self.count = count;
+                self.g_pending = true;
+                Step::Pending
+            }
+            _ if g_pending => Step::Cancelled,
+            'G' => Step::Move(count.map_or(Motion::Bottom, Motion::Position)),
+            'j' => Step::Move(Motion::Down(count.unwrap_or(1))),
+            'k' => Step::Move(Motion::Up(count.unwrap_or(1))),
+            _ if count.is_some() => Step::Cancelled,
+            _ => Step::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(state: &mut MotionState, keys: &str) -> Vec<Step> {
+        keys.chars().map(|key| state.feed(key)).collect()
+    }
+
+    #[test]
+    fn resolves_counts_and_g_sequences() {
+        let mut state = MotionState::default();
+        assert_eq!(
+            feed_all(&mut state, "gg"),
+            [Step::Pending, Step::Move(Motion::Top)]
+        );
+        assert_eq!(state.feed('G'), Step::Move(Motion::Bottom));
+        assert_eq!(
+            feed_all(&mut state, "12j"),
+            [Step::Pending, Step::Pending, Step::Move(Motion::Down(12))]
+        );
+        assert_eq!(state.feed('k'), Step::Move(Motion::Up(1)));
+        assert_eq!(
+            feed_all(&mut state, "3G"),
+            [Step::Pending, Step::Move(Motion::Position(3))]
+        );
+        assert_eq!(
+            feed_all(&mut state, "10gg"),
+            [
+                Step::Pending,
+                Step::Pending,
+                Step::Pending,
+                Step::Move(Motion::Position(10))
+            ]
+        );
+        assert!(!state.is_pending());
+    }
+
+    #[test]
+    fn other_keys_pass_through_or_cancel() {
+        let mut state = MotionState::default();
+        assert_eq!(state.feed('i'), Step::Other);
+        assert_eq!(state.feed('0'), Step::Other);
+
+        assert_eq!(feed_all(&mut state, "gx"), [Step::Pending, Step::Cancelled]);
+        assert_eq!(feed_all(&mut state, "4i"), [Step::Pending, Step::Cancelled]);
+        assert_eq!(state.feed('g'), Step::Pending);
+        state.reset();
+        assert!(!state.is_pending());
+        assert_eq!(state.feed('i'), Step::Other);
+    }
+}
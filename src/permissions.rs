@@ -0,0 +1,58 @@
+use anyhow::{Context, Result};
+use std::{fs, os::unix::fs::PermissionsExt, path::Path};
+
+/// The mode ssh expects of its config and private keys.
+pub const PRIVATE_MODE: u32 = 0o600;
+
+/// Whether only the owner can read or write a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionStatus {
+    Private,
+    /// Group or others have some access; carries the permission bits.
+    TooOpen(u32),
+    /// The file is missing or its metadata can't be read.
+    Unknown,
+}
+
+/// Reads the permission bits of `path`. Anything beyond the owner's bits
+/// is too open, which ssh warns about for configs and refuses for keys.
+pub fn check_permissions(path: &Path) -> PermissionStatus {
+    match fs::metadata(path) {
+        Ok(metadata) => {
+            let mode = metadata.permissions().mode() & 0o777;
+            if mode & 0o077 == 0 {
+                PermissionStatus::Private
+            } else {
+                PermissionStatus::TooOpen(mode)
+            }
+        }
+        Err(_) => PermissionStatus::Unknown,
+    }
+}
+
+/// Sets `path` to mode 0600.
+pub fn restrict_permissions(path: &Path) -> Result<()> {
+    fs::set_permissions(path, fs::Permissions::from_mode(PRIVATE_MODE))
+        .with_context(|| format!("Failed to chmod {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_group_and_world_readable_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config");
+        fs::write(&path, "Host a\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        assert_eq!(check_permissions(&path), PermissionStatus::TooOpen(0o644));
+        restrict_permissions(&path).unwrap();
+        assert_eq!(check_permissions(&path), PermissionStatus::Private);
+        assert_eq!(
+            check_permissions(&dir.path().join("missing")),
+            PermissionStatus::Unknown
+        );
+    }
+}
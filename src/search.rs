@@ -0,0 +1,224 @@
+use regex::Regex;
+
+/// Returns true when every character of `query` appears in `text` in order,
+/// ignoring case. An empty query matches everything.
+pub fn fuzzy_match(query: &str, text: &str) -> bool {
+    let mut haystack = text.chars().flat_map(char::to_lowercase);
+    query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|needle| haystack.any(|c| c == needle))
+}
+
+/// Splits `tag:name` terms off a search query, returning the tags and the
+/// rest of the query. Without tags the query is returned untouched.
+pub fn split_tag_filters(query: &str) -> (Vec<String>, String) {
+    let mut tags = Vec::new();
+    let mut rest = Vec::new();
+    for word in query.split_whitespace() {
+        match word.strip_prefix("tag:") {
+            Some(tag) if !tag.is_empty() => tags.push(tag.to_string()),
+            _ => rest.push(word),
+        }
+    }
+    if tags.is_empty() {
+        return (tags, query.to_string());
+    }
+    (tags, rest.join(" "))
+}
+
+/// How the host search interprets its query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    #[default]
+    Fuzzy,
+    Regex,
+}
+
+impl MatchMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            MatchMode::Fuzzy => MatchMode::Regex,
+            MatchMode::Regex => MatchMode::Fuzzy,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MatchMode::Fuzzy => "fuzzy",
+            MatchMode::Regex => "regex",
+        }
+    }
+}
+
+/// A compiled search query.
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Fuzzy(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    /// Compiles `query` for `mode`; only regex queries can fail.
+    pub fn new(query: &str, mode: MatchMode) -> Result<Self, regex::Error> {
+        match mode {
+            MatchMode::Fuzzy => Ok(Matcher::Fuzzy(query.to_string())),
+            MatchMode::Regex => Regex::new(query).map(Matcher::Regex),
+        }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Fuzzy(query) => fuzzy_match(query, text),
+            Matcher::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+/// Query state for a list narrowed down as the user types. The list keeps
+/// indices into the caller's items so the same widget can back any popup.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilteredList {
+    pub query: String,
+    pub selected: usize,
+    matches: Vec<usize>,
+}
+
+impl FilteredList {
+    pub fn new<'a, I>(labels: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut list = Self::default();
+        list.refilter(labels);
+        list
+    }
+
+    pub fn refilter<'a, I>(&mut self, labels: I)
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        self.refilter_any(labels.into_iter().map(std::iter::once));
+    }
+
+    /// Like [`refilter`](Self::refilter) for items known by several names;
+    /// an item matches when any of its names does.
+    pub fn refilter_any<'a, I, N>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = N>,
+        N: IntoIterator<Item = &'a str>,
+    {
+        self.matches = items
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, names)| {
+                let mut names = names.into_iter();
+                names
+                    .any(|name| fuzzy_match(&self.query, name))
+                    .then_some(index)
+            })
+            .collect();
+        if self.selected >= self.matches.len() {
+            self.selected = 0;
+        }
+    }
+
+    pub fn matches(&self) -> &[usize] {
+        &self.matches
+    }
+
+    pub fn selected_index(&self) -> Option<usize> {
+        self.matches.get(self.selected).copied()
+    }
+
+    pub fn next(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = (self.selected + 1) % self.matches.len();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if !self.matches.is_empty() {
+            self.selected = self
+                .selected
+                .checked_sub(1)
+                .unwrap_or(self.matches.len() - 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_match_is_ordered_subsequence() {
+        assert!(fuzzy_match("cnt", "Connect"));
+        assert!(fuzzy_match("", "anything"));
+        assert!(!fuzzy_match("tc", "Connect"));
+    }
+
+    #[test]
+    fn tag_filters_are_split_from_the_query() {
+        assert_eq!(
+            split_tag_filters("tag:prod web tag:db"),
+            (
+                vec!["prod".to_string(), "db".to_string()],
+                "web".to_string()
+            )
+        );
+        assert_eq!(
+            split_tag_filters("tag: web"),
+            (Vec::new(), "tag: web".to_string())
+        );
+        assert_eq!(split_tag_filters("a  b"), (Vec::new(), "a  b".to_string()));
+    }
+
+    #[test]
+    fn filtered_list_tracks_matching_indices() {
+        let labels = ["Connect", "Edit", "New host"];
+        let mut list = FilteredList::new(labels);
+        assert_eq!(list.matches(), &[0, 1, 2]);
+
+        list.query = "e".to_string();
+        list.selected = 2;
+        list.refilter(labels);
+        assert_eq!(list.matches(), &[0, 1, 2]);
+
+        list.query = "ed".to_string();
+        list.refilter(labels);
+        assert_eq!(list.matches(), &[1]);
+        assert_eq!(list.selected_index(), Some(1));
+    }
+
+    #[test]
+    fn filtered_list_navigation_wraps() {
+        let mut list = FilteredList::new(["a", "b", "c"]);
+        list.previous();
+        assert_eq!(list.selected_index(), Some(2));
+        list.next();
+        assert_eq!(list.selected_index(), Some(0));
+    }
+
+    #[test]
+    fn regex_matcher_matches_several_hosts() {
+        let matcher = Matcher::new(r"^web-\d+ ", MatchMode::Regex).unwrap();
+        let texts = [
+            "web-1 10.0.0.1",
+            "web-22 10.0.0.2",
+            "web-db 10.0.0.3",
+            "db web-1",
+        ];
+        let matched: Vec<&str> = texts
+            .into_iter()
+            .filter(|text| matcher.is_match(text))
+            .collect();
+        assert_eq!(matched, vec!["web-1 10.0.0.1", "web-22 10.0.0.2"]);
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        assert!(Matcher::new("web-(", MatchMode::Regex).is_err());
+        assert!(Matcher::new("web-(", MatchMode::Fuzzy).is_ok());
+    }
+}
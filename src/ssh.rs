@@ -1,41 +1,140 @@
+use crate::{hook::shell_quote, log, ssh_config::HostEntry};
 use anyhow::{Context, Result};
 use std::{
-    process::{Command, ExitStatus},
-    time::Duration,
+    fmt, io,
+    net::{IpAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Output},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
-pub trait CommandExecutor {
-    fn execute(&self, ssh_binary: &str, host: &str) -> Result<ExitStatus>;
+/// ssh's exit code when it could not connect or authenticate, as opposed
+/// to the remote command's own status.
+const CONNECTION_FAILED: i32 = 255;
+
+/// Wait before the first connection retry.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Runs external programs. `Send + Sync` so a connection can be cloned into
+/// a background thread for checks that shouldn't block the UI.
+pub trait CommandExecutor: Send + Sync {
+    fn execute(&self, program: &str, args: &[String]) -> Result<ExitStatus>;
+
+    /// Runs the command with captured stdout/stderr instead of handing it
+    /// the terminal.
+    fn output(&self, program: &str, args: &[String]) -> Result<Output>;
 }
 
 pub struct RealCommandExecutor;
 
 impl CommandExecutor for RealCommandExecutor {
-    fn execute(&self, ssh_binary: &str, host: &str) -> Result<ExitStatus> {
-        let status = Command::new(ssh_binary)
-            .arg(host)
+    fn execute(&self, program: &str, args: &[String]) -> Result<ExitStatus> {
+        let status = Command::new(program)
+            .args(args)
             .status()
-            .context("Failed to execute SSH command")?;
+            .with_context(|| format!("Failed to execute {}", program))?;
         Ok(status)
     }
+
+    fn output(&self, program: &str, args: &[String]) -> Result<Output> {
+        Command::new(program)
+            .args(args)
+            .output()
+            .with_context(|| format!("Failed to execute {}", program))
+    }
+}
+
+/// Outcome of a non-interactive connection check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionTest {
+    pub passed: bool,
+    /// ssh's explanation on failure, e.g. `Permission denied (publickey).`
+    pub detail: String,
+}
+
+impl ConnectionTest {
+    pub fn summary(&self, host: &str) -> String {
+        match (self.passed, self.detail.is_empty()) {
+            (true, _) => format!("Connection test to {} passed", host),
+            (false, true) => format!("Connection test to {} failed", host),
+            (false, false) => format!("Connection test to {} failed: {}", host, self.detail),
+        }
+    }
 }
 
+/// The client program used for an interactive session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectTool {
+    Ssh,
+    Mosh,
+}
+
+impl ConnectTool {
+    pub fn name(self) -> &'static str {
+        match self {
+            ConnectTool::Ssh => "ssh",
+            ConnectTool::Mosh => "mosh",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "ssh" => Some(ConnectTool::Ssh),
+            "mosh" => Some(ConnectTool::Mosh),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct SshConnection {
-    executor: Box<dyn CommandExecutor>,
+    executor: Arc<dyn CommandExecutor>,
     ssh_binary: String,
-    #[allow(dead_code)]
+    mosh_binary: String,
+    sftp_binary: String,
+    ssh_copy_id_binary: String,
     timeout: Duration,
+    /// Wait before the first retry of a failed connection; each later
+    /// retry waits twice as long as the one before.
+    retry_delay: Duration,
 }
 
 impl SshConnection {
     pub fn new(ssh_binary: String, timeout: Duration) -> Self {
         Self {
-            executor: Box::new(RealCommandExecutor),
+            executor: Arc::new(RealCommandExecutor),
             ssh_binary,
+            mosh_binary: "mosh".to_string(),
+            sftp_binary: "sftp".to_string(),
+            ssh_copy_id_binary: "ssh-copy-id".to_string(),
             timeout,
+            retry_delay: RETRY_DELAY,
         }
     }
 
+    pub fn with_mosh_binary(mut self, mosh_binary: String) -> Self {
+        self.mosh_binary = mosh_binary;
+        self
+    }
+
+    pub fn with_sftp_binary(mut self, sftp_binary: String) -> Self {
+        self.sftp_binary = sftp_binary;
+        self
+    }
+
+    pub fn with_ssh_copy_id_binary(mut self, ssh_copy_id_binary: String) -> Self {
+        self.ssh_copy_id_binary = ssh_copy_id_binary;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
     #[allow(dead_code)]
     pub fn with_executor(
         ssh_binary: String,
@@ -43,21 +142,317 @@ impl SshConnection {
         executor: Box<dyn CommandExecutor>,
     ) -> Self {
         Self {
-            executor,
+            executor: Arc::from(executor),
             ssh_binary,
+            mosh_binary: "mosh".to_string(),
+            sftp_binary: "sftp".to_string(),
+            ssh_copy_id_binary: "ssh-copy-id".to_string(),
             timeout,
+            retry_delay: RETRY_DELAY,
         }
     }
 
+    #[allow(dead_code)]
     pub fn connect(&self, host: &str) -> Result<String> {
-        let status = self.executor.execute(&self.ssh_binary, host)?;
+        self.connect_with(host, ConnectTool::Ssh)
+    }
+
+    /// Runs the session and summarizes it; a non-zero exit is an error.
+    pub fn connect_with(&self, host: &str, tool: ConnectTool) -> Result<String> {
+        let (program, args) = self.command_for(host, tool);
+        self.session(host, tool, program, &args)
+    }
+
+    /// Connects with ssh, passing `extra` before the alias and trying again
+    /// up to `retries` times while ssh exits with 255, its code for a
+    /// connection that could not be made. The waits between attempts
+    /// double from the retry delay. Any other exit, clean or not, is final.
+    /// The summary counts the attempts when there was more than one.
+    pub fn connect_with_retry(&self, host: &str, extra: &[String], retries: u32) -> Result<String> {
+        let mut args = extra.to_vec();
+        args.push(host.to_string());
+        let mut attempt = 1;
+        loop {
+            let started = Instant::now();
+            let status = self.execute_session(host, ConnectTool::Ssh, &self.ssh_binary, &args)?;
+            if status.code() == Some(CONNECTION_FAILED) && attempt <= retries {
+                let delay = self.retry_delay * 2u32.saturating_pow(attempt - 1);
+                log::warn(&format!(
+                    "Connection to {} failed (attempt {} of {}); retrying in {:?}",
+                    host,
+                    attempt,
+                    retries + 1,
+                    delay
+                ));
+                thread::sleep(delay);
+                attempt += 1;
+                continue;
+            }
+
+            let mut summary = session_summary(host, ConnectTool::Ssh, status, started.elapsed());
+            if attempt > 1 {
+                summary.push_str(&format!(" ({} attempts)", attempt));
+            }
+            return if status.success() {
+                Ok(summary)
+            } else {
+                Err(SessionFailed::new(summary, status).into())
+            };
+        }
+    }
+
+    /// Connects with ssh as `user` instead of the config's `User`, for
+    /// this session only. `-l` goes before the host, as ssh expects its
+    /// options.
+    pub fn connect_as(&self, host: &str, user: &str) -> Result<String> {
+        let args = ["-l", user, host].map(str::to_string);
+        self.session(host, ConnectTool::Ssh, &self.ssh_binary, &args)
+    }
 
+    /// Connects with `tool`, handing ssh `extra` (e.g. `-v` or
+    /// `-L 8080:localhost:80`) as well. No retries; ssh sessions that want
+    /// them go through `connect_with_retry`.
+    pub fn connect_with_args(
+        &self,
+        host: &str,
+        tool: ConnectTool,
+        extra: &[String],
+    ) -> Result<String> {
+        let (program, args) = self.command_with_args(host, tool, extra);
+        self.session(host, tool, program, &args)
+    }
+
+    fn session(
+        &self,
+        host: &str,
+        tool: ConnectTool,
+        program: &str,
+        args: &[String],
+    ) -> Result<String> {
+        let started = Instant::now();
+        let status = self.execute_session(host, tool, program, args)?;
+        let summary = session_summary(host, tool, status, started.elapsed());
         if status.success() {
-            Ok(format!("Successfully connected to {}", host))
+            Ok(summary)
+        } else {
+            Err(SessionFailed::new(summary, status).into())
+        }
+    }
+
+    fn execute_session(
+        &self,
+        host: &str,
+        tool: ConnectTool,
+        program: &str,
+        args: &[String],
+    ) -> Result<ExitStatus> {
+        log::info(&format!("Connecting to {} via {}", host, tool.name()));
+        self.start(tool.name(), program, host, args)
+    }
+
+    /// Runs `program` for `host`, logging a failure to start it. A program
+    /// that doesn't exist is reported by `role` (`ssh`, `sftp`, ...) rather
+    /// than as a bare OS error, since it usually means a wrong path in the
+    /// config.
+    fn start(&self, role: &str, program: &str, host: &str, args: &[String]) -> Result<ExitStatus> {
+        self.executor.execute(program, args).map_err(|err| {
+            log::warn(&format!(
+                "Failed to start {} for {}: {:#}",
+                program, host, err
+            ));
+            explain_missing_program(err, role, program)
+        })
+    }
+
+    /// Holds the host's forwards open with `ssh -N` until ssh exits, which
+    /// for a healthy tunnel means the user pressed Ctrl+C.
+    pub fn tunnel(&self, host: &str) -> Result<String> {
+        let (program, args) = self.tunnel_command(host);
+        log::info(&format!("Opening tunnel to {}", host));
+        let started = Instant::now();
+        let status = self.start("ssh", program, host, &args)?;
+        Ok(tunnel_summary(host, status, started.elapsed()))
+    }
+
+    pub fn tunnel_command(&self, host: &str) -> (&str, Vec<String>) {
+        (
+            self.ssh_binary.as_str(),
+            vec!["-N".to_string(), host.to_string()],
+        )
+    }
+
+    /// Installs a public key on `host` with ssh-copy-id, which asks for the
+    /// password on the terminal. `identity` picks the key; ssh-copy-id
+    /// finds the `.pub` next to a private key on its own.
+    pub fn copy_id(&self, host: &str, identity: Option<&str>) -> Result<String> {
+        let args = copy_id_args(host, identity);
+        log::info(&format!("Copying public key to {}", host));
+        let status = self.start("ssh-copy-id", &self.ssh_copy_id_binary, host, &args)?;
+        if status.success() {
+            Ok(format!("Copied public key to {}", host))
         } else {
             match status.code() {
-                Some(code) => Ok(format!("Connection to {} exited with code {}", host, code)),
-                None => Ok(format!("Connection to {} terminated by signal", host)),
+                Some(code) => anyhow::bail!("ssh-copy-id to {} exited with code {}", host, code),
+                None => anyhow::bail!("ssh-copy-id to {} terminated by signal", host),
+            }
+        }
+    }
+
+    /// Runs an interactive sftp session against the alias, which sftp
+    /// resolves through the same ssh config. A non-zero exit is an error.
+    pub fn sftp(&self, host: &str) -> Result<String> {
+        log::info(&format!("Opening SFTP session to {}", host));
+        let status = self.start("sftp", &self.sftp_binary, host, &[host.to_string()])?;
+        if status.success() {
+            Ok(sftp_summary(host, status))
+        } else {
+            anyhow::bail!(sftp_summary(host, status))
+        }
+    }
+
+    /// Checks connectivity and authentication without opening a shell:
+    /// BatchMode makes ssh fail instead of prompting, and the remote side
+    /// only runs `true`.
+    pub fn test_connection(&self, host: &str) -> Result<ConnectionTest> {
+        let output = self
+            .executor
+            .output(&self.ssh_binary, &self.test_connection_args(host))
+            .map_err(|err| explain_missing_program(err, "ssh", &self.ssh_binary))?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let detail = stderr
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty())
+            .unwrap_or_default()
+            .to_string();
+        if output.status.success() {
+            return Ok(ConnectionTest {
+                passed: true,
+                detail: String::new(),
+            });
+        }
+        let detail = match (detail.is_empty(), output.status.code()) {
+            (true, Some(code)) => format!("exit code {}", code),
+            _ => detail,
+        };
+        Ok(ConnectionTest {
+            passed: false,
+            detail,
+        })
+    }
+
+    /// Whether ssh-agent holds the key at `identity_file`, judged by
+    /// comparing the key's fingerprint from `ssh-keygen -l` with those
+    /// `ssh-add -l` lists. The `.pub` next to the key is fingerprinted when
+    /// there is one, so no passphrase is needed. False when there is no
+    /// agent or either fingerprint can't be read.
+    pub fn agent_has_key(&self, identity_file: &Path) -> bool {
+        let public = PathBuf::from(format!("{}.pub", identity_file.display()));
+        let key = if public.is_file() {
+            public
+        } else {
+            identity_file.to_path_buf()
+        };
+        let keygen_args = [
+            "-l".to_string(),
+            "-f".to_string(),
+            key.display().to_string(),
+        ];
+        let fingerprint = match self.executor.output("ssh-keygen", &keygen_args) {
+            Ok(output) if output.status.success() => {
+                match key_fingerprint(&String::from_utf8_lossy(&output.stdout)) {
+                    Some(fingerprint) => fingerprint.to_string(),
+                    None => return false,
+                }
+            }
+            _ => return false,
+        };
+        match self.executor.output("ssh-add", &["-l".to_string()]) {
+            Ok(output) if output.status.success() => {
+                agent_lists_fingerprint(&String::from_utf8_lossy(&output.stdout), &fingerprint)
+            }
+            _ => false,
+        }
+    }
+
+    fn test_connection_args(&self, host: &str) -> Vec<String> {
+        vec![
+            "-o".to_string(),
+            "BatchMode=yes".to_string(),
+            "-o".to_string(),
+            format!("ConnectTimeout={}", self.timeout.as_secs()),
+            host.to_string(),
+            "true".to_string(),
+        ]
+    }
+
+    /// Opens one tmux window per host, each running ssh. Windows are named
+    /// after the alias; failures are collected so one bad host does not
+    /// stop the rest.
+    pub fn open_in_tmux(&self, hosts: &[String]) -> Result<String> {
+        let mut failed = Vec::new();
+        for (host, (program, args)) in hosts.iter().zip(self.tmux_commands(hosts)) {
+            match self.executor.execute(program, &args) {
+                Ok(status) if status.success() => {}
+                _ => failed.push(host.as_str()),
+            }
+        }
+        if failed.is_empty() {
+            Ok(format!("Opened {} tmux window(s)", hosts.len()))
+        } else {
+            anyhow::bail!("Failed to open tmux window(s) for {}", failed.join(", "))
+        }
+    }
+
+    pub fn tmux_commands(&self, hosts: &[String]) -> Vec<(&str, Vec<String>)> {
+        hosts
+            .iter()
+            .map(|host| {
+                (
+                    "tmux",
+                    vec![
+                        "new-window".to_string(),
+                        "-n".to_string(),
+                        host.clone(),
+                        self.ssh_binary.clone(),
+                        host.clone(),
+                    ],
+                )
+            })
+            .collect()
+    }
+
+    /// Picks the binary and argument vector for an interactive session.
+    /// mosh reuses the ssh binary (and with it the host's ssh config) for
+    /// its bootstrap connection.
+    pub fn command_for(&self, host: &str, tool: ConnectTool) -> (&str, Vec<String>) {
+        self.command_with_args(host, tool, &[])
+    }
+
+    /// Like `command_for`, with `extra` ssh arguments before the host. mosh
+    /// gets them quoted into its `--ssh` command, which it splits again.
+    fn command_with_args(
+        &self,
+        host: &str,
+        tool: ConnectTool,
+        extra: &[String],
+    ) -> (&str, Vec<String>) {
+        match tool {
+            ConnectTool::Ssh => {
+                let mut args = extra.to_vec();
+                args.push(host.to_string());
+                (self.ssh_binary.as_str(), args)
+            }
+            ConnectTool::Mosh => {
+                let mut ssh = self.ssh_binary.clone();
+                for arg in extra {
+                    ssh.push(' ');
+                    ssh.push_str(&shell_quote(arg));
+                }
+                (
+                    self.mosh_binary.as_str(),
+                    vec![format!("--ssh={}", ssh), host.to_string()],
+                )
             }
         }
     }
@@ -68,21 +463,284 @@ impl SshConnection {
     }
 }
 
+/// `[-i <identity>] <host>` for ssh-copy-id; options go before the host.
+fn copy_id_args(host: &str, identity: Option<&str>) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(identity) = identity {
+        args.extend(["-i".to_string(), identity.to_string()]);
+    }
+    args.push(host.to_string());
+    args
+}
+
+/// The fingerprint in a `<bits> <fingerprint> <comment> (<type>)` line, the
+/// format both `ssh-keygen -l` and `ssh-add -l` print.
+fn key_fingerprint(line: &str) -> Option<&str> {
+    line.split_whitespace().nth(1)
+}
+
+/// Whether any key `ssh-add -l` listed has `fingerprint`.
+fn agent_lists_fingerprint(agent_output: &str, fingerprint: &str) -> bool {
+    agent_output
+        .lines()
+        .filter_map(key_fingerprint)
+        .any(|listed| listed == fingerprint)
+}
+
+/// The argument mosh connects to: the alias, or `user@alias` when a user
+/// overrides the config's `User` for one session, since mosh has no `-l`.
+/// The alias still picks up the rest of its block.
+pub fn destination(host: &str, user: Option<&str>) -> String {
+    match user {
+        Some(user) => format!("{}@{}", user, host),
+        None => host.to_string(),
+    }
+}
+
+/// Looks `hostname` up through the system resolver, returning each address
+/// once in resolver order. Failures resolve to nothing.
+pub fn resolve_host(hostname: &str, port: u16) -> Vec<IpAddr> {
+    let hostname = hostname.trim_start_matches('[').trim_end_matches(']');
+    let Ok(addrs) = (hostname, port).to_socket_addrs() else {
+        return Vec::new();
+    };
+    let mut ips: Vec<IpAddr> = Vec::new();
+    for addr in addrs {
+        if !ips.contains(&addr.ip()) {
+            ips.push(addr.ip());
+        }
+    }
+    ips
+}
+
+/// Replaces a "no such file" error from starting `program` with one that
+/// names the setting to fix; other errors pass through.
+fn explain_missing_program(err: anyhow::Error, role: &str, program: &str) -> anyhow::Error {
+    match err.downcast_ref::<io::Error>() {
+        Some(io_err) if io_err.kind() == io::ErrorKind::NotFound => {
+            anyhow::anyhow!(
+                "{} binary '{}' not found — check your config",
+                role,
+                program
+            )
+        }
+        _ => err,
+    }
+}
+
+/// An interactive session that exited unsuccessfully. Shows as its summary;
+/// `code` is the client's exit code, `None` when a signal ended it.
+#[derive(Debug)]
+pub struct SessionFailed {
+    summary: String,
+    pub code: Option<i32>,
+}
+
+impl SessionFailed {
+    fn new(summary: String, status: ExitStatus) -> Self {
+        Self {
+            summary,
+            code: status.code(),
+        }
+    }
+}
+
+impl fmt::Display for SessionFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.summary)
+    }
+}
+
+impl std::error::Error for SessionFailed {}
+
+/// Status line for a finished interactive session.
+fn session_summary(host: &str, tool: ConnectTool, status: ExitStatus, elapsed: Duration) -> String {
+    let tool = tool.name();
+    let elapsed = format_duration(elapsed);
+    if status.success() {
+        return format!("Session to {} via {} ended after {}", host, tool, elapsed);
+    }
+    match status.code() {
+        Some(code) => format!(
+            "Connection to {} via {} exited with code {} after {}",
+            host, tool, code, elapsed
+        ),
+        None => format!(
+            "Connection to {} via {} terminated by signal after {}",
+            host, tool, elapsed
+        ),
+    }
+}
+
+/// Values substituted into a `ControlPath`, already defaulted the way ssh
+/// would: the alias as hostname, the local user, port 22.
+pub struct ControlTokens<'a> {
+    pub alias: &'a str,
+    pub hostname: &'a str,
+    pub user: &'a str,
+    pub port: &'a str,
+    pub local_user: &'a str,
+    pub home: &'a Path,
+}
+
+/// Expands a `ControlPath` the way ssh does for the tokens it can know
+/// without connecting (`%%`, `%d`, `%h`, `%n`, `%p`, `%r`, `%u`) and a
+/// leading `~`. `None` for `none` or any other token, e.g. the hashed `%C`.
+pub fn expand_control_path(template: &str, tokens: &ControlTokens) -> Option<PathBuf> {
+    if template.is_empty() || template.eq_ignore_ascii_case("none") {
+        return None;
+    }
+    let home = tokens.home.to_string_lossy();
+    let mut expanded = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        expanded.push_str(match chars.next()? {
+            '%' => "%",
+            'd' => &home,
+            'h' => tokens.hostname,
+            'n' => tokens.alias,
+            'p' => tokens.port,
+            'r' => tokens.user,
+            'u' => tokens.local_user,
+            _ => return None,
+        });
+    }
+    Some(crate::keys::expand_tilde(&expanded, tokens.home))
+}
+
+/// Previews a ProxyCommand-style template for `entry`: `%h` is the
+/// HostName (or the alias), `%p` the port (22 by default), `%r` the User
+/// and `%n` the alias; `%%` is a literal `%`. Unknown tokens, and `%r`
+/// when no User is set, are left as written.
+pub fn expand_ssh_tokens(template: &str, entry: &HostEntry) -> String {
+    let mut expanded = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        let Some(token) = chars.next() else {
+            expanded.push('%');
+            break;
+        };
+        match token {
+            '%' => expanded.push('%'),
+            'h' => expanded.push_str(entry.target_hostname()),
+            'n' => expanded.push_str(entry.host.trim()),
+            'p' => expanded.push_str(match entry.port() {
+                "" => "22",
+                port => port,
+            }),
+            'r' if !entry.user().is_empty() => expanded.push_str(entry.user()),
+            _ => {
+                expanded.push('%');
+                expanded.push(token);
+            }
+        }
+    }
+    expanded
+}
+
+/// Status line for a closed tunnel. Ctrl+C makes ssh exit non-zero, so a
+/// code is only worth showing as a hint when the tunnel never came up.
+fn tunnel_summary(host: &str, status: ExitStatus, elapsed: Duration) -> String {
+    let elapsed = format_duration(elapsed);
+    match status.code() {
+        Some(code) if code != 0 => format!(
+            "Tunnel to {} closed after {} (ssh exited with code {})",
+            host, elapsed, code
+        ),
+        _ => format!("Tunnel to {} closed after {}", host, elapsed),
+    }
+}
+
+/// Status line for a finished sftp session.
+fn sftp_summary(host: &str, status: ExitStatus) -> String {
+    match status.code() {
+        Some(0) => format!("SFTP session to {} ended", host),
+        Some(code) => format!("SFTP session to {} exited with code {}", host, code),
+        None => format!("SFTP session to {} terminated by signal", host),
+    }
+}
+
+/// Renders a duration as e.g. `45s`, `12m3s` or `2h5m0s`.
+fn format_duration(duration: Duration) -> String {
+    let total = duration.as_secs();
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{}h{}m{}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m{}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::process::ExitStatus;
+    use std::sync::Mutex;
 
     #[cfg(unix)]
     use std::os::unix::process::ExitStatusExt;
 
+    /// Program and arguments of each command run, in order.
+    #[cfg(unix)]
+    type Calls = Arc<Mutex<Vec<(String, Vec<String>)>>>;
+
+    /// Records every command it is asked to run and reports success.
+    #[cfg(unix)]
+    #[derive(Default)]
+    struct RecordingExecutor {
+        calls: Calls,
+    }
+
+    #[cfg(unix)]
+    impl CommandExecutor for RecordingExecutor {
+        fn output(&self, program: &str, args: &[String]) -> Result<Output> {
+            Ok(Output {
+                status: self.execute(program, args)?,
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            })
+        }
+
+        fn execute(&self, program: &str, args: &[String]) -> Result<ExitStatus> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push((program.to_string(), args.to_vec()));
+            Ok(ExitStatus::from_raw(0))
+        }
+    }
+
     struct MockCommandExecutor {
         success: bool,
         exit_code: Option<i32>,
     }
 
     impl CommandExecutor for MockCommandExecutor {
-        fn execute(&self, _ssh_binary: &str, _host: &str) -> Result<ExitStatus> {
+        fn output(&self, program: &str, args: &[String]) -> Result<Output> {
+            let status = self.execute(program, args)?;
+            let stderr = if status.success() {
+                Vec::new()
+            } else {
+                b"debug1: connecting\nPermission denied (publickey).\n".to_vec()
+            };
+            Ok(Output {
+                status,
+                stdout: Vec::new(),
+                stderr,
+            })
+        }
+
+        fn execute(&self, _program: &str, _args: &[String]) -> Result<ExitStatus> {
             #[cfg(unix)]
             {
                 let status = if self.success {
@@ -112,7 +770,7 @@ mod tests {
             SshConnection::with_executor("ssh".to_string(), Duration::from_secs(30), executor);
 
         let result = connection.connect("test-host").unwrap();
-        assert_eq!(result, "Successfully connected to test-host");
+        assert!(result.starts_with("Session to test-host via ssh ended after "));
     }
 
     #[test]
@@ -125,8 +783,410 @@ mod tests {
         let connection =
             SshConnection::with_executor("ssh".to_string(), Duration::from_secs(30), executor);
 
-        let result = connection.connect("test-host").unwrap();
-        assert_eq!(result, "Connection to test-host exited with code 255");
+        let result = connection.connect("test-host").unwrap_err().to_string();
+        assert!(result.starts_with("Connection to test-host via ssh exited with code 255 after "));
+    }
+
+    #[test]
+    fn test_missing_binary_is_named_in_the_error() {
+        let connection = SshConnection::new(
+            "ssh-tui-test-no-such-ssh".to_string(),
+            Duration::from_secs(30),
+        )
+        .with_sftp_binary("/nonexistent/sftp".to_string());
+
+        assert_eq!(
+            connection.connect("web").unwrap_err().to_string(),
+            "ssh binary 'ssh-tui-test-no-such-ssh' not found — check your config"
+        );
+        assert_eq!(
+            connection.sftp("web").unwrap_err().to_string(),
+            "sftp binary '/nonexistent/sftp' not found — check your config"
+        );
+        assert!(connection
+            .test_connection("web")
+            .unwrap_err()
+            .to_string()
+            .starts_with("ssh binary 'ssh-tui-test-no-such-ssh' not found"));
+    }
+
+    /// Exits with each of `codes` in turn, then 0, counting the attempts.
+    #[cfg(unix)]
+    struct SequenceExecutor {
+        codes: Mutex<Vec<i32>>,
+        attempts: Arc<Mutex<usize>>,
+    }
+
+    #[cfg(unix)]
+    impl CommandExecutor for SequenceExecutor {
+        fn output(&self, program: &str, args: &[String]) -> Result<Output> {
+            Ok(Output {
+                status: self.execute(program, args)?,
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            })
+        }
+
+        fn execute(&self, _program: &str, _args: &[String]) -> Result<ExitStatus> {
+            *self.attempts.lock().unwrap() += 1;
+            let mut codes = self.codes.lock().unwrap();
+            let code = if codes.is_empty() { 0 } else { codes.remove(0) };
+            Ok(ExitStatus::from_raw(code << 8))
+        }
+    }
+
+    #[cfg(unix)]
+    fn sequence_connection(codes: &[i32]) -> (SshConnection, Arc<Mutex<usize>>) {
+        let attempts = Arc::new(Mutex::new(0));
+        let executor = Box::new(SequenceExecutor {
+            codes: Mutex::new(codes.to_vec()),
+            attempts: Arc::clone(&attempts),
+        });
+        let connection =
+            SshConnection::with_executor("ssh".to_string(), Duration::from_secs(30), executor)
+                .with_retry_delay(Duration::ZERO);
+        (connection, attempts)
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_connect_with_retry_retries_connection_failures() {
+        let (connection, attempts) = sequence_connection(&[255, 255]);
+        let summary = connection.connect_with_retry("flaky", &[], 3).unwrap();
+        assert_eq!(*attempts.lock().unwrap(), 3);
+        assert!(summary.starts_with("Session to flaky via ssh ended after "));
+        assert!(summary.ends_with(" (3 attempts)"), "{}", summary);
+
+        let (connection, attempts) = sequence_connection(&[255, 255, 255]);
+        let err = connection
+            .connect_with_retry("down", &[], 1)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(*attempts.lock().unwrap(), 2);
+        assert!(err.contains("exited with code 255"), "{}", err);
+        assert!(err.ends_with(" (2 attempts)"), "{}", err);
+
+        // Only 255 means ssh never got through; other codes are final.
+        let (connection, attempts) = sequence_connection(&[1, 255]);
+        let err = connection
+            .connect_with_retry("remote", &[], 3)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(*attempts.lock().unwrap(), 1);
+        assert!(!err.contains("attempts"), "{}", err);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_failed_session_carries_exit_code() {
+        let executor = Box::new(MockCommandExecutor {
+            success: false,
+            exit_code: Some(255),
+        });
+        let connection =
+            SshConnection::with_executor("ssh".to_string(), Duration::from_secs(30), executor);
+
+        let err = connection
+            .connect_with("test-host", ConnectTool::Ssh)
+            .unwrap_err()
+            .context("post-connect hook ok");
+        let failed = err.downcast_ref::<SessionFailed>().unwrap();
+        assert_eq!(failed.code, Some(255));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_sftp_reports_exit_status() {
+        let connection = |success, exit_code| {
+            let executor = Box::new(MockCommandExecutor { success, exit_code });
+            SshConnection::with_executor("ssh".to_string(), Duration::from_secs(30), executor)
+                .with_sftp_binary("/opt/bin/sftp".to_string())
+        };
+
+        assert_eq!(
+            connection(true, None).sftp("files").unwrap(),
+            "SFTP session to files ended"
+        );
+        assert_eq!(
+            connection(false, Some(1))
+                .sftp("files")
+                .unwrap_err()
+                .to_string(),
+            "SFTP session to files exited with code 1"
+        );
+    }
+
+    #[test]
+    fn test_agent_fingerprints_are_matched_by_the_second_field() {
+        let agent = "256 SHA256:mV0d/2tVpGtTv0ZebP8Zl6Yy8ckWHd4j2MXv8KxQ+7o me@laptop (ED25519)\n\
+                     3072 SHA256:Q1vAZf2mhhvXC4u1n6Xr0fn3xgPbQ1GWc8-4CKVNsmE deploy key (RSA)\n";
+        assert!(agent_lists_fingerprint(
+            agent,
+            "SHA256:Q1vAZf2mhhvXC4u1n6Xr0fn3xgPbQ1GWc8-4CKVNsmE"
+        ));
+        assert!(!agent_lists_fingerprint(agent, "SHA256:other"));
+        assert!(!agent_lists_fingerprint(
+            "The agent has no identities.\n",
+            "SHA256:other"
+        ));
+        assert_eq!(
+            key_fingerprint("256 SHA256:abc me@laptop (ED25519)\n"),
+            Some("SHA256:abc")
+        );
+    }
+
+    /// Answers each program with a fixed exit code and stdout.
+    #[cfg(unix)]
+    struct OutputExecutor {
+        outputs: Vec<(&'static str, i32, &'static str)>,
+    }
+
+    #[cfg(unix)]
+    impl CommandExecutor for OutputExecutor {
+        fn output(&self, program: &str, _args: &[String]) -> Result<Output> {
+            let (_, code, stdout) = self
+                .outputs
+                .iter()
+                .find(|(name, _, _)| *name == program)
+                .copied()
+                .unwrap_or((program, 127, ""));
+            Ok(Output {
+                status: ExitStatus::from_raw(code << 8),
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: Vec::new(),
+            })
+        }
+
+        fn execute(&self, program: &str, args: &[String]) -> Result<ExitStatus> {
+            Ok(self.output(program, args)?.status)
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_agent_has_key_compares_the_key_with_the_agent() {
+        let agent_has = |agent: (i32, &'static str)| {
+            let executor = Box::new(OutputExecutor {
+                outputs: vec![
+                    ("ssh-keygen", 0, "256 SHA256:abc me@laptop (ED25519)\n"),
+                    ("ssh-add", agent.0, agent.1),
+                ],
+            });
+            SshConnection::with_executor("ssh".to_string(), Duration::from_secs(30), executor)
+                .agent_has_key(Path::new("/home/me/.ssh/id_ed25519"))
+        };
+
+        assert!(agent_has((0, "256 SHA256:abc me@laptop (ED25519)\n")));
+        assert!(!agent_has((0, "256 SHA256:xyz other (ED25519)\n")));
+        assert!(!agent_has((1, "The agent has no identities.\n")));
+        assert!(!agent_has((2, "")));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_copy_id_passes_the_identity_before_the_host() {
+        let executor = RecordingExecutor::default();
+        let calls = Arc::clone(&executor.calls);
+        let connection = SshConnection::with_executor(
+            "ssh".to_string(),
+            Duration::from_secs(30),
+            Box::new(executor),
+        )
+        .with_ssh_copy_id_binary("/usr/bin/ssh-copy-id".to_string());
+
+        assert_eq!(
+            connection
+                .copy_id("web", Some("/home/me/.ssh/id_web"))
+                .unwrap(),
+            "Copied public key to web"
+        );
+        connection.copy_id("db", None).unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                (
+                    "/usr/bin/ssh-copy-id".to_string(),
+                    vec![
+                        "-i".to_string(),
+                        "/home/me/.ssh/id_web".to_string(),
+                        "web".to_string()
+                    ]
+                ),
+                ("/usr/bin/ssh-copy-id".to_string(), vec!["db".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_mosh_gets_a_one_off_user_as_user_at_host() {
+        let executor = RecordingExecutor::default();
+        let calls = Arc::clone(&executor.calls);
+        let connection = SshConnection::with_executor(
+            "/usr/bin/ssh".to_string(),
+            Duration::from_secs(30),
+            Box::new(executor),
+        );
+
+        connection
+            .connect_with(&destination("web", Some("deploy")), ConnectTool::Mosh)
+            .unwrap();
+        connection
+            .connect_with(&destination("web", None), ConnectTool::Mosh)
+            .unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                (
+                    "mosh".to_string(),
+                    vec!["--ssh=/usr/bin/ssh".to_string(), "deploy@web".to_string()]
+                ),
+                (
+                    "mosh".to_string(),
+                    vec!["--ssh=/usr/bin/ssh".to_string(), "web".to_string()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_connect_as_puts_the_login_user_before_the_host() {
+        let executor = RecordingExecutor::default();
+        let calls = Arc::clone(&executor.calls);
+        let connection = SshConnection::with_executor(
+            "/usr/bin/ssh".to_string(),
+            Duration::from_secs(30),
+            Box::new(executor),
+        );
+
+        let summary = connection.connect_as("web", "root").unwrap();
+        assert!(summary.starts_with("Session to web via ssh ended after "));
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![(
+                "/usr/bin/ssh".to_string(),
+                vec!["-l".to_string(), "root".to_string(), "web".to_string()]
+            )]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_connect_with_args_puts_the_extra_args_before_the_host() {
+        let executor = RecordingExecutor::default();
+        let calls = Arc::clone(&executor.calls);
+        let connection = SshConnection::with_executor(
+            "/usr/bin/ssh".to_string(),
+            Duration::from_secs(30),
+            Box::new(executor),
+        );
+
+        let extra = ["-v", "-L", "8080:localhost:80"].map(str::to_string);
+        connection.connect_with_retry("web", &extra, 2).unwrap();
+        let extra = ["-o", "ProxyJump bastion's"].map(str::to_string);
+        connection
+            .connect_with_args("web", ConnectTool::Mosh, &extra)
+            .unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                (
+                    "/usr/bin/ssh".to_string(),
+                    ["-v", "-L", "8080:localhost:80", "web"]
+                        .map(str::to_string)
+                        .to_vec()
+                ),
+                (
+                    "mosh".to_string(),
+                    ["--ssh=/usr/bin/ssh '-o' 'ProxyJump bastion'\\''s'", "web"]
+                        .map(str::to_string)
+                        .to_vec()
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tunnel_command_skips_remote_command() {
+        let connection = SshConnection::new("/usr/bin/ssh".to_string(), Duration::from_secs(30));
+        let (program, args) = connection.tunnel_command("db");
+        assert_eq!(program, "/usr/bin/ssh");
+        assert_eq!(args, vec!["-N", "db"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_tunnel_summary_mentions_exit_code() {
+        let executor = Box::new(MockCommandExecutor {
+            success: false,
+            exit_code: Some(255),
+        });
+        let connection =
+            SshConnection::with_executor("ssh".to_string(), Duration::from_secs(30), executor);
+        assert_eq!(
+            connection.tunnel("db").unwrap(),
+            "Tunnel to db closed after 0s (ssh exited with code 255)"
+        );
+    }
+
+    #[test]
+    fn test_expand_control_path_tokens() {
+        let home = Path::new("/home/me");
+        let tokens = ControlTokens {
+            alias: "web",
+            hostname: "web.example.com",
+            user: "deploy",
+            port: "2222",
+            local_user: "me",
+            home,
+        };
+        let expand = |template| expand_control_path(template, &tokens);
+
+        assert_eq!(
+            expand("~/.ssh/cm-%r@%h:%p"),
+            Some(PathBuf::from(
+                "/home/me/.ssh/cm-deploy@web.example.com:2222"
+            ))
+        );
+        assert_eq!(
+            expand("/tmp/%u/%n-100%%"),
+            Some(PathBuf::from("/tmp/me/web-100%"))
+        );
+        assert_eq!(expand("%d/sock"), Some(PathBuf::from("/home/me/sock")));
+        assert_eq!(expand("~/.ssh/%C"), None);
+        assert_eq!(expand("/tmp/trailing%"), None);
+        assert_eq!(expand("none"), None);
+    }
+
+    #[test]
+    fn test_expand_ssh_tokens_fills_host_and_port() {
+        let entry = HostEntry::new("legacy").with("HostName", "10.0.0.6");
+        assert_eq!(
+            expand_ssh_tokens("ssh -W %h:%p bastion", &entry),
+            "ssh -W 10.0.0.6:22 bastion"
+        );
+        assert_eq!(
+            expand_ssh_tokens("nc %n %p", &entry.with("Port", "2222")),
+            "nc legacy 2222"
+        );
+        assert_eq!(
+            expand_ssh_tokens("ncat --proxy %C %x 100%% %", &HostEntry::new("a")),
+            "ncat --proxy %C %x 100% %"
+        );
+    }
+
+    #[test]
+    fn test_expand_ssh_tokens_fills_the_remote_user() {
+        let entry = HostEntry::new("db").with("HostName", "db.internal");
+        assert_eq!(
+            expand_ssh_tokens("ssh %r@jump -W %h:%p", &entry),
+            "ssh %r@jump -W db.internal:22"
+        );
+        assert_eq!(
+            expand_ssh_tokens("ssh %r@jump -W %h:%p", &entry.with("User", "deploy")),
+            "ssh deploy@jump -W db.internal:22"
+        );
     }
 
     #[test]
@@ -150,4 +1210,134 @@ mod tests {
         let result = connection.connect("test-host");
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_mosh_command_uses_configured_binaries() {
+        let connection = SshConnection::new("/opt/ssh".to_string(), Duration::from_secs(30))
+            .with_mosh_binary("/opt/mosh".to_string());
+
+        let (program, args) = connection.command_for("test-host", ConnectTool::Mosh);
+        assert_eq!(program, "/opt/mosh");
+        assert_eq!(args, vec!["--ssh=/opt/ssh", "test-host"]);
+
+        let (program, args) = connection.command_for("test-host", ConnectTool::Ssh);
+        assert_eq!(program, "/opt/ssh");
+        assert_eq!(args, vec!["test-host"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_mosh_connection_reports_tool() {
+        let executor = Box::new(MockCommandExecutor {
+            success: true,
+            exit_code: None,
+        });
+        let connection =
+            SshConnection::with_executor("ssh".to_string(), Duration::from_secs(30), executor);
+
+        let result = connection
+            .connect_with("test-host", ConnectTool::Mosh)
+            .unwrap();
+        assert!(result.starts_with("Session to test-host via mosh ended after "));
+    }
+
+    #[test]
+    fn test_connect_tool_from_name() {
+        assert_eq!(ConnectTool::from_name("Mosh"), Some(ConnectTool::Mosh));
+        assert_eq!(ConnectTool::from_name("ssh"), Some(ConnectTool::Ssh));
+        assert_eq!(ConnectTool::from_name("telnet"), None);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_millis(45_900)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(12 * 60 + 3)), "12m3s");
+        assert_eq!(
+            format_duration(Duration::from_secs(2 * 3600 + 5 * 60)),
+            "2h5m0s"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_session_summary_includes_duration() {
+        let elapsed = Duration::from_secs(723);
+        assert_eq!(
+            session_summary("x", ConnectTool::Ssh, ExitStatus::from_raw(0), elapsed),
+            "Session to x via ssh ended after 12m3s"
+        );
+        assert_eq!(
+            session_summary("x", ConnectTool::Mosh, ExitStatus::from_raw(9), elapsed),
+            "Connection to x via mosh terminated by signal after 12m3s"
+        );
+    }
+
+    #[test]
+    fn test_tmux_commands_per_host() {
+        let connection = SshConnection::new("/opt/ssh".to_string(), Duration::from_secs(30));
+        let hosts = vec!["web".to_string(), "db".to_string()];
+
+        let commands = connection.tmux_commands(&hosts);
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].0, "tmux");
+        assert_eq!(
+            commands[0].1,
+            vec!["new-window", "-n", "web", "/opt/ssh", "web"]
+        );
+        assert_eq!(
+            commands[1].1,
+            vec!["new-window", "-n", "db", "/opt/ssh", "db"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_localhost_to_loopback() {
+        let ips = resolve_host("localhost", 22);
+        assert!(!ips.is_empty());
+        assert!(ips.iter().all(|ip| ip.is_loopback()));
+        assert!(resolve_host("host.invalid", 22).is_empty());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_connection_test_pass_and_fail() {
+        let passing = SshConnection::with_executor(
+            "ssh".to_string(),
+            Duration::from_secs(5),
+            Box::new(MockCommandExecutor {
+                success: true,
+                exit_code: None,
+            }),
+        );
+        let result = passing.test_connection("web").unwrap();
+        assert!(result.passed);
+        assert_eq!(result.summary("web"), "Connection test to web passed");
+        assert_eq!(
+            passing.test_connection_args("web"),
+            vec![
+                "-o",
+                "BatchMode=yes",
+                "-o",
+                "ConnectTimeout=5",
+                "web",
+                "true"
+            ]
+        );
+
+        let failing = SshConnection::with_executor(
+            "ssh".to_string(),
+            Duration::from_secs(5),
+            Box::new(MockCommandExecutor {
+                success: false,
+                exit_code: Some(255),
+            }),
+        );
+        let result = failing.test_connection("web").unwrap();
+        assert!(!result.passed);
+        assert_eq!(
+            result.summary("web"),
+            "Connection test to web failed: Permission denied (publickey)."
+        );
+    }
 }
@@ -1,19 +1,299 @@
-use crate::config::Config;
+use crate::{config::Config, log};
 use anyhow::{Context, Result};
-use std::{fs, path::Path};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::{self, BufRead, BufReader},
+    net::{IpAddr, Ipv6Addr},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// A directive set differently on two hosts; an empty side is unset.
+/// Repeated keywords such as `LocalForward` are joined with `, `.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub keyword: String,
+    pub left: String,
+    pub right: String,
+}
 
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct HostEntry {
     pub host: String,
-    pub hostname: String,
-    pub user: String,
-    pub port: String,
-    pub identity_file: String,
-    pub proxy_command: String,
-    pub extra: Vec<String>,
+    /// The block body in file order, keywords in their original case.
+    /// Comment and blank lines are kept verbatim with an empty keyword, so
+    /// a rewrite reproduces everything it did not change.
+    pub directives: Vec<(String, String)>,
+    /// True when the whole block is commented out with `#`.
+    pub disabled: bool,
+    /// Which config the block was read from.
+    pub origin: Origin,
+    /// A block for a pattern such as `Host * !badhost` rather than an
+    /// alias. `host` then holds the whole pattern list. Pattern blocks are
+    /// never listed as hosts; their directives are defaults for the hosts
+    /// they match.
+    pub is_pattern: bool,
+}
+
+/// The config file a host comes from. System hosts are shown but never
+/// written to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Origin {
+    #[default]
+    User,
+    System,
+}
+
+/// How aliases are compared when looking up a block to update or delete.
+/// ssh itself matches `Host` patterns case-sensitively, hence the default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AliasMatch {
+    #[default]
+    Exact,
+    IgnoreCase,
+}
+
+impl AliasMatch {
+    pub fn matches(self, alias: &str, host: &str) -> bool {
+        match self {
+            AliasMatch::Exact => alias == host,
+            AliasMatch::IgnoreCase => alias.eq_ignore_ascii_case(host),
+        }
+    }
+}
+
+/// The directives the form edits and the app reads directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KnownField {
+    HostName,
+    User,
+    Port,
+    IdentityFile,
+    ProxyCommand,
+}
+
+impl KnownField {
+    pub const ALL: [KnownField; 5] = [
+        KnownField::HostName,
+        KnownField::User,
+        KnownField::Port,
+        KnownField::IdentityFile,
+        KnownField::ProxyCommand,
+    ];
+
+    pub fn keyword(self) -> &'static str {
+        match self {
+            KnownField::HostName => "HostName",
+            KnownField::User => "User",
+            KnownField::Port => "Port",
+            KnownField::IdentityFile => "IdentityFile",
+            KnownField::ProxyCommand => "ProxyCommand",
+        }
+    }
+
+    fn is_keyword(keyword: &str) -> bool {
+        Self::ALL
+            .into_iter()
+            .any(|field| field.keyword().eq_ignore_ascii_case(keyword))
+    }
+
+    /// Lines the form has no field for. ProxyCommand is known to the app
+    /// but only editable as raw config, so it counts as extra.
+    fn is_extra(keyword: &str) -> bool {
+        !Self::is_keyword(keyword)
+            || keyword.eq_ignore_ascii_case(KnownField::ProxyCommand.keyword())
+    }
 }
 
 impl HostEntry {
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Appends a raw block line, parsed as the loader would.
+    #[cfg(test)]
+    pub fn with_line(mut self, line: &str) -> Self {
+        apply_directive_line(&mut self, line);
+        self
+    }
+
+    /// Builder-style [`HostEntry::set`].
+    #[cfg(test)]
+    pub fn with(mut self, keyword: &str, value: &str) -> Self {
+        self.set(keyword, value);
+        self
+    }
+
+    /// The first value given for `keyword`, as ssh uses the first one, with
+    /// any inline comment removed. Empty when the directive is absent.
+    pub fn get(&self, keyword: &str) -> &str {
+        self.position(keyword)
+            .map(|index| strip_inline_comment(&self.directives[index].1).trim())
+            .unwrap_or("")
+    }
+
+    /// Sets `keyword`, replacing the first occurrence in place or adding it
+    /// after the last directive of the block. A trailing `# comment` on the
+    /// old value is carried over unless the new value is empty or brings
+    /// its own.
+    pub fn set(&mut self, keyword: &str, value: &str) {
+        let raw = self.directive_mut(keyword);
+        let comment = match raw.find('#') {
+            Some(index) if !value.is_empty() && !value.contains('#') => {
+                let gap = &raw[raw[..index].trim_end().len()..index];
+                let gap = if gap.is_empty() { " " } else { gap };
+                Some(format!("{}{}", gap, raw[index..].trim_end()))
+            }
+            _ => None,
+        };
+        *raw = match comment {
+            Some(comment) => format!("{}{}", value.trim_end(), comment),
+            None => value.to_string(),
+        };
+    }
+
+    /// The raw value of `keyword` for editing, adding an empty directive
+    /// when it is absent. Empty directives are never written out.
+    pub fn directive_mut(&mut self, keyword: &str) -> &mut String {
+        let index = match self.position(keyword) {
+            Some(index) => index,
+            None => {
+                let index = self
+                    .directives
+                    .iter()
+                    .rposition(|(keyword, _)| !keyword.is_empty())
+                    .map_or(0, |index| index + 1);
+                self.directives
+                    .insert(index, (keyword.to_string(), String::new()));
+                index
+            }
+        };
+        &mut self.directives[index].1
+    }
+
+    fn position(&self, keyword: &str) -> Option<usize> {
+        self.directives
+            .iter()
+            .position(|(name, _)| !name.is_empty() && name.eq_ignore_ascii_case(keyword))
+    }
+
+    pub fn field(&self, field: KnownField) -> &str {
+        self.get(field.keyword())
+    }
+
+    pub fn hostname(&self) -> &str {
+        self.field(KnownField::HostName)
+    }
+
+    pub fn user(&self) -> &str {
+        self.field(KnownField::User)
+    }
+
+    pub fn port(&self) -> &str {
+        self.field(KnownField::Port)
+    }
+
+    pub fn identity_file(&self) -> &str {
+        self.field(KnownField::IdentityFile)
+    }
+
+    pub fn proxy_command(&self) -> &str {
+        self.field(KnownField::ProxyCommand)
+    }
+
+    /// Block lines the form does not edit, as they appear in the file
+    /// (minus indentation): other directives, comments and blank lines.
+    /// The `# tags:` and `# note:` comments have their own fields.
+    pub fn extra_lines(&self) -> Vec<String> {
+        self.directives
+            .iter()
+            .filter(|(keyword, line)| {
+                KnownField::is_extra(keyword)
+                    && !(keyword.is_empty()
+                        && (comment_field(line, "tags").is_some()
+                            || comment_field(line, "note").is_some()))
+            })
+            .map(|(keyword, value)| {
+                if keyword.is_empty() {
+                    value.trim().to_string()
+                } else {
+                    format!("{} {}", keyword, value)
+                }
+            })
+            .collect()
+    }
+
+    /// The directives that differ from `other`'s, compared by keyword
+    /// ignoring case and listed in the order they first appear in `self`,
+    /// then `other`. Comments and blank lines are not compared.
+    pub fn diff(&self, other: &HostEntry) -> Vec<FieldDiff> {
+        let left = self.grouped_directives();
+        let right = other.grouped_directives();
+        let mut keywords: Vec<&str> = Vec::new();
+        for (keyword, _) in left.iter().chain(&right) {
+            if !keywords
+                .iter()
+                .any(|seen| seen.eq_ignore_ascii_case(keyword))
+            {
+                keywords.push(keyword);
+            }
+        }
+        let value = |grouped: &[(String, String)], keyword: &str| {
+            grouped
+                .iter()
+                .find(|(other, _)| other.eq_ignore_ascii_case(keyword))
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default()
+        };
+        keywords
+            .into_iter()
+            .filter_map(|keyword| {
+                let (left, right) = (value(&left, keyword), value(&right, keyword));
+                (left != right).then(|| FieldDiff {
+                    keyword: keyword.to_string(),
+                    left,
+                    right,
+                })
+            })
+            .collect()
+    }
+
+    /// Each keyword once, in first-appearance order, with its values joined.
+    fn grouped_directives(&self) -> Vec<(String, String)> {
+        let mut grouped: Vec<(String, String)> = Vec::new();
+        for (keyword, value) in &self.directives {
+            if keyword.is_empty() || value.trim().is_empty() {
+                continue;
+            }
+            match grouped
+                .iter_mut()
+                .find(|(seen, _)| seen.eq_ignore_ascii_case(keyword))
+            {
+                Some((_, values)) => {
+                    values.push_str(", ");
+                    values.push_str(value.trim());
+                }
+                None => grouped.push((keyword.clone(), value.trim().to_string())),
+            }
+        }
+        grouped
+    }
+
+    /// How many directives the form does not edit, ignoring comments and
+    /// blank lines.
+    pub fn extra_directive_count(&self) -> usize {
+        self.directives
+            .iter()
+            .filter(|(keyword, _)| !keyword.is_empty() && KnownField::is_extra(keyword))
+            .count()
+    }
+
     pub fn validate(&self) -> Result<()> {
         if self.host.trim().is_empty() {
             anyhow::bail!("Host cannot be empty");
@@ -21,21 +301,280 @@ impl HostEntry {
         if self.host.contains('*') || self.host.contains('?') {
             anyhow::bail!("Host cannot contain wildcard characters");
         }
-        if self.hostname.trim().is_empty() {
+        if self.hostname().is_empty() {
             anyhow::bail!("HostName cannot be empty");
         }
-        if !self.port.trim().is_empty() {
-            let port_num: u16 = self
-                .port
-                .trim()
-                .parse()
-                .context("Port must be a number between 1 and 65535")?;
-            if port_num == 0 {
-                anyhow::bail!("Port must be greater than 0");
-            }
+        if !is_plausible_hostname(self.hostname()) {
+            anyhow::bail!(
+                "HostName '{}' is not a valid host name or IP address",
+                self.hostname()
+            );
+        }
+        if !self.port().is_empty() {
+            check_port(self.port())?;
         }
         Ok(())
     }
+
+    /// Reads a per-host client override from a `# connect-with: <tool>`
+    /// comment inside the block.
+    pub fn connect_with(&self) -> Option<&str> {
+        self.comment_value("connect-with")
+    }
+
+    /// For a pattern block, whether it applies to `alias`: some pattern
+    /// matches it and no `!` pattern does.
+    pub fn pattern_applies_to(&self, alias: &str) -> bool {
+        let mut matched = false;
+        for pattern in self.host.split_whitespace() {
+            match pattern.strip_prefix('!') {
+                Some(negated) if pattern_matches(negated, alias) => return false,
+                Some(_) => {}
+                None => matched |= pattern_matches(pattern, alias),
+            }
+        }
+        matched
+    }
+
+    /// Whether connections go through another host, via a `ProxyJump` or a
+    /// `ProxyCommand` other than `none`.
+    pub fn uses_proxy(&self) -> bool {
+        ["ProxyCommand", "ProxyJump"].iter().any(|keyword| {
+            let value = self.get(keyword).trim();
+            !value.is_empty() && !value.eq_ignore_ascii_case("none")
+        })
+    }
+
+    /// Whether the host comes from the system config and must not be
+    /// written back.
+    pub fn is_read_only(&self) -> bool {
+        self.origin == Origin::System
+    }
+
+    /// Tags from a `# tags: prod db` comment inside the block.
+    pub fn tags(&self) -> Vec<&str> {
+        self.comment_value("tags")
+            .map(|value| value.split_whitespace().collect())
+            .unwrap_or_default()
+    }
+
+    /// Rewrites the `# tags:` comment in place, adds one after the last
+    /// directive, or drops it when `tags` is empty.
+    pub fn set_tags(&mut self, tags: &[&str]) {
+        let position = self.directives.iter().position(|(keyword, line)| {
+            keyword.is_empty() && comment_field(line, "tags").is_some()
+        });
+        match (position, tags.is_empty()) {
+            (Some(index), true) => {
+                self.directives.remove(index);
+            }
+            (Some(index), false) => {
+                let line = &mut self.directives[index].1;
+                let indent = &line[..line.len() - line.trim_start().len()];
+                *line = format!("{}# tags: {}", indent, tags.join(" "));
+            }
+            (None, true) => {}
+            (None, false) => {
+                self.directives.insert(
+                    self.after_last_directive(),
+                    (
+                        String::new(),
+                        format!("{}# tags: {}", DEFAULT_INDENT, tags.join(" ")),
+                    ),
+                );
+            }
+        }
+    }
+
+    /// The text of every `# note:` comment in the block, joined with a
+    /// space in file order.
+    pub fn note(&self) -> String {
+        self.directives
+            .iter()
+            .filter(|(keyword, _)| keyword.is_empty())
+            .filter_map(|(_, line)| comment_field(line, "note"))
+            .filter(|note| !note.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Replaces the note. An unchanged note leaves its lines as they are;
+    /// otherwise the first `# note:` line takes the new text in place and
+    /// the rest are dropped. A new note goes after the last directive.
+    pub fn set_note(&mut self, note: &str) {
+        let note = note.trim();
+        if self.note() == note {
+            return;
+        }
+        let is_note = |(keyword, line): &(String, String)| {
+            keyword.is_empty() && comment_field(line, "note").is_some()
+        };
+        let Some(first) = self.directives.iter().position(is_note) else {
+            self.directives.insert(
+                self.after_last_directive(),
+                (String::new(), format!("{}# note: {}", DEFAULT_INDENT, note)),
+            );
+            return;
+        };
+        let line = &self.directives[first].1;
+        let indent = &line[..line.len() - line.trim_start().len()];
+        let replacement = (String::new(), format!("{}# note: {}", indent, note));
+        self.directives.retain(|directive| !is_note(directive));
+        if !note.is_empty() {
+            self.directives.insert(first, replacement);
+        }
+    }
+
+    /// Where a new comment line goes: right after the last directive.
+    fn after_last_directive(&self) -> usize {
+        self.directives
+            .iter()
+            .rposition(|(keyword, _)| !keyword.is_empty())
+            .map_or(0, |index| index + 1)
+    }
+
+    /// The HostName, or the alias when none is set.
+    pub fn target_hostname(&self) -> &str {
+        match self.hostname() {
+            "" => self.host.trim(),
+            hostname => hostname,
+        }
+    }
+
+    /// The `user@hostname:port` this entry resolves to, falling back to the
+    /// alias when no HostName is set and ssh's default port 22.
+    pub fn connection_target(&self) -> String {
+        let port = match self.port() {
+            "" => "22",
+            port => port,
+        };
+        let hostname = bracket_ipv6(self.target_hostname());
+        if self.user().is_empty() {
+            format!("{}:{}", hostname, port)
+        } else {
+            format!("{}@{}:{}", self.user(), hostname, port)
+        }
+    }
+
+    /// A standalone ssh command line reaching this host's user, address and
+    /// port without relying on the config file, as the details pane shows
+    /// it. IPv6 literals force `-6` and are bracketed when a port is given.
+    pub fn ssh_command(&self) -> String {
+        let hostname = self.target_hostname();
+        let port = self.port();
+
+        let mut parts = vec!["ssh".to_string()];
+        if is_ipv6_literal(hostname) {
+            parts.push("-6".to_string());
+        }
+        if !port.is_empty() {
+            parts.push(format!("-p {}", port));
+        }
+        let target = if port.is_empty() {
+            hostname.to_string()
+        } else {
+            bracket_ipv6(hostname)
+        };
+        if self.user().is_empty() {
+            parts.push(target);
+        } else {
+            parts.push(format!("{}@{}", self.user(), target));
+        }
+        parts.join(" ")
+    }
+
+    /// A copy with surrounding whitespace removed from the alias and every
+    /// directive value.
+    pub fn trimmed(&self) -> Self {
+        Self {
+            host: self.host.trim().to_string(),
+            directives: self
+                .directives
+                .iter()
+                .map(|(keyword, value)| {
+                    if keyword.is_empty() {
+                        (keyword.clone(), value.clone())
+                    } else {
+                        (keyword.clone(), value.trim().to_string())
+                    }
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Directive names of the known fields this entry sets.
+    fn field_keywords(&self) -> Vec<&'static str> {
+        KnownField::ALL
+            .into_iter()
+            .filter(|&field| !self.field(field).is_empty())
+            .map(KnownField::keyword)
+            .collect()
+    }
+
+    fn directive_values<'a>(&'a self, keyword: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.directives
+            .iter()
+            .filter(move |(name, _)| !name.is_empty() && name.eq_ignore_ascii_case(keyword))
+            .map(|(_, value)| strip_inline_comment(value).trim())
+    }
+
+    fn comment_value(&self, key: &str) -> Option<&str> {
+        self.directives
+            .iter()
+            .filter(|(keyword, _)| keyword.is_empty())
+            .find_map(|(_, line)| comment_field(line, key))
+    }
+}
+
+/// The value of a `# key: value` comment line.
+fn comment_field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let comment = line.trim_start().strip_prefix('#')?.trim_start();
+    let (name, value) = comment.split_once(':')?;
+    name.trim().eq_ignore_ascii_case(key).then(|| value.trim())
+}
+
+/// JSON view used by `--list-json`: the known fields by name plus every
+/// directive in order.
+impl Serialize for HostEntry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let directives: Vec<(&str, &str)> = self
+            .directives
+            .iter()
+            .filter(|(keyword, value)| !keyword.is_empty() && !value.trim().is_empty())
+            .map(|(keyword, value)| (keyword.as_str(), value.trim()))
+            .collect();
+        let mut state = serializer.serialize_struct("HostEntry", 8)?;
+        state.serialize_field("host", &self.host)?;
+        state.serialize_field("hostname", self.hostname())?;
+        state.serialize_field("user", self.user())?;
+        state.serialize_field("port", self.port())?;
+        state.serialize_field("identity_file", self.identity_file())?;
+        state.serialize_field("proxy_command", self.proxy_command())?;
+        state.serialize_field("directives", &directives)?;
+        state.serialize_field("disabled", &self.disabled)?;
+        state.end()
+    }
+}
+
+/// What the config file looked like when it was last parsed. A reload
+/// with the same stamp can keep the hosts it already has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfigStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+/// The current stamp of `path`, or `None` when it cannot be read (missing
+/// file, or a filesystem without modification times).
+pub fn config_stamp(path: &Path) -> Option<ConfigStamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(ConfigStamp {
+        modified: metadata.modified().ok()?,
+        len: metadata.len(),
+    })
 }
 
 #[allow(dead_code)]
@@ -49,74 +588,341 @@ pub fn load_host_entries() -> Result<Vec<HostEntry>> {
 }
 
 pub fn load_host_entries_from_path(path: &Path) -> Result<Vec<HostEntry>> {
-    if !path.exists() {
-        return Ok(Vec::new());
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            let err = anyhow::Error::new(err).context("Failed to read SSH config file");
+            log::warn(&format!("Failed to parse {}: {:#}", path.display(), err));
+            return Err(err);
+        }
+    };
+    Ok(parse_host_entries(&contents))
+}
+
+/// The hosts of `contents` in ssh_config syntax, e.g. a pasted snippet.
+/// Pattern blocks and parser warnings are left out, as when loading a file.
+pub fn parse_host_entries(contents: &str) -> Vec<HostEntry> {
+    HostEntries::new(contents.lines().map(str::to_string).collect())
+        .map_while(Result::ok)
+        .collect()
+}
+
+/// Everything one parse of a config yields.
+#[derive(Debug, Default)]
+pub struct ParsedConfig {
+    pub hosts: Vec<HostEntry>,
+    /// Pattern blocks such as `Host *`, in file order.
+    pub patterns: Vec<HostEntry>,
+    /// What the parser passed over: `Match` lines and keywords ssh would
+    /// not recognise. Each warning names its line.
+    pub warnings: Vec<String>,
+}
+
+/// Like [`load_host_entries_from_path`], also returning the pattern blocks
+/// and parser warnings.
+pub fn load_config(path: &Path) -> Result<ParsedConfig> {
+    let mut entries = host_entries(path);
+    let hosts = entries.by_ref().collect::<Result<_>>().inspect_err(|err| {
+        log::warn(&format!("Failed to parse {}: {:#}", path.display(), err));
+    })?;
+    Ok(ParsedConfig {
+        hosts,
+        patterns: entries.patterns,
+        warnings: entries.warnings,
+    })
+}
+
+/// Hosts from the system-wide config, tagged [`Origin::System`]. A missing
+/// or unreadable file has no hosts, and its parse warnings are dropped:
+/// the file is not the user's to fix.
+pub fn load_system_host_entries(path: &Path) -> Vec<HostEntry> {
+    host_entries(path)
+        .map_while(Result::ok)
+        .map(|entry| HostEntry {
+            origin: Origin::System,
+            ..entry
+        })
+        .collect()
+}
+
+/// Parses `path`, yielding each host once its block ends. A missing file
+/// yields nothing; a read error is yielded once and ends the scan.
+pub fn iter_host_entries(path: &Path) -> impl Iterator<Item = Result<HostEntry>> {
+    host_entries(path)
+}
+
+fn host_entries(path: &Path) -> HostEntries {
+    // Read whole, since telling a disabled block from a commented-out
+    // `Host` line inside an active one needs the lines after it.
+    let lines = fs::File::open(path).and_then(|file| BufReader::new(file).lines().collect());
+    match lines {
+        Ok(lines) => HostEntries::new(lines),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => HostEntries::new(Vec::new()),
+        Err(err) => HostEntries {
+            pending_error: Some(err),
+            ..HostEntries::new(Vec::new())
+        },
     }
+}
 
-    let contents = fs::read_to_string(path).context("Failed to read SSH config file")?;
+struct HostEntries {
+    lines: Vec<String>,
+    /// How many of `lines` have been fed.
+    position: usize,
+    /// An error reading the file, reported by the first `next`.
+    pending_error: Option<io::Error>,
+    current: Option<HostEntry>,
+    line_number: usize,
+    /// Finished pattern blocks, kept aside from the hosts yielded.
+    patterns: Vec<HostEntry>,
+    warnings: Vec<String>,
+}
 
-    let mut entries = Vec::new();
-    let mut current: Option<HostEntry> = None;
+impl HostEntries {
+    fn new(lines: Vec<String>) -> Self {
+        Self {
+            lines,
+            position: 0,
+            pending_error: None,
+            current: None,
+            line_number: 0,
+            patterns: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
 
-    for raw_line in contents.lines() {
-        let trimmed_start = raw_line.trim_start();
-        if trimmed_start.starts_with('#') {
-            if let Some(entry) = current.as_mut() {
-                entry.extra.push(raw_line.trim_end().to_string());
+    /// Feeds the line at `index` to the block being built, returning the
+    /// previous block when that line starts a new one.
+    fn feed(&mut self, index: usize) -> Option<HostEntry> {
+        self.line_number += 1;
+        let header = block_header(&self.lines, index);
+        let raw_line = self.lines[index].clone();
+        let raw_line = raw_line.as_str();
+        if let Some((host_name, disabled)) = header {
+            let finished = self.finish_block();
+            if !is_host_pattern(&host_name) {
+                self.current = Some(HostEntry {
+                    disabled,
+                    ..HostEntry::new(&host_name)
+                });
+            } else if !disabled {
+                let patterns = host_patterns_from_line(raw_line).unwrap_or_default();
+                self.current = Some(HostEntry {
+                    is_pattern: true,
+                    ..HostEntry::new(&patterns.join(" "))
+                });
             }
-            continue;
+            return finished;
         }
 
-        let line = strip_inline_comment(raw_line).trim();
-        if line.is_empty() {
-            if let Some(entry) = current.as_mut() {
-                entry.extra.push(String::new());
-            }
-            continue;
+        let keyword = directive_keyword(raw_line);
+        if keyword.eq_ignore_ascii_case("match") {
+            let warning = match &self.current {
+                Some(entry) if !entry.disabled => format!(
+                    "Match is not supported; the lines below it are read as part of '{}'",
+                    entry.host
+                ),
+                Some(_) => return self.add_line(raw_line),
+                None => "Match is not supported; its block is skipped".to_string(),
+            };
+            self.warn(warning);
+        } else if !keyword.is_empty()
+            && self.current.as_ref().is_some_and(|entry| !entry.disabled)
+            && !SSH_KEYWORDS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(keyword))
+        {
+            self.warn(format!("unknown keyword '{}' kept as is", keyword));
+        }
+        self.add_line(raw_line)
+    }
+
+    /// Ends the current block: a host is handed back, a pattern block is
+    /// set aside.
+    fn finish_block(&mut self) -> Option<HostEntry> {
+        let finished = self.current.take()?;
+        if finished.is_pattern {
+            self.patterns.push(finished);
+            return None;
         }
+        Some(finished).filter(|entry| !entry.host.is_empty())
+    }
 
-        let mut parts = line.split_whitespace();
-        let keyword = parts.next().unwrap_or("");
+    fn add_line(&mut self, raw_line: &str) -> Option<HostEntry> {
+        let entry = self.current.as_mut()?;
+        if entry.disabled {
+            let inner = uncomment_line(raw_line);
+            apply_directive_line(entry, &inner);
+        } else {
+            apply_directive_line(entry, raw_line);
+        }
+        None
+    }
 
-        if keyword.eq_ignore_ascii_case("host") {
-            if let Some(entry) = current.take() {
-                if !entry.host.is_empty() {
-                    entries.push(entry);
-                }
-            }
+    fn warn(&mut self, message: String) {
+        self.warnings
+            .push(format!("line {}: {}", self.line_number, message));
+    }
+}
 
-            if let Some(host_name) = parts.next() {
-                if host_name.contains('*') || host_name.contains('?') {
-                    current = None;
-                } else {
-                    current = Some(HostEntry {
-                        host: host_name.to_string(),
-                        ..HostEntry::default()
-                    });
-                }
-            } else {
-                current = None;
-            }
-        } else if let Some(entry) = current.as_mut() {
-            let value = parts.collect::<Vec<_>>().join(" ");
-            match keyword.to_ascii_lowercase().as_str() {
-                "hostname" => entry.hostname = value,
-                "user" => entry.user = value,
-                "port" => entry.port = value,
-                "identityfile" => entry.identity_file = value,
-                "proxycommand" => entry.proxy_command = value,
-                _ => entry.extra.push(raw_line.trim_end().to_string()),
+/// The keyword of an active directive line; empty for blanks and comments.
+fn directive_keyword(raw_line: &str) -> &str {
+    let trimmed = raw_line.trim();
+    if trimmed.starts_with('#') {
+        return "";
+    }
+    let end = trimmed
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(trimmed.len());
+    &trimmed[..end]
+}
+
+/// Client keywords from ssh_config(5), plus a few deprecated or
+/// vendor-specific ones ssh still accepts.
+const SSH_KEYWORDS: &[&str] = &[
+    "AddKeysToAgent",
+    "AddressFamily",
+    "BatchMode",
+    "BindAddress",
+    "BindInterface",
+    "CanonicalDomains",
+    "CanonicalizeFallbackLocal",
+    "CanonicalizeHostname",
+    "CanonicalizeMaxDots",
+    "CanonicalizePermittedCNAMEs",
+    "CASignatureAlgorithms",
+    "CertificateFile",
+    "ChallengeResponseAuthentication",
+    "ChannelTimeout",
+    "CheckHostIP",
+    "Ciphers",
+    "ClearAllForwardings",
+    "Compression",
+    "ConnectionAttempts",
+    "ConnectTimeout",
+    "ControlMaster",
+    "ControlPath",
+    "ControlPersist",
+    "DynamicForward",
+    "EnableEscapeCommandline",
+    "EnableSSHKeysign",
+    "EscapeChar",
+    "ExitOnForwardFailure",
+    "FingerprintHash",
+    "ForkAfterAuthentication",
+    "ForwardAgent",
+    "ForwardX11",
+    "ForwardX11Timeout",
+    "ForwardX11Trusted",
+    "GatewayPorts",
+    "GlobalKnownHostsFile",
+    "GSSAPIAuthentication",
+    "GSSAPIDelegateCredentials",
+    "HashKnownHosts",
+    "Host",
+    "HostbasedAcceptedAlgorithms",
+    "HostbasedAuthentication",
+    "HostbasedKeyTypes",
+    "HostKeyAlgorithms",
+    "HostKeyAlias",
+    "HostName",
+    "IdentitiesOnly",
+    "IdentityAgent",
+    "IdentityFile",
+    "IgnoreUnknown",
+    "Include",
+    "IPQoS",
+    "KbdInteractiveAuthentication",
+    "KbdInteractiveDevices",
+    "KexAlgorithms",
+    "KnownHostsCommand",
+    "LocalCommand",
+    "LocalForward",
+    "LogLevel",
+    "LogVerbose",
+    "MACs",
+    "NoHostAuthenticationForLocalhost",
+    "NumberOfPasswordPrompts",
+    "ObscureKeystrokeTiming",
+    "PasswordAuthentication",
+    "PermitLocalCommand",
+    "PermitRemoteOpen",
+    "PKCS11Provider",
+    "Port",
+    "PreferredAuthentications",
+    "ProxyCommand",
+    "ProxyJump",
+    "ProxyUseFdpass",
+    "PubkeyAcceptedAlgorithms",
+    "PubkeyAcceptedKeyTypes",
+    "PubkeyAuthentication",
+    "RekeyLimit",
+    "RemoteCommand",
+    "RemoteForward",
+    "RequestTTY",
+    "RequiredRSASize",
+    "RevokedHostKeys",
+    "SecurityKeyProvider",
+    "SendEnv",
+    "ServerAliveCountMax",
+    "ServerAliveInterval",
+    "SessionType",
+    "SetEnv",
+    "StdinNull",
+    "StreamLocalBindMask",
+    "StreamLocalBindUnlink",
+    "StrictHostKeyChecking",
+    "SyslogFacility",
+    "Tag",
+    "TCPKeepAlive",
+    "Tunnel",
+    "TunnelDevice",
+    "UpdateHostKeys",
+    "UseKeychain",
+    "User",
+    "UserKnownHostsFile",
+    "VerifyHostKeyDNS",
+    "VisualHostKey",
+    "XAuthLocation",
+];
+
+impl Iterator for HostEntries {
+    type Item = Result<HostEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.pending_error.take() {
+            return Some(Err(err).context("Failed to read SSH config file"));
+        }
+        while self.position < self.lines.len() {
+            let index = self.position;
+            self.position += 1;
+            if let Some(entry) = self.feed(index) {
+                return Some(Ok(entry));
             }
         }
+        self.finish_block().map(Ok)
     }
+}
 
-    if let Some(entry) = current {
-        if !entry.host.is_empty() {
-            entries.push(entry);
-        }
+fn apply_directive_line(entry: &mut HostEntry, raw_line: &str) {
+    let trimmed = raw_line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        entry
+            .directives
+            .push((String::new(), raw_line.trim_end().to_string()));
+        return;
     }
 
-    Ok(entries)
+    // `Keyword value` and `Keyword=value` are both valid ssh syntax.
+    let split = trimmed
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(trimmed.len());
+    let (keyword, rest) = trimmed.split_at(split);
+    let value = rest.trim_start().strip_prefix('=').unwrap_or(rest).trim();
+    entry
+        .directives
+        .push((keyword.to_string(), value.to_string()));
 }
 
 #[allow(dead_code)]
@@ -125,15 +931,24 @@ pub fn add_host_entry(entry: &HostEntry) -> Result<()> {
     add_host_entry_at_path(&path, entry)
 }
 
-#[allow(dead_code)]
 pub fn add_host_entry_at_path(path: &Path, entry: &HostEntry) -> Result<()> {
+    add_host_entry_matching(path, entry, AliasMatch::Exact)
+}
+
+/// Like [`add_host_entry_at_path`], refusing aliases that already exist
+/// under `matching`.
+pub fn add_host_entry_matching(path: &Path, entry: &HostEntry, matching: AliasMatch) -> Result<()> {
     entry.validate()?;
     let mut lines = read_config_lines(path)?;
 
-    if find_host_block(&lines, &entry.host).is_some() {
+    if find_host_block(&lines, &entry.host, matching).is_some() {
         anyhow::bail!("Host '{}' already exists", entry.host);
     }
 
+    if let Some(shadowed) = check_not_shadowed(&lines, entry, &entry.field_keywords(), lines.len())?
+    {
+        anyhow::bail!(shadowed);
+    }
     append_block(&mut lines, entry);
     write_config_lines(path, &lines)
 }
@@ -147,15 +962,25 @@ pub fn upsert_host_entry_at_path(path: &Path, entry: &HostEntry) -> Result<()> {
     entry.validate()?;
     let mut lines = read_config_lines(path)?;
 
-    if let Some((start, end)) = find_host_block(&lines, &entry.host) {
+    if let Some((start, end)) = find_host_block(&lines, &entry.host, AliasMatch::Exact) {
+        let changed = changed_field_keywords(&lines[start..end], entry);
+        if let Some(shadowed) = check_not_shadowed(&lines, entry, &changed, start)? {
+            log::warn(&shadowed);
+        }
         replace_block(&mut lines, start, end, entry);
     } else {
+        if let Some(shadowed) =
+            check_not_shadowed(&lines, entry, &entry.field_keywords(), lines.len())?
+        {
+            anyhow::bail!(shadowed);
+        }
         append_block(&mut lines, entry);
     }
 
     write_config_lines(path, &lines)
 }
 
+#[allow(dead_code)]
 pub fn update_host_entry(original_host: &str, entry: &HostEntry) -> Result<()> {
     let path = Config::ssh_config_path()?;
     update_host_entry_at_path(&path, original_host, entry)
@@ -166,16 +991,102 @@ pub fn update_host_entry_at_path(
     original_host: &str,
     entry: &HostEntry,
 ) -> Result<()> {
+    update_host_entry_matching(path, original_host, entry, AliasMatch::Exact)
+}
+
+/// Like [`update_host_entry_at_path`], finding `original_host` under
+/// `matching`. A miss still appends a new block.
+pub fn update_host_entry_matching(
+    path: &Path,
+    original_host: &str,
+    entry: &HostEntry,
+    matching: AliasMatch,
+) -> Result<()> {
+    update_host_entry_occurrence(path, original_host, 0, entry, matching).map(|_| ())
+}
+
+/// Like [`update_host_entry_matching`], replacing the `occurrence`-th
+/// (zero-based) active block of `original_host` when it is defined more
+/// than once. The update is written even when an earlier pattern block
+/// already sets a field it changes; the warning for that is returned.
+pub fn update_host_entry_occurrence(
+    path: &Path,
+    original_host: &str,
+    occurrence: usize,
+    entry: &HostEntry,
+    matching: AliasMatch,
+) -> Result<Option<String>> {
     entry.validate()?;
     let mut lines = read_config_lines(path)?;
 
-    if let Some((start, end)) = find_host_block(&lines, original_host) {
+    let warning = if let Some((start, end)) =
+        find_nth_block(&lines, original_host, false, matching, occurrence)
+    {
+        let changed = changed_field_keywords(&lines[start..end], entry);
+        let warning = check_not_shadowed(&lines, entry, &changed, start)?;
         replace_block(&mut lines, start, end, entry);
+        warning
     } else {
+        if let Some(shadowed) =
+            check_not_shadowed(&lines, entry, &entry.field_keywords(), lines.len())?
+        {
+            anyhow::bail!(shadowed);
+        }
         append_block(&mut lines, entry);
-    }
+        None
+    };
 
-    write_config_lines(path, &lines)
+    write_config_lines(path, &lines)?;
+    Ok(warning)
+}
+
+/// The lines of the `occurrence`-th (zero-based) active block of `host` as
+/// they stand in the file, without the blank lines that separate it from
+/// the next block.
+pub fn read_host_block(
+    path: &Path,
+    host: &str,
+    occurrence: usize,
+    matching: AliasMatch,
+) -> Result<Vec<String>> {
+    let lines = read_config_lines(path)?;
+    let (start, end) = find_nth_block(&lines, host, false, matching, occurrence)
+        .with_context(|| format!("Host '{}' not found in SSH config", host))?;
+    let (content, _) = split_trailing_blanks(&lines[start..end]);
+    Ok(content.to_vec())
+}
+
+/// Parses a hand-edited block back into an entry. The text must hold
+/// exactly one `Host` block for a single alias, starting on its first
+/// non-blank line; trailing blank lines are dropped.
+pub fn parse_host_block(text: &str) -> Result<HostEntry> {
+    let lines: Vec<&str> = text
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .collect();
+    let Some((header, body)) = lines.split_first() else {
+        anyhow::bail!("The block is empty; it must start with a Host line");
+    };
+    let Some(host) = host_name_from_line(header) else {
+        anyhow::bail!("The block must start with a Host line");
+    };
+
+    let content = body
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(0, |index| index + 1);
+    let mut entry = HostEntry::new(&host);
+    for line in &body[..content] {
+        let keyword = directive_keyword(line);
+        if keyword.eq_ignore_ascii_case("host") || keyword.eq_ignore_ascii_case("match") {
+            anyhow::bail!(
+                "Only one Host block can be edited at a time; remove the {} line",
+                keyword
+            );
+        }
+        apply_directive_line(&mut entry, line);
+    }
+    Ok(entry)
 }
 
 #[allow(dead_code)]
@@ -184,11 +1095,229 @@ pub fn delete_host_entry(host: &str) -> Result<()> {
     delete_host_entry_at_path(&path, host)
 }
 
-#[allow(dead_code)]
+/// Disables the `occurrence`-th (zero-based) active block of `host` by
+/// prefixing every non-blank line with `# `.
+pub fn comment_host_block_occurrence(
+    path: &Path,
+    host: &str,
+    occurrence: usize,
+    matching: AliasMatch,
+) -> Result<()> {
+    let mut lines = read_config_lines(path)?;
+    let (start, end) = find_nth_block(&lines, host, false, matching, occurrence)
+        .with_context(|| format!("Host '{}' not found", host))?;
+
+    for line in &mut lines[start..end] {
+        if !line.trim().is_empty() {
+            *line = format!("# {}", line);
+        }
+    }
+    write_config_lines(path, &lines)
+}
+
+/// Re-enables the `occurrence`-th disabled block of `host`, as disabled by
+/// [`comment_host_block_occurrence`], removing exactly one leading `#` (and
+/// the space after it) from each line. Blocks commented by hand as `#Host`
+/// only lose the `#`, keeping their indent.
+pub fn uncomment_host_block_occurrence(
+    path: &Path,
+    host: &str,
+    occurrence: usize,
+    matching: AliasMatch,
+) -> Result<()> {
+    let mut lines = read_config_lines(path)?;
+    let (start, end) = find_nth_block(&lines, host, true, matching, occurrence)
+        .with_context(|| format!("Disabled host '{}' not found", host))?;
+
+    let marker = if lines[start].trim_start().starts_with("# ") {
+        "# "
+    } else {
+        "#"
+    };
+    for line in &mut lines[start..end] {
+        *line = strip_comment_marker(line, marker);
+    }
+    write_config_lines(path, &lines)
+}
+
+/// Writes `entries` to `path` as a fresh config, replacing whatever the
+/// file held. Disabled hosts are written commented out, as the live config
+/// keeps them.
+pub fn export_host_entries(path: &Path, entries: &[HostEntry]) -> Result<()> {
+    let mut lines = Vec::new();
+    for entry in entries {
+        let mut block = render_host_entry_lines(entry);
+        if entry.disabled {
+            for line in block.iter_mut().filter(|line| !line.trim().is_empty()) {
+                *line = format!("# {}", line);
+            }
+        }
+        lines.extend(block);
+    }
+    write_config_lines(path, &lines)
+}
+
+/// What a merge does with an incoming host whose alias is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    Skip,
+    Overwrite,
+    /// Adds the host under the first free `<alias>-N`, from `-2` up.
+    Rename,
+}
+
+impl ConflictPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "skip" => Some(ConflictPolicy::Skip),
+            "overwrite" => Some(ConflictPolicy::Overwrite),
+            "rename" => Some(ConflictPolicy::Rename),
+            _ => None,
+        }
+    }
+}
+
+/// How many hosts a merge added, replaced and left out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+impl fmt::Display for MergeSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} added, {} updated, {} skipped",
+            self.added, self.updated, self.skipped
+        )
+    }
+}
+
+/// Upserts the enabled hosts of `incoming` into the config at `path`.
+/// `resolve` decides each alias collision; renamed hosts count as added.
+/// Commented-out incoming hosts are left behind.
+pub fn merge_host_entries(
+    path: &Path,
+    incoming: &[HostEntry],
+    mut resolve: impl FnMut(&HostEntry) -> ConflictPolicy,
+) -> Result<MergeSummary> {
+    let mut taken: HashSet<String> = load_host_entries_from_path(path)?
+        .into_iter()
+        .filter(|entry| !entry.disabled)
+        .map(|entry| entry.host)
+        .collect();
+    let mut summary = MergeSummary::default();
+
+    for entry in incoming.iter().filter(|entry| !entry.disabled) {
+        let result = if !taken.contains(&entry.host) {
+            summary.added += 1;
+            add_host_entry_at_path(path, entry)
+        } else {
+            match resolve(entry) {
+                ConflictPolicy::Skip => {
+                    summary.skipped += 1;
+                    Ok(())
+                }
+                ConflictPolicy::Overwrite => {
+                    summary.updated += 1;
+                    upsert_host_entry_at_path(path, entry)
+                }
+                ConflictPolicy::Rename => {
+                    let renamed = HostEntry {
+                        host: free_alias(&entry.host, &taken),
+                        ..entry.clone()
+                    };
+                    summary.added += 1;
+                    let result = add_host_entry_at_path(path, &renamed);
+                    taken.insert(renamed.host);
+                    result
+                }
+            }
+        };
+        result.with_context(|| format!("Failed to merge host '{}'", entry.host))?;
+        taken.insert(entry.host.clone());
+    }
+    Ok(summary)
+}
+
+/// The first of `<alias>-2`, `<alias>-3`, ... not in `taken`.
+fn free_alias(alias: &str, taken: &HashSet<String>) -> String {
+    (2..)
+        .map(|n| format!("{}-{}", alias, n))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap_or_default()
+}
+
+/// Which neighbour a block trades places with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveDirection {
+    Up,
+    Down,
+}
+
+/// Swaps the `occurrence`-th active block of `host` with the adjacent block
+/// above or below it, keeping the blank-line spacing between and after the
+/// two blocks.
+pub fn move_host_block_occurrence(
+    path: &Path,
+    host: &str,
+    occurrence: usize,
+    matching: AliasMatch,
+    direction: MoveDirection,
+) -> Result<()> {
+    let mut lines = read_config_lines(path)?;
+    let (start, end) = find_nth_block(&lines, host, false, matching, occurrence)
+        .with_context(|| format!("Host '{}' not found", host))?;
+
+    let ((first_start, first_end), (second_start, second_end)) = match direction {
+        MoveDirection::Up => {
+            let previous = (0..start)
+                .rev()
+                .find(|&index| block_header(&lines, index).is_some())
+                .with_context(|| format!("Host '{}' is already first", host))?;
+            ((previous, start), (start, end))
+        }
+        MoveDirection::Down => {
+            if end >= lines.len() {
+                anyhow::bail!("Host '{}' is already last", host);
+            }
+            let next_end = (end + 1..lines.len())
+                .find(|&index| block_header(&lines, index).is_some())
+                .unwrap_or(lines.len());
+            ((start, end), (end, next_end))
+        }
+    };
+
+    let (first, gap) = split_trailing_blanks(&lines[first_start..first_end]);
+    let (second, tail) = split_trailing_blanks(&lines[second_start..second_end]);
+    let mut swapped = second.to_vec();
+    swapped.extend(gap.iter().cloned());
+    swapped.extend(first.iter().cloned());
+    swapped.extend(tail.iter().cloned());
+
+    lines.splice(first_start..second_end, swapped);
+    write_config_lines(path, &lines)
+}
+
+fn split_trailing_blanks(block: &[String]) -> (&[String], &[String]) {
+    let content = block
+        .iter()
+        .rposition(|line| !line.trim().is_empty())
+        .map_or(0, |index| index + 1);
+    block.split_at(content)
+}
+
 pub fn delete_host_entry_at_path(path: &Path, host: &str) -> Result<()> {
+    delete_host_entry_matching(path, host, AliasMatch::Exact)
+}
+
+/// Like [`delete_host_entry_at_path`], finding `host` under `matching`.
+pub fn delete_host_entry_matching(path: &Path, host: &str, matching: AliasMatch) -> Result<()> {
     let mut lines = read_config_lines(path)?;
 
-    if let Some((start, end)) = find_host_block(&lines, host) {
+    if let Some((start, end)) = find_host_block(&lines, host, matching) {
         remove_block(&mut lines, start, end);
         write_config_lines(path, &lines)
     } else {
@@ -196,6 +1325,192 @@ pub fn delete_host_entry_at_path(path: &Path, host: &str) -> Result<()> {
     }
 }
 
+/// Deletes several blocks, each given as an alias and which of its active
+/// blocks (zero-based) to remove, with a single read and write of the file.
+/// Every block is found before any is removed, so later occurrences of the
+/// same alias keep their numbers. Nothing is written unless all are found.
+pub fn delete_host_entry_occurrences(
+    path: &Path,
+    blocks: &[(String, usize)],
+    matching: AliasMatch,
+) -> Result<()> {
+    let mut lines = read_config_lines(path)?;
+    let mut ranges = blocks
+        .iter()
+        .map(|(host, occurrence)| {
+            find_nth_block(&lines, host, false, matching, *occurrence)
+                .with_context(|| format!("Host '{}' not found", host))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ranges.sort_unstable();
+    for (start, end) in ranges.into_iter().rev() {
+        remove_block(&mut lines, start, end);
+    }
+    write_config_lines(path, &lines)
+}
+
+/// True for IPv6 literals, with or without a `%zone` suffix.
+fn is_ipv6_literal(value: &str) -> bool {
+    let address = value.split_once('%').map_or(value, |(address, _)| address);
+    address.parse::<Ipv6Addr>().is_ok()
+}
+
+fn bracket_ipv6(host: &str) -> String {
+    if is_ipv6_literal(host) {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    }
+}
+
+/// Accepts IP literals (including IPv6 with a `%zone`) and DNS-like names
+/// made of letters, digits, `-`, `_` and `.`. `%` is allowed so ssh tokens
+/// such as `%h.internal` pass.
+pub fn is_plausible_hostname(value: &str) -> bool {
+    if value.parse::<IpAddr>().is_ok() || is_ipv6_literal(value) {
+        return true;
+    }
+
+    !value.is_empty()
+        && value.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '%'))
+        })
+}
+
+/// Matches an alias against an ssh_config style pattern where `*` matches
+/// any run of characters and `?` exactly one.
+pub fn pattern_matches(pattern: &str, alias: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let alias: Vec<char> = alias.chars().collect();
+    let (mut p, mut a) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while a < alias.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, a));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == alias[a] => {
+                p += 1;
+                a += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    a = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Returns the aliases of hosts whose `ProxyJump` or `ProxyCommand` points
+/// at `alias`, e.g. to warn about stale references after a rename.
+pub fn find_alias_references(entries: &[HostEntry], alias: &str) -> Vec<String> {
+    entries
+        .iter()
+        .filter(|entry| entry.host != alias)
+        .filter(|entry| {
+            let proxy_command = entry.proxy_command().split_whitespace();
+            let proxy_jump = entry
+                .directive_values("ProxyJump")
+                .flat_map(|value| value.split([',', ' ']))
+                .filter(|target| !target.is_empty());
+
+            proxy_command
+                .chain(proxy_jump)
+                .any(|target| target_host(target) == alias)
+        })
+        .map(|entry| entry.host.clone())
+        .collect()
+}
+
+fn check_port(port: &str) -> Result<()> {
+    let port_num: u16 = port
+        .parse()
+        .context("Port must be a number between 1 and 65535")?;
+    if port_num == 0 {
+        anyhow::bail!("Port must be greater than 0");
+    }
+    Ok(())
+}
+
+/// Problems in an otherwise loadable config that ssh would accept or
+/// silently work around: aliases without a HostName, aliases defined more
+/// than once, and unusable ports. Commented-out hosts are not checked.
+pub fn lint_hosts(entries: &[HostEntry]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut seen = HashSet::new();
+    let mut duplicates = HashSet::new();
+    for entry in entries.iter().filter(|entry| !entry.disabled) {
+        if !seen.insert(entry.host.as_str()) {
+            if duplicates.insert(entry.host.as_str()) {
+                warnings.push(format!(
+                    "'{}' is defined more than once; ssh only uses the first block",
+                    entry.host
+                ));
+            }
+            continue;
+        }
+        if entry.hostname().is_empty() {
+            warnings.push(format!(
+                "'{}' has no HostName; ssh connects to the alias itself",
+                entry.host
+            ));
+        }
+        if !entry.port().is_empty() {
+            if let Err(err) = check_port(entry.port()) {
+                warnings.push(format!(
+                    "'{}': Port '{}': {}",
+                    entry.host,
+                    entry.port(),
+                    err
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Aliases with more than one active block and how many each has, in the
+/// order they first appear.
+pub fn duplicate_aliases(entries: &[HostEntry]) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for entry in entries.iter().filter(|entry| !entry.disabled) {
+        match counts.iter_mut().find(|(alias, _)| *alias == entry.host) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((entry.host.clone(), 1)),
+        }
+    }
+    counts.retain(|&(_, count)| count > 1);
+    counts
+}
+
+/// Aliases that equal `alias` ignoring case but are spelled differently,
+/// i.e. blocks that would be near-duplicates of it.
+pub fn find_case_variants(entries: &[HostEntry], alias: &str) -> Vec<String> {
+    entries
+        .iter()
+        .filter(|entry| entry.host != alias && entry.host.eq_ignore_ascii_case(alias))
+        .map(|entry| entry.host.clone())
+        .collect()
+}
+
+/// Strips the optional `user@` prefix and `:port` suffix from a jump target.
+fn target_host(target: &str) -> &str {
+    let without_user = target.rsplit('@').next().unwrap_or(target);
+    without_user.split(':').next().unwrap_or(without_user)
+}
+
 fn read_config_lines(path: &Path) -> Result<Vec<String>> {
     if !path.exists() {
         return Ok(Vec::new());
@@ -203,21 +1518,95 @@ fn read_config_lines(path: &Path) -> Result<Vec<String>> {
 
     let contents = fs::read_to_string(path).context("Failed to read SSH config file")?;
 
-    Ok(contents.lines().map(|line| line.to_string()).collect())
+    Ok(contents
+        .lines()
+        .map(|line| line.trim_end_matches('\r').to_string())
+        .collect())
+}
+
+/// The terminator most lines in `contents` end with, so a file once saved
+/// on Windows keeps its CRLF endings when rewritten.
+fn detect_line_ending(contents: &str) -> &'static str {
+    let crlf = contents.matches("\r\n").count();
+    let lf = contents.matches('\n').count() - crlf;
+    if crlf > lf {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// The file a write to `path` lands in: the final target when `path` is a
+/// symlink, even a dangling one, otherwise `path` itself. Writing through
+/// the target keeps the link in place.
+pub fn resolve_config_target(path: &Path) -> PathBuf {
+    let Ok(link) = fs::read_link(path) else {
+        return path.to_path_buf();
+    };
+    fs::canonicalize(path).unwrap_or_else(|_| match path.parent() {
+        Some(parent) => parent.join(link),
+        None => link,
+    })
 }
 
 fn write_config_lines(path: &Path, lines: &[String]) -> Result<()> {
+    let target = resolve_config_target(path);
+    let path = target.as_path();
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).context("Failed to create SSH config directory")?;
+        if !parent.as_os_str().is_empty() && !parent.exists() {
+            create_private_dir(parent).context("Failed to create SSH config directory")?;
+        }
+    }
+
+    let original = fs::read_to_string(path).unwrap_or_default();
+    let line_ending = detect_line_ending(&original);
+    let final_newline = original.is_empty() || original.ends_with('\n');
+
+    // Keep at most one trailing blank line, and none when the file did not
+    // end with a newline to begin with.
+    let mut end = lines.len();
+    while end > 0 && lines[end - 1].trim().is_empty() {
+        end -= 1;
+    }
+    if final_newline && end < lines.len() {
+        end += 1;
+    }
+
+    let mut buffer = lines[..end].join(line_ending);
+    if final_newline && end > 0 {
+        buffer.push_str(line_ending);
+    }
+
+    if !path.exists() {
+        create_private_file(path).context("Failed to create SSH config file")?;
     }
+    fs::write(path, buffer).context("Failed to write SSH config file")?;
+    log::info(&format!("Wrote {} lines to {}", end, path.display()));
+    Ok(())
+}
 
-    let mut buffer = String::new();
-    for line in lines {
-        buffer.push_str(line);
-        buffer.push('\n');
+/// Creates the directory tree with mode 0700, as OpenSSH expects for `~/.ssh`.
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
     }
+    builder.create(dir)
+}
 
-    fs::write(path, buffer).context("Failed to write SSH config file")
+/// Creates an empty file readable only by its owner (mode 0600).
+fn create_private_file(path: &Path) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path).map(|_| ())
 }
 
 fn append_block(lines: &mut Vec<String>, entry: &HostEntry) {
@@ -228,10 +1617,130 @@ fn append_block(lines: &mut Vec<String>, entry: &HostEntry) {
 }
 
 fn replace_block(lines: &mut Vec<String>, start: usize, end: usize, entry: &HostEntry) {
-    lines.splice(start..end, render_host_entry_lines(entry));
+    let indent = block_indent(&lines[start..end]);
+    let mut rendered = render_host_entry_lines_with_indent(entry, &indent);
+
+    // Keep any extra patterns listed after the alias (`Host web web-*`).
+    let extra_patterns: Vec<String> = host_patterns_from_line(&lines[start])
+        .map(|patterns| patterns.into_iter().skip(1).collect())
+        .unwrap_or_default();
+    if !extra_patterns.is_empty() {
+        rendered[0] = format!("{} {}", rendered[0], extra_patterns.join(" "));
+    }
+
+    lines.splice(start..end, rendered);
+}
+
+/// Keywords ssh accumulates over every matching block instead of taking
+/// the first value, so an earlier pattern block can't shadow them.
+const CUMULATIVE_KEYWORDS: [&str; 6] = [
+    "IdentityFile",
+    "CertificateFile",
+    "LocalForward",
+    "RemoteForward",
+    "DynamicForward",
+    "SendEnv",
+];
+
+/// The field keywords whose values `entry` changes from the `block` it
+/// replaces.
+fn changed_field_keywords(block: &[String], entry: &HostEntry) -> Vec<&'static str> {
+    let Some(Ok(old)) = HostEntries::new(block.to_vec()).next() else {
+        return entry.field_keywords();
+    };
+    KnownField::ALL
+        .into_iter()
+        .filter(|&field| !entry.field(field).is_empty() && entry.field(field) != old.field(field))
+        .map(KnownField::keyword)
+        .collect()
+}
+
+/// Checks a write before `position` against the blocks above it. An alias
+/// already listed on another `Host` line would become a second,
+/// conflicting definition, which is an error. An earlier block whose
+/// patterns match the alias and already sets one of `wanted` wins under
+/// ssh's first-match rule; that is returned as a message for the caller to
+/// refuse or warn with.
+fn check_not_shadowed(
+    lines: &[String],
+    entry: &HostEntry,
+    wanted: &[&str],
+    position: usize,
+) -> Result<Option<String>> {
+    let alias = entry.host.trim();
+    let mut index = 0;
+    while index < position.min(lines.len()) {
+        let Some(patterns) = host_patterns_from_line(&lines[index]) else {
+            index += 1;
+            continue;
+        };
+        let header = lines[index].trim().to_string();
+        index += 1;
+
+        let mut set = Vec::new();
+        while index < lines.len() && block_header(lines, index).is_none() {
+            if let Some(keyword) = strip_inline_comment(&lines[index])
+                .split_whitespace()
+                .next()
+            {
+                set.push(keyword.to_ascii_lowercase());
+            }
+            index += 1;
+        }
+
+        if patterns.iter().skip(1).any(|pattern| pattern == alias) {
+            anyhow::bail!("Host '{}' is already listed in '{}'", alias, header);
+        }
+
+        let negated = patterns.iter().any(|pattern| {
+            pattern
+                .strip_prefix('!')
+                .is_some_and(|negated| pattern_matches(negated, alias))
+        });
+        let matched = patterns.iter().any(|pattern| {
+            !pattern.starts_with('!')
+                && (pattern.contains('*') || pattern.contains('?'))
+                && pattern_matches(pattern, alias)
+        });
+        if !matched || negated {
+            continue;
+        }
+
+        let shadowed: Vec<&str> = wanted
+            .iter()
+            .copied()
+            .filter(|keyword| {
+                !CUMULATIVE_KEYWORDS
+                    .iter()
+                    .any(|k| k.eq_ignore_ascii_case(keyword))
+            })
+            .filter(|keyword| set.contains(&keyword.to_ascii_lowercase()))
+            .collect();
+        if !shadowed.is_empty() {
+            return Ok(Some(format!(
+                "Host '{}' would be shadowed by earlier '{}', which already sets {}",
+                alias,
+                header,
+                shadowed.join(", ")
+            )));
+        }
+    }
+    Ok(None)
+}
+
+/// The leading whitespace of the block's first indented directive, so a
+/// tab-indented block stays tab-indented when rewritten.
+fn block_indent(block: &[String]) -> String {
+    block
+        .iter()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .find(|indent| !indent.is_empty())
+        .unwrap_or(DEFAULT_INDENT)
+        .to_string()
 }
 
-#[allow(dead_code)]
 fn remove_block(lines: &mut Vec<String>, start: usize, end: usize) {
     lines.drain(start..end);
 
@@ -254,28 +1763,22 @@ fn strip_inline_comment(line: &str) -> &str {
     }
 }
 
+const DEFAULT_INDENT: &str = "  ";
+
 fn render_host_entry_lines(entry: &HostEntry) -> Vec<String> {
+    render_host_entry_lines_with_indent(entry, DEFAULT_INDENT)
+}
+
+fn render_host_entry_lines_with_indent(entry: &HostEntry, indent: &str) -> Vec<String> {
     let mut lines = Vec::new();
     lines.push(format!("Host {}", entry.host.trim()));
 
-    if !entry.hostname.trim().is_empty() {
-        lines.push(format!("  HostName {}", entry.hostname.trim()));
-    }
-    if !entry.user.trim().is_empty() {
-        lines.push(format!("  User {}", entry.user.trim()));
-    }
-    if !entry.port.trim().is_empty() {
-        lines.push(format!("  Port {}", entry.port.trim()));
-    }
-    if !entry.identity_file.trim().is_empty() {
-        lines.push(format!("  IdentityFile {}", entry.identity_file.trim()));
-    }
-    if !entry.proxy_command.trim().is_empty() {
-        lines.push(format!("  ProxyCommand {}", entry.proxy_command.trim()));
-    }
-
-    for extra_line in &entry.extra {
-        lines.push(extra_line.clone());
+    for (keyword, value) in &entry.directives {
+        if keyword.is_empty() {
+            lines.push(value.clone());
+        } else if !value.trim().is_empty() {
+            lines.push(format!("{}{} {}", indent, keyword, value.trim()));
+        }
     }
 
     if !lines.last().map(|line| line.is_empty()).unwrap_or(false) {
@@ -286,6 +1789,11 @@ fn render_host_entry_lines(entry: &HostEntry) -> Vec<String> {
 }
 
 fn host_name_from_line(line: &str) -> Option<String> {
+    host_patterns_from_line(line)?.into_iter().next()
+}
+
+/// Every pattern on an active `Host` line, in order.
+fn host_patterns_from_line(line: &str) -> Option<Vec<String>> {
     let stripped = strip_inline_comment(line);
     let trimmed = stripped.trim_start();
     if trimmed.is_empty() || trimmed.starts_with('#') {
@@ -295,27 +1803,94 @@ fn host_name_from_line(line: &str) -> Option<String> {
     let mut parts = trimmed.split_whitespace();
     let keyword = parts.next()?;
     if keyword.eq_ignore_ascii_case("host") {
-        parts.next().map(|name| name.to_string())
+        let patterns: Vec<String> = parts.map(str::to_string).collect();
+        (!patterns.is_empty()).then_some(patterns)
     } else {
         None
     }
 }
 
-fn find_host_block(lines: &[String], host: &str) -> Option<(usize, usize)> {
+/// Whether a block's first `Host` pattern makes it a pattern block rather
+/// than an alias: a wildcard or a negation.
+fn is_host_pattern(first: &str) -> bool {
+    first.contains('*') || first.contains('?') || first.starts_with('!')
+}
+
+/// Recognizes the first line of a block: either an active `Host` line or a
+/// commented-out `# Host` line. The flag is true for the commented form,
+/// which only starts a disabled block when everything after it, up to the
+/// next active `Host` or `Match` line, is commented out too. Followed by
+/// active lines, it is a comment inside the block being read, such as
+/// `# Host b is the old name`.
+fn block_header(lines: &[String], index: usize) -> Option<(String, bool)> {
+    let line = &lines[index];
+    if let Some(name) = host_name_from_line(line) {
+        return Some((name, false));
+    }
+    let commented = line.trim_start().strip_prefix('#')?;
+    let name = host_name_from_line(commented)?;
+    let body_commented = lines[index + 1..]
+        .iter()
+        .map(|line| directive_keyword(line))
+        .take_while(|keyword| {
+            !keyword.eq_ignore_ascii_case("host") && !keyword.eq_ignore_ascii_case("match")
+        })
+        .all(str::is_empty);
+    body_commented.then_some((name, true))
+}
+
+fn uncomment_line(line: &str) -> String {
+    strip_comment_marker(line, "# ")
+}
+
+/// Removes `marker`, or failing that a bare `#`, after the indentation.
+fn strip_comment_marker(line: &str, marker: &str) -> String {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    match rest.strip_prefix(marker).or_else(|| rest.strip_prefix('#')) {
+        Some(uncommented) => format!("{}{}", &line[..indent], uncommented),
+        None => line.to_string(),
+    }
+}
+
+fn find_host_block(lines: &[String], host: &str, matching: AliasMatch) -> Option<(usize, usize)> {
+    find_block(lines, host, false, matching)
+}
+
+fn find_block(
+    lines: &[String],
+    host: &str,
+    disabled: bool,
+    matching: AliasMatch,
+) -> Option<(usize, usize)> {
+    find_nth_block(lines, host, disabled, matching, 0)
+}
+
+/// The `nth` (zero-based) block of `host`, for aliases defined twice.
+fn find_nth_block(
+    lines: &[String],
+    host: &str,
+    disabled: bool,
+    matching: AliasMatch,
+    mut nth: usize,
+) -> Option<(usize, usize)> {
     let mut index = 0;
     while index < lines.len() {
-        if let Some(name) = host_name_from_line(&lines[index]) {
+        if let Some((name, commented)) = block_header(lines, index) {
             let start = index;
             index += 1;
             while index < lines.len() {
-                if host_name_from_line(&lines[index]).is_some() {
+                if block_header(lines, index).is_some() {
                     break;
                 }
                 index += 1;
             }
 
-            if name == host {
-                return Some((start, index));
+            if matching.matches(&name, host) && commented == disabled {
+                if nth == 0 {
+                    return Some((start, index));
+                }
+                nth -= 1;
             }
         } else {
             index += 1;
@@ -346,17 +1921,15 @@ mod tests {
         assert_eq!(entries.len(), 2);
 
         let app = entries.iter().find(|e| e.host == "app-server").unwrap();
-        assert_eq!(app.hostname, "app.example.com");
-        assert_eq!(app.user, "deploy");
-        assert_eq!(app.port, "2222");
-        assert_eq!(app.identity_file, "~/.ssh/app_rsa");
-        assert_eq!(app.proxy_command, "ssh -W %h:%p bastion");
-        assert!(app.extra.iter().any(|line| line.contains("LocalForward")));
-        assert!(app
-            .extra
-            .iter()
-            .any(|line| line.contains("# inline comment")));
-        assert!(app.extra.iter().any(|line| line.contains("ForwardAgent")));
+        assert_eq!(app.hostname(), "app.example.com");
+        assert_eq!(app.user(), "deploy");
+        assert_eq!(app.port(), "2222");
+        assert_eq!(app.identity_file(), "~/.ssh/app_rsa");
+        assert_eq!(app.proxy_command(), "ssh -W %h:%p bastion");
+        let extra = app.extra_lines();
+        assert!(extra.iter().any(|line| line.contains("LocalForward")));
+        assert!(extra.iter().any(|line| line.contains("# inline comment")));
+        assert!(extra.iter().any(|line| line.contains("ForwardAgent")));
     }
 
     #[test]
@@ -369,6 +1942,64 @@ mod tests {
         assert!(entries.is_empty());
     }
 
+    #[test]
+    fn test_system_entries_are_tagged_and_may_be_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ssh_config");
+        assert!(load_system_host_entries(&path).is_empty());
+
+        fs::write(&path, "Host bastion\n  HostName 10.0.0.1\n").unwrap();
+        let entries = load_system_host_entries(&path);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].origin, Origin::System);
+        assert!(entries[0].is_read_only());
+        assert!(!HostEntry::new("web").is_read_only());
+    }
+
+    #[test]
+    fn test_iter_host_entries_yields_blocks_in_order() {
+        let path = fixture_path("sample_config");
+        let mut entries = iter_host_entries(&path);
+        let first = entries.next().unwrap().unwrap();
+        assert_eq!(first, load_host_entries_from_path(&path).unwrap()[0]);
+        assert_eq!(entries.count(), 1);
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut errors = iter_host_entries(dir.path());
+        assert!(errors.next().unwrap().is_err());
+        assert!(errors.next().is_none());
+    }
+
+    #[test]
+    fn test_alias_lookup_is_exact_unless_asked_otherwise() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(temp, "Host WebProd\n  HostName web.example.com\n").unwrap();
+        let entry = HostEntry::new("webprod").with("HostName", "new.example.com");
+
+        update_host_entry_at_path(temp.path(), "webprod", &entry).unwrap();
+        let entries = load_host_entries_from_path(temp.path()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(find_case_variants(&entries, "webprod"), vec!["WebProd"]);
+        assert!(delete_host_entry_at_path(temp.path(), "WEBPROD").is_err());
+    }
+
+    #[test]
+    fn test_case_insensitive_update_and_delete() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(temp, "Host WebProd\n  HostName web.example.com\n").unwrap();
+        let entry = HostEntry::new("webprod").with("HostName", "new.example.com");
+
+        update_host_entry_matching(temp.path(), "webprod", &entry, AliasMatch::IgnoreCase).unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host webprod\n  HostName new.example.com\n\n"
+        );
+        assert!(add_host_entry_matching(temp.path(), &entry, AliasMatch::IgnoreCase).is_err());
+
+        delete_host_entry_matching(temp.path(), "WEBPROD", AliasMatch::IgnoreCase).unwrap();
+        assert!(load_host_entries_from_path(temp.path()).unwrap().is_empty());
+    }
+
     #[test]
     fn test_upsert_preserves_unknown_directives() {
         let path = fixture_path("sample_config");
@@ -381,7 +2012,7 @@ mod tests {
             .into_iter()
             .find(|e| e.host == "app-server")
             .unwrap();
-        entry.hostname = "new.example.com".to_string();
+        entry.set("HostName", "new.example.com");
 
         update_host_entry_at_path(temp.path(), "app-server", &entry).unwrap();
 
@@ -391,6 +2022,73 @@ mod tests {
         assert!(file_contents.contains("HostName new.example.com"));
     }
 
+    #[test]
+    fn test_load_with_warnings_reports_skipped_lines() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Match host bastion\n  User admin\nHost web\n  hostname web.example.com\n  Colour blue\n# Host old\n#  Bogus yes\nHost web-*\n  User ops\nHost db\nMatch exec true\n"
+        )
+        .unwrap();
+
+        let parsed = load_config(temp.path()).unwrap();
+        let entries = parsed.hosts;
+        assert_eq!(entries, load_host_entries_from_path(temp.path()).unwrap());
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            parsed.warnings,
+            vec![
+                "line 1: Match is not supported; its block is skipped",
+                "line 5: unknown keyword 'Colour' kept as is",
+                "line 11: Match is not supported; the lines below it are read as part of 'db'",
+            ]
+        );
+        assert_eq!(entries[0].get("Colour"), "blue");
+        assert_eq!(parsed.patterns.len(), 1);
+        assert_eq!(parsed.patterns[0].host, "web-*");
+    }
+
+    #[test]
+    fn test_update_targets_duplicate_occurrence() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host web\n  HostName one.example.com\n\nHost db\n  HostName db.example.com\n\nHost web\n  HostName two.example.com\n"
+        )
+        .unwrap();
+        let entries = load_host_entries_from_path(temp.path()).unwrap();
+        assert_eq!(duplicate_aliases(&entries), vec![("web".to_string(), 2)]);
+
+        let mut second = entries[2].clone();
+        second.set("HostName", "new.example.com");
+        update_host_entry_occurrence(temp.path(), "web", 1, &second, AliasMatch::Exact).unwrap();
+
+        let contents = fs::read_to_string(temp.path()).unwrap();
+        assert!(contents.starts_with("Host web\n  HostName one.example.com\n"));
+        assert!(contents.contains("Host web\n  HostName new.example.com\n"));
+        assert!(!contents.contains("two.example.com"));
+    }
+
+    #[test]
+    fn test_upsert_keeps_full_line_comments_in_block() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host web\n  HostName old.example.com\n  # jump via bastion on Fridays\n  User deploy\n"
+        )
+        .unwrap();
+
+        let mut entry = load_host_entries_from_path(temp.path()).unwrap().remove(0);
+        assert_eq!(entry.extra_lines(), vec!["# jump via bastion on Fridays"]);
+        entry.set("HostName", "new.example.com");
+        upsert_host_entry_at_path(temp.path(), &entry).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n  HostName new.example.com\n  # jump via bastion on Fridays\n  User deploy\n\n"
+        );
+    }
+
     #[test]
     fn test_add_and_delete_host_entry() {
         let path = fixture_path("sample_config");
@@ -398,15 +2096,12 @@ mod tests {
         let contents = fs::read_to_string(path).unwrap();
         write!(temp, "{}", contents).unwrap();
 
-        let new_entry = HostEntry {
-            host: "web".to_string(),
-            hostname: "web.example.com".to_string(),
-            user: "www".to_string(),
-            port: "22".to_string(),
-            identity_file: "~/.ssh/web_rsa".to_string(),
-            proxy_command: String::new(),
-            extra: vec!["  ForwardAgent yes".to_string()],
-        };
+        let new_entry = HostEntry::new("web")
+            .with("HostName", "web.example.com")
+            .with("User", "www")
+            .with("Port", "22")
+            .with("IdentityFile", "~/.ssh/web_rsa")
+            .with("ForwardAgent", "yes");
 
         add_host_entry_at_path(temp.path(), &new_entry).unwrap();
         let contents = fs::read_to_string(temp.path()).unwrap();
@@ -418,6 +2113,24 @@ mod tests {
         assert!(!contents.contains("Host web"));
     }
 
+    #[test]
+    fn test_delete_several_hosts_at_once() {
+        let mut temp = NamedTempFile::new().unwrap();
+        let original = "Host a\n  HostName a.example.com\n\nHost b\n  HostName b.example.com\n\nHost c\n  HostName c.example.com\n";
+        write!(temp, "{}", original).unwrap();
+
+        let missing = [("a".to_string(), 0), ("nope".to_string(), 0)];
+        assert!(delete_host_entry_occurrences(temp.path(), &missing, AliasMatch::Exact).is_err());
+        assert_eq!(fs::read_to_string(temp.path()).unwrap(), original);
+
+        let hosts = [("a".to_string(), 0), ("c".to_string(), 0)];
+        delete_host_entry_occurrences(temp.path(), &hosts, AliasMatch::Exact).unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host b\n  HostName b.example.com\n"
+        );
+    }
+
     #[test]
     fn test_add_duplicate_host_fails() {
         let path = fixture_path("sample_config");
@@ -425,15 +2138,10 @@ mod tests {
         let contents = fs::read_to_string(path).unwrap();
         write!(temp, "{}", contents).unwrap();
 
-        let entry = HostEntry {
-            host: "app-server".to_string(),
-            hostname: "example.com".to_string(),
-            user: "user".to_string(),
-            port: "22".to_string(),
-            identity_file: String::new(),
-            proxy_command: String::new(),
-            extra: vec![],
-        };
+        let entry = HostEntry::new("app-server")
+            .with("HostName", "example.com")
+            .with("User", "user")
+            .with("Port", "22");
 
         assert!(add_host_entry_at_path(temp.path(), &entry).is_err());
     }
@@ -446,20 +2154,939 @@ mod tests {
 
     #[test]
     fn test_host_entry_validation() {
-        let mut entry = HostEntry::default();
-        entry.host = "".to_string();
-        entry.hostname = "example.com".to_string();
+        let mut entry = HostEntry::new("").with("HostName", "example.com");
         assert!(entry.validate().is_err());
 
         entry.host = "valid".to_string();
-        entry.hostname.clear();
+        entry.set("HostName", "");
         assert!(entry.validate().is_err());
 
-        entry.hostname = "example.com".to_string();
-        entry.port = "abc".to_string();
+        entry.set("HostName", "example.com");
+        entry.set("Port", "abc");
         assert!(entry.validate().is_err());
 
-        entry.port = "22".to_string();
+        entry.set("Port", "22");
+        assert!(entry.validate().is_ok());
+    }
+
+    #[test]
+    fn test_find_alias_references() {
+        let bastion = HostEntry::new("bastion").with("HostName", "bastion.example.com");
+        let jumper = HostEntry::new("inner")
+            .with("HostName", "10.0.0.5")
+            .with("ProxyJump", "admin@bastion:2222,other");
+        let proxied = HostEntry::new("legacy")
+            .with("HostName", "10.0.0.6")
+            .with("ProxyCommand", "ssh -W %h:%p bastion");
+        let unrelated = HostEntry::new("web")
+            .with("HostName", "web.example.com")
+            .with("ProxyCommand", "ssh -W %h:%p bastion-old");
+
+        let entries = vec![bastion, jumper, proxied, unrelated];
+        assert_eq!(
+            find_alias_references(&entries, "bastion"),
+            vec!["inner".to_string(), "legacy".to_string()]
+        );
+        assert!(find_alias_references(&entries, "web").is_empty());
+    }
+
+    #[test]
+    fn test_connect_with_comment_override() {
+        let mut entry = HostEntry::new("flaky")
+            .with("HostName", "flaky.example.com")
+            .with_line("  # connect-with: mosh");
+        assert_eq!(entry.connect_with(), Some("mosh"));
+
+        entry.directives = vec![(String::new(), "  # just a note".to_string())];
+        assert_eq!(entry.connect_with(), None);
+    }
+
+    #[test]
+    fn test_set_tags_round_trips_through_the_file() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host web\n    HostName web.example.com\n    # tags: old\n"
+        )
+        .unwrap();
+
+        let mut entry = load_host_entries_from_path(temp.path()).unwrap().remove(0);
+        assert_eq!(entry.tags(), vec!["old"]);
+        assert!(entry.extra_lines().is_empty());
+        entry.set_tags(&["prod", "db"]);
+        update_host_entry_at_path(temp.path(), "web", &entry).unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n    HostName web.example.com\n    # tags: prod db\n\n"
+        );
+
+        let mut untagged = HostEntry::new("db").with("HostName", "db.example.com");
+        untagged.set_tags(&["db"]);
+        assert_eq!(untagged.tags(), vec!["db"]);
+        assert_eq!(
+            render_host_entry_lines(&untagged),
+            vec!["Host db", "  HostName db.example.com", "  # tags: db", ""]
+        );
+        untagged.set_tags(&[]);
+        assert!(untagged.tags().is_empty());
+        assert_eq!(untagged.directives.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_host_block_reads_edited_text() {
+        let entry = parse_host_block(
+            "\nHost web\n    HostName=web.example.com\n    # note: front end\n\n    LocalForward 8080 localhost:80\n\n\n",
+        )
+        .unwrap();
+
+        assert_eq!(entry.host, "web");
+        assert_eq!(entry.hostname(), "web.example.com");
+        assert_eq!(entry.note(), "front end");
+        assert_eq!(entry.get("LocalForward"), "8080 localhost:80");
+        assert_eq!(entry.directives.len(), 4);
+    }
+
+    #[test]
+    fn test_parse_host_block_rejects_text_without_one_host_line() {
+        for text in [
+            "",
+            "  \n",
+            "HostName web.example.com\n",
+            "# Host web\n  Port 22\n",
+        ] {
+            let err = parse_host_block(text).unwrap_err().to_string();
+            assert!(err.contains("must start with a Host line"), "{}", err);
+        }
+
+        let err = parse_host_block("Host web\n  HostName a\nHost db\n  HostName b\n")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Only one Host block"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_host_entries_reads_a_multi_host_string() {
+        let entries = parse_host_entries(
+            "Host *\r\n  ServerAliveInterval 30\r\n\r\nHost web\r\n  HostName web.example.com\r\n  User deploy\r\n\r\nMatch all\r\nHost db\r\n  HostName=10.0.0.2\r\n",
+        );
+
+        let hosts: Vec<_> = entries.iter().map(|entry| entry.host.as_str()).collect();
+        assert_eq!(hosts, ["web", "db"]);
+        assert_eq!(entries[0].hostname(), "web.example.com");
+        assert_eq!(entries[0].user(), "deploy");
+        assert_eq!(entries[1].hostname(), "10.0.0.2");
+        assert!(parse_host_entries("").is_empty());
+    }
+
+    #[test]
+    fn test_note_lines_concatenate_and_keep_their_place() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host web\n    #note: migrate off\n    HostName web.example.com\n    # note: by Q3\n    User deploy\n"
+        )
+        .unwrap();
+
+        let mut entry = load_host_entries_from_path(temp.path()).unwrap().remove(0);
+        assert_eq!(entry.note(), "migrate off by Q3");
+        assert!(entry.extra_lines().is_empty());
+
+        entry.set_note("migrate off by Q3");
+        entry.set("User", "admin");
+        update_host_entry_at_path(temp.path(), "web", &entry).unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n    #note: migrate off\n    HostName web.example.com\n    # note: by Q3\n    User admin\n\n"
+        );
+
+        entry.set_note("retired");
+        assert_eq!(
+            render_host_entry_lines(&entry),
+            vec![
+                "Host web",
+                "    # note: retired",
+                "  HostName web.example.com",
+                "  User admin",
+                ""
+            ]
+        );
+        entry.set_note("");
+        assert_eq!(entry.note(), "");
+        assert_eq!(entry.directives.len(), 2);
+
+        let mut fresh = HostEntry::new("db").with("HostName", "db.example.com");
+        fresh.set_note("primary");
+        assert_eq!(
+            render_host_entry_lines(&fresh),
+            vec![
+                "Host db",
+                "  HostName db.example.com",
+                "  # note: primary",
+                ""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tags_and_connection_target() {
+        let mut entry = HostEntry::new("db")
+            .with("HostName", "db.internal")
+            .with("User", "postgres")
+            .with_line("  # tags: prod  db");
+        assert_eq!(entry.tags(), vec!["prod", "db"]);
+        assert_eq!(entry.connection_target(), "postgres@db.internal:22");
+
+        entry.set("User", "");
+        entry.set("HostName", "");
+        entry.set("Port", "2200");
+        assert_eq!(entry.connection_target(), "db:2200");
+    }
+
+    #[test]
+    fn test_target_hostname_falls_back_to_alias() {
+        let named = HostEntry::new("web").with("HostName", "web.example.com");
+        assert_eq!(named.target_hostname(), "web.example.com");
+        assert_eq!(HostEntry::new("db").target_hostname(), "db");
+    }
+
+    #[test]
+    fn test_trailing_comments_survive_edits() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host web\n  HostName web.example.com\n  Port 22 # temporary\n"
+        )
+        .unwrap();
+
+        let mut entry = load_host_entries_from_path(temp.path()).unwrap()[0].clone();
+        assert_eq!(entry.port(), "22");
+        entry.set("User", "deploy");
+        update_host_entry_at_path(temp.path(), "web", &entry).unwrap();
+        assert!(fs::read_to_string(temp.path())
+            .unwrap()
+            .contains("  Port 22 # temporary\n"));
+
+        entry.set("Port", "2222");
+        assert_eq!(entry.get("Port"), "2222");
+        update_host_entry_at_path(temp.path(), "web", &entry).unwrap();
+        assert!(fs::read_to_string(temp.path())
+            .unwrap()
+            .contains("  Port 2222 # temporary\n"));
+
+        entry.set("Port", "");
+        assert_eq!(entry.directives[1], ("Port".to_string(), String::new()));
+    }
+
+    #[test]
+    fn test_lint_flags_duplicates_missing_hostnames_and_bad_ports() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host web\n  HostName web.example.com\n\nHost bare\n  User me\n\nHost web\n  HostName other\n\nHost odd\n  HostName odd.example.com\n  Port 70000\n\n# Host off\n#   User nobody\n"
+        )
+        .unwrap();
+
+        let entries = load_host_entries_from_path(temp.path()).unwrap();
+        assert_eq!(
+            lint_hosts(&entries),
+            vec![
+                "'bare' has no HostName; ssh connects to the alias itself",
+                "'web' is defined more than once; ssh only uses the first block",
+                "'odd': Port '70000': Port must be a number between 1 and 65535",
+            ]
+        );
+        assert!(lint_hosts(&entries[..1]).is_empty());
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        assert!(pattern_matches("*prod*", "web-prod-1"));
+        assert!(pattern_matches("db-?", "db-1"));
+        assert!(pattern_matches("*", "anything"));
+        assert!(!pattern_matches("db-?", "db-10"));
+        assert!(!pattern_matches("*prod", "production"));
+    }
+
+    #[test]
+    fn test_comment_and_uncomment_round_trip() {
+        let path = fixture_path("sample_config");
+        let mut temp = NamedTempFile::new().unwrap();
+        let original = fs::read_to_string(path).unwrap();
+        write!(temp, "{}", original).unwrap();
+
+        comment_host_block_occurrence(temp.path(), "app-server", 0, AliasMatch::Exact).unwrap();
+        let commented = fs::read_to_string(temp.path()).unwrap();
+        assert!(commented.contains("# Host app-server"));
+        assert!(commented.contains("#   User deploy"));
+        assert!(commented.contains("#   # inline comment"));
+        assert!(commented.contains("\nHost db-server"));
+
+        let entries = load_host_entries_from_path(temp.path()).unwrap();
+        let app = entries.iter().find(|e| e.host == "app-server").unwrap();
+        assert!(app.disabled);
+        assert_eq!(app.hostname(), "app.example.com");
+        assert_eq!(app.proxy_command(), "ssh -W %h:%p bastion");
+        let db = entries.iter().find(|e| e.host == "db-server").unwrap();
+        assert!(!db.disabled);
+
+        assert!(
+            comment_host_block_occurrence(temp.path(), "app-server", 0, AliasMatch::Exact).is_err()
+        );
+        uncomment_host_block_occurrence(temp.path(), "app-server", 0, AliasMatch::Exact).unwrap();
+        assert_eq!(fs::read_to_string(temp.path()).unwrap(), original);
+    }
+
+    #[test]
+    fn test_export_writes_entries_as_config() {
+        let entries = load_host_entries_from_path(&fixture_path("sample_config")).unwrap();
+        let mut entries: Vec<HostEntry> = entries.into_iter().take(2).collect();
+        entries[1].disabled = true;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exported");
+
+        export_host_entries(&path, &entries).unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(written.starts_with(&format!("Host {}\n", entries[0].host)));
+        assert!(written.contains(&format!("\n# Host {}\n", entries[1].host)));
+        let reloaded = load_host_entries_from_path(&path).unwrap();
+        assert_eq!(reloaded[0], entries[0]);
+        assert!(reloaded[1].disabled);
+        assert_eq!(reloaded[1].hostname(), entries[1].hostname());
+    }
+
+    #[test]
+    fn test_diff_of_identical_entries_is_empty() {
+        let entry = HostEntry::new("web")
+            .with("HostName", "web.example.com")
+            .with("LocalForward", "8080 localhost:80");
+        let same = HostEntry::new("web-staging")
+            .with("hostname", "web.example.com")
+            .with_line("  # only a comment differs")
+            .with("LocalForward", "8080 localhost:80");
+        assert!(entry.diff(&same).is_empty());
+    }
+
+    #[test]
+    fn test_diff_lists_changed_missing_and_extra_directives() {
+        let prod = HostEntry::new("web-prod")
+            .with("HostName", "prod.example.com")
+            .with("User", "deploy")
+            .with("LocalForward", "8080 localhost:80")
+            .with_line("  LocalForward 5432 db:5432");
+        let staging = HostEntry::new("web-staging")
+            .with("HostName", "staging.example.com")
+            .with("User", "deploy")
+            .with("LocalForward", "8080 localhost:80")
+            .with("ForwardAgent", "yes");
+        let diff = |keyword: &str, left: &str, right: &str| FieldDiff {
+            keyword: keyword.to_string(),
+            left: left.to_string(),
+            right: right.to_string(),
+        };
+
+        assert_eq!(
+            prod.diff(&staging),
+            vec![
+                diff("HostName", "prod.example.com", "staging.example.com"),
+                diff(
+                    "LocalForward",
+                    "8080 localhost:80, 5432 db:5432",
+                    "8080 localhost:80"
+                ),
+                diff("ForwardAgent", "", "yes"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_applies_the_conflict_policy() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host web\n  HostName old.example.com\n\nHost web-2\n  HostName spare\n"
+        )
+        .unwrap();
+        let incoming = vec![
+            HostEntry::new("web").with("HostName", "new.example.com"),
+            HostEntry::new("db").with("HostName", "db.example.com"),
+            HostEntry {
+                disabled: true,
+                ..HostEntry::new("old")
+            },
+        ];
+        let aliases = |path: &Path| -> Vec<(String, String)> {
+            load_host_entries_from_path(path)
+                .unwrap()
+                .into_iter()
+                .map(|entry| (entry.host.clone(), entry.hostname().to_string()))
+                .collect()
+        };
+
+        let summary = merge_host_entries(temp.path(), &incoming, |_| ConflictPolicy::Skip).unwrap();
+        assert_eq!(
+            summary,
+            MergeSummary {
+                added: 1,
+                updated: 0,
+                skipped: 1
+            }
+        );
+        assert_eq!(summary.to_string(), "1 added, 0 updated, 1 skipped");
+
+        let summary =
+            merge_host_entries(temp.path(), &incoming, |_| ConflictPolicy::Rename).unwrap();
+        assert_eq!((summary.added, summary.skipped), (2, 0));
+        assert_eq!(
+            aliases(temp.path()),
+            vec![
+                ("web".to_string(), "old.example.com".to_string()),
+                ("web-2".to_string(), "spare".to_string()),
+                ("db".to_string(), "db.example.com".to_string()),
+                ("web-3".to_string(), "new.example.com".to_string()),
+                ("db-2".to_string(), "db.example.com".to_string()),
+            ]
+        );
+
+        let summary =
+            merge_host_entries(temp.path(), &incoming[..1], |_| ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(summary.updated, 1);
+        assert_eq!(aliases(temp.path())[0].1, "new.example.com");
+        assert_eq!(
+            ConflictPolicy::from_name("Rename"),
+            Some(ConflictPolicy::Rename)
+        );
+        assert_eq!(ConflictPolicy::from_name("ask"), None);
+    }
+
+    #[test]
+    fn test_hand_commented_block_restores_exactly() {
+        let mut temp = NamedTempFile::new().unwrap();
+        let commented = "Host live\n  HostName live.example.com\n\n#Host old\n#  HostName old.example.com\n#  # retired 2023\n";
+        write!(temp, "{}", commented).unwrap();
+
+        let entries = load_host_entries_from_path(temp.path()).unwrap();
+        assert!(entries[1].disabled);
+        assert_eq!(entries[1].hostname(), "old.example.com");
+
+        uncomment_host_block_occurrence(temp.path(), "old", 0, AliasMatch::Exact).unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host live\n  HostName live.example.com\n\nHost old\n  HostName old.example.com\n  # retired 2023\n"
+        );
+    }
+
+    #[test]
+    fn test_commented_block_does_not_leak_into_previous_host() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host a\n  HostName a.example.com\n\n# Host b\n#   HostName b.example.com\n"
+        )
+        .unwrap();
+
+        let entries = load_host_entries_from_path(temp.path()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0].directives,
+            vec![
+                ("HostName".to_string(), "a.example.com".to_string()),
+                (String::new(), String::new()),
+            ]
+        );
+        assert!(entries[1].disabled);
+        assert_eq!(entries[1].hostname(), "b.example.com");
+
+        let mut a = entries[0].clone();
+        a.set("User", "admin");
+        update_host_entry_at_path(temp.path(), "a", &a).unwrap();
+        let contents = fs::read_to_string(temp.path()).unwrap();
+        assert!(contents.contains("# Host b\n#   HostName b.example.com"));
+    }
+
+    #[test]
+    fn test_commented_host_line_inside_an_active_block_is_a_comment() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host web\n  HostName a.example.com\n  # Host b is the old name\n  User x\n\nHost db\n  HostName db.example.com\n"
+        )
+        .unwrap();
+
+        let entries = load_host_entries_from_path(temp.path()).unwrap();
+        let hosts: Vec<_> = entries.iter().map(|entry| entry.host.as_str()).collect();
+        assert_eq!(hosts, ["web", "db"]);
+        assert_eq!(entries[0].user(), "x");
+
+        let mut web = entries[0].clone();
+        web.set("HostName", "b.example.com");
+        update_host_entry_at_path(temp.path(), "web", &web).unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host web\n  HostName b.example.com\n  # Host b is the old name\n  User x\n\nHost db\n  HostName db.example.com\n"
+        );
+    }
+
+    #[test]
+    fn test_hostname_syntax_validation() {
+        let mut entry = HostEntry::new("valid").with("HostName", "app.example.com");
+        assert!(entry.validate().is_ok());
+
+        entry.set("HostName", "2001:db8::1");
+        assert!(entry.validate().is_ok());
+
+        entry.set("HostName", "fe80::1%eth0");
+        assert!(entry.validate().is_ok());
+
+        entry.set("HostName", "%h.internal");
         assert!(entry.validate().is_ok());
+
+        entry.set("HostName", "app example.com");
+        let err = entry.validate().unwrap_err();
+        assert!(err.to_string().contains("not a valid host name"));
+
+        for invalid in ["bad..dots", "-leading.example.com", "semi;colon"] {
+            entry.set("HostName", invalid);
+            assert!(entry.validate().is_err(), "{} should be rejected", invalid);
+        }
+    }
+
+    #[test]
+    fn test_tab_indented_config_parses_like_spaces() {
+        let spaced = load_host_entries_from_path(&fixture_path("sample_config")).unwrap();
+        let tabbed = load_host_entries_from_path(&fixture_path("tab_config")).unwrap();
+        assert_eq!(spaced.len(), tabbed.len());
+
+        for (spaced, tabbed) in spaced.iter().zip(&tabbed) {
+            let trimmed = |entry: &HostEntry| HostEntry {
+                directives: entry
+                    .directives
+                    .iter()
+                    .map(|(keyword, value)| (keyword.clone(), value.trim().to_string()))
+                    .collect(),
+                ..entry.clone()
+            };
+            assert_eq!(trimmed(spaced), trimmed(tabbed));
+        }
+    }
+
+    #[test]
+    fn test_tab_indented_block_round_trips_with_tabs() {
+        let mut temp = NamedTempFile::new().unwrap();
+        let contents = fs::read_to_string(fixture_path("tab_config")).unwrap();
+        write!(temp, "{}", contents).unwrap();
+
+        let mut entry = load_host_entries_from_path(temp.path())
+            .unwrap()
+            .into_iter()
+            .find(|e| e.host == "db-server")
+            .unwrap();
+        entry.set("Port", "5433");
+        update_host_entry_at_path(temp.path(), "db-server", &entry).unwrap();
+
+        let rewritten = fs::read_to_string(temp.path()).unwrap();
+        assert!(rewritten.contains("\tHostName db.internal\n"));
+        assert!(rewritten.contains("\tPort 5433\n"));
+        assert!(rewritten.contains("\tDynamicForward 9000 localhost:9000"));
+        assert!(!rewritten.contains("  HostName db.internal"));
+
+        let reparsed = load_host_entries_from_path(temp.path()).unwrap();
+        let db = reparsed.iter().find(|e| e.host == "db-server").unwrap();
+        assert_eq!(db.user(), "postgres");
+        assert_eq!(db.port(), "5433");
+    }
+
+    #[test]
+    fn test_ipv6_targets_are_bracketed() {
+        let mut entry = HostEntry::new("v6")
+            .with("HostName", "2001:db8::1")
+            .with("User", "admin");
+        assert_eq!(entry.ssh_command(), "ssh -6 admin@2001:db8::1");
+        assert_eq!(entry.connection_target(), "admin@[2001:db8::1]:22");
+
+        entry.set("Port", "2222");
+        assert_eq!(entry.ssh_command(), "ssh -6 -p 2222 admin@[2001:db8::1]");
+        assert_eq!(entry.connection_target(), "admin@[2001:db8::1]:2222");
+
+        entry.set("HostName", "fe80::1%eth0");
+        entry.set("Port", "");
+        assert_eq!(entry.ssh_command(), "ssh -6 admin@fe80::1%eth0");
+        assert_eq!(entry.connection_target(), "admin@[fe80::1%eth0]:22");
+        entry.set("Port", "22");
+        assert_eq!(entry.ssh_command(), "ssh -6 -p 22 admin@[fe80::1%eth0]");
+
+        entry.set("HostName", "10.0.0.1");
+        assert_eq!(entry.ssh_command(), "ssh -p 22 admin@10.0.0.1");
+        assert_eq!(entry.connection_target(), "admin@10.0.0.1:22");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_first_save_creates_private_dir_and_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let home = tempfile::tempdir().unwrap();
+        let path = home.path().join(".ssh").join("config");
+        let entry = HostEntry::new("first").with("HostName", "first.example.com");
+
+        add_host_entry_at_path(&path, &entry).unwrap();
+
+        let dir_mode = fs::metadata(path.parent().unwrap())
+            .unwrap()
+            .permissions()
+            .mode();
+        let file_mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(dir_mode & 0o777, 0o700);
+        assert_eq!(file_mode & 0o777, 0o600);
+        assert!(fs::read_to_string(&path).unwrap().contains("Host first"));
+    }
+
+    #[test]
+    fn test_pattern_blocks_next_to_concrete_hosts() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host app-*\n  ServerAliveInterval 30\nHost app-server\n  HostName app.example.com\n"
+        )
+        .unwrap();
+
+        let entries = load_host_entries_from_path(temp.path()).unwrap();
+        assert_eq!(entries.len(), 1);
+        let mut entry = entries[0].clone();
+        entry.set("User", "deploy");
+        update_host_entry_at_path(temp.path(), "app-server", &entry).unwrap();
+
+        let contents = fs::read_to_string(temp.path()).unwrap();
+        assert!(contents.starts_with("Host app-*\n  ServerAliveInterval 30\nHost app-server\n"));
+        assert!(contents.contains("  User deploy"));
+    }
+
+    #[test]
+    fn test_refuses_hosts_shadowed_by_earlier_patterns() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host web-* !web-legacy\n  User www\n\nHost *\n  ServerAliveInterval 60\n"
+        )
+        .unwrap();
+
+        let mut entry = HostEntry::new("web-1")
+            .with("HostName", "web1.example.com")
+            .with("User", "admin");
+        let err = add_host_entry_at_path(temp.path(), &entry).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("shadowed by earlier 'Host web-* !web-legacy'"));
+        assert!(err.to_string().contains("User"));
+
+        entry.set("User", "");
+        add_host_entry_at_path(temp.path(), &entry).unwrap();
+
+        let legacy = HostEntry::new("web-legacy")
+            .with("HostName", "old.example.com")
+            .with("User", "root");
+        add_host_entry_at_path(temp.path(), &legacy).unwrap();
+    }
+
+    #[test]
+    fn test_updates_under_a_catch_all_only_warn_about_changed_fields() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host *\n  User me\n  IdentityFile ~/.ssh/id_default\n\nHost web\n  HostName a.example.com\n  User deploy\n"
+        )
+        .unwrap();
+        let update = |entry: &HostEntry| {
+            update_host_entry_occurrence(temp.path(), "web", 0, entry, AliasMatch::Exact).unwrap()
+        };
+
+        let mut web = load_host_entries_from_path(temp.path()).unwrap()[0].clone();
+        web.set("HostName", "b.example.com");
+        assert_eq!(update(&web), None);
+        web.set("IdentityFile", "~/.ssh/id_web");
+        assert_eq!(update(&web), None);
+
+        web.set("User", "root");
+        let warning = update(&web).unwrap();
+        assert!(
+            warning.contains("shadowed by earlier 'Host *'"),
+            "{}",
+            warning
+        );
+        assert!(warning.ends_with("already sets User"), "{}", warning);
+        let contents = fs::read_to_string(temp.path()).unwrap();
+        assert!(contents
+            .contains("  HostName b.example.com\n  User root\n  IdentityFile ~/.ssh/id_web\n"));
+
+        let api = HostEntry::new("api")
+            .with("HostName", "api.example.com")
+            .with("IdentityFile", "~/.ssh/id_api");
+        add_host_entry_at_path(temp.path(), &api).unwrap();
+    }
+
+    #[test]
+    fn test_extra_host_patterns_survive_rewrite() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(temp, "Host web web.example.com\n  HostName 10.0.0.1\n").unwrap();
+
+        let mut entry = load_host_entries_from_path(temp.path()).unwrap()[0].clone();
+        entry.set("Port", "2222");
+        update_host_entry_at_path(temp.path(), "web", &entry).unwrap();
+
+        let contents = fs::read_to_string(temp.path()).unwrap();
+        assert!(contents.starts_with("Host web web.example.com\n"));
+
+        let duplicate = HostEntry::new("web.example.com").with("HostName", "10.0.0.2");
+        let err = add_host_entry_at_path(temp.path(), &duplicate).unwrap_err();
+        assert!(err.to_string().contains("already listed"));
+    }
+
+    #[test]
+    fn test_move_host_block_swaps_whole_blocks() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "# global\nHost a\n  HostName a.example.com\n\nHost b\n  HostName b.example.com\n  # keep me\n\nHost c\n  HostName c.example.com\n"
+        )
+        .unwrap();
+
+        move_host_block_occurrence(temp.path(), "b", 0, AliasMatch::Exact, MoveDirection::Up)
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "# global\nHost b\n  HostName b.example.com\n  # keep me\n\nHost a\n  HostName a.example.com\n\nHost c\n  HostName c.example.com\n"
+        );
+
+        let err =
+            move_host_block_occurrence(temp.path(), "b", 0, AliasMatch::Exact, MoveDirection::Up)
+                .unwrap_err();
+        assert!(err.to_string().contains("already first"));
+
+        move_host_block_occurrence(temp.path(), "a", 0, AliasMatch::Exact, MoveDirection::Down)
+            .unwrap();
+        let order: Vec<String> = load_host_entries_from_path(temp.path())
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.host)
+            .collect();
+        assert_eq!(order, vec!["b", "c", "a"]);
+        assert!(fs::read_to_string(temp.path())
+            .unwrap()
+            .ends_with("HostName c.example.com\n\nHost a\n  HostName a.example.com\n"));
+    }
+
+    #[test]
+    fn test_crlf_config_keeps_crlf_on_rewrite() {
+        let mut temp = NamedTempFile::new().unwrap();
+        let contents = fs::read_to_string(fixture_path("crlf_config")).unwrap();
+        write!(temp, "{}", contents).unwrap();
+
+        let entries = load_host_entries_from_path(temp.path()).unwrap();
+        assert!(entries
+            .iter()
+            .all(|entry| !entry.hostname().contains('\r') && !entry.user().contains('\r')));
+
+        let mut entry = entries[0].clone();
+        entry.set("Port", "2200");
+        update_host_entry_at_path(temp.path(), &entry.host.clone(), &entry).unwrap();
+        add_host_entry_at_path(
+            temp.path(),
+            &HostEntry::new("added").with("HostName", "added.example.com"),
+        )
+        .unwrap();
+        upsert_host_entry_at_path(
+            temp.path(),
+            &HostEntry::new("added").with("HostName", "upserted.example.com"),
+        )
+        .unwrap();
+
+        let rewritten = fs::read_to_string(temp.path()).unwrap();
+        assert!(rewritten.contains("Port 2200\r\n"));
+        assert!(rewritten.ends_with("HostName upserted.example.com\r\n\r\n"));
+        assert_eq!(
+            rewritten.matches('\n').count(),
+            rewritten.matches("\r\n").count()
+        );
+        assert!(!rewritten.contains("\r\r"));
+    }
+
+    #[test]
+    fn test_detect_line_ending() {
+        assert_eq!(detect_line_ending("Host a\r\n  User b\r\n"), "\r\n");
+        assert_eq!(detect_line_ending("Host a\n  User b\r\n  Port 1\n"), "\n");
+        assert_eq!(detect_line_ending(""), "\n");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_writes_through_a_config_symlink_land_on_its_target() {
+        let home = tempfile::tempdir().unwrap();
+        let dotfiles = tempfile::tempdir().unwrap();
+        let target = dotfiles.path().join("ssh_config");
+        let link = home.path().join("config");
+        fs::write(&target, "Host web\n  HostName web.example.com\n").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        assert_eq!(
+            resolve_config_target(&link),
+            fs::canonicalize(&target).unwrap()
+        );
+        assert_eq!(resolve_config_target(&target), target);
+        add_host_entry_at_path(&link, &HostEntry::new("db").with("HostName", "10.0.0.5")).unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            fs::read_to_string(&target).unwrap(),
+            "Host web\n  HostName web.example.com\n\nHost db\n  HostName 10.0.0.5\n\n"
+        );
+
+        // A dangling link gets its target created rather than replaced.
+        let missing = dotfiles.path().join("new_config");
+        let dangling = home.path().join("dangling");
+        std::os::unix::fs::symlink(&missing, &dangling).unwrap();
+        add_host_entry_at_path(
+            &dangling,
+            &HostEntry::new("db").with("HostName", "10.0.0.5"),
+        )
+        .unwrap();
+        assert!(fs::symlink_metadata(&dangling)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(
+            fs::read_to_string(&missing).unwrap(),
+            "Host db\n  HostName 10.0.0.5\n\n"
+        );
+    }
+
+    #[test]
+    fn test_negated_pattern_block_is_kept_apart_and_preserved() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host * !badhost\n  ServerAliveInterval 30\n\nHost web\n  HostName web.example.com\n"
+        )
+        .unwrap();
+
+        let parsed = load_config(temp.path()).unwrap();
+        assert_eq!(parsed.hosts.len(), 1);
+        assert_eq!(parsed.hosts[0].host, "web");
+        assert!(!parsed.hosts[0].is_pattern);
+        let pattern = &parsed.patterns[0];
+        assert!(pattern.is_pattern);
+        assert_eq!(pattern.host, "* !badhost");
+        assert_eq!(pattern.get("ServerAliveInterval"), "30");
+        assert!(pattern.pattern_applies_to("web"));
+        assert!(!pattern.pattern_applies_to("badhost"));
+
+        let mut web = parsed.hosts[0].clone();
+        web.set("User", "deploy");
+        update_host_entry_at_path(temp.path(), "web", &web).unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host * !badhost\n  ServerAliveInterval 30\n\nHost web\n  HostName web.example.com\n  User deploy\n\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_final_newline_is_preserved() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(
+            temp,
+            "Host a\n  HostName a.example.com\n\nHost b\n  HostName b.example.com"
+        )
+        .unwrap();
+
+        let mut entry = load_host_entries_from_path(temp.path()).unwrap()[0].clone();
+        entry.set("User", "deploy");
+        update_host_entry_at_path(temp.path(), "a", &entry).unwrap();
+        delete_host_entry_at_path(temp.path(), "b").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host a\n  HostName a.example.com\n  User deploy"
+        );
+    }
+
+    #[test]
+    fn test_trailing_blank_lines_collapse_to_one() {
+        let mut temp = NamedTempFile::new().unwrap();
+        write!(temp, "Host a\n  HostName a.example.com\n\n\n\n").unwrap();
+
+        let mut entry = load_host_entries_from_path(temp.path()).unwrap()[0].clone();
+        entry.set("Port", "2222");
+        update_host_entry_at_path(temp.path(), "a", &entry).unwrap();
+        update_host_entry_at_path(temp.path(), "a", &entry).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            "Host a\n  HostName a.example.com\n  Port 2222\n\n"
+        );
+    }
+
+    #[test]
+    fn test_non_canonical_field_order_round_trips() {
+        let mut temp = NamedTempFile::new().unwrap();
+        let original = "Host web\n  # owner: ops\n  User deploy\n  ForwardAgent yes\n  HostName web.example.com\n  Port 2222\n\nHost db\n  HostName db.internal\n";
+        write!(temp, "{}", original).unwrap();
+
+        let entry = load_host_entries_from_path(temp.path()).unwrap()[0].clone();
+        update_host_entry_at_path(temp.path(), "web", &entry).unwrap();
+        assert_eq!(fs::read_to_string(temp.path()).unwrap(), original);
+
+        let mut entry = entry;
+        entry.set("Port", "");
+        entry.set("IdentityFile", "~/.ssh/web");
+        update_host_entry_at_path(temp.path(), "web", &entry).unwrap();
+        assert!(fs::read_to_string(temp.path()).unwrap().starts_with(
+            "Host web\n  # owner: ops\n  User deploy\n  ForwardAgent yes\n  HostName web.example.com\n  IdentityFile ~/.ssh/web\n\nHost db\n"
+        ));
+    }
+
+    #[test]
+    fn test_varied_directives_round_trip_losslessly() {
+        let mut temp = NamedTempFile::new().unwrap();
+        let original = "Host build
+  # CI runners
+  hostname build.internal
+  USER ci
+  Port 2200
+  IdentityFile ~/.ssh/ci
+  IdentityFile ~/.ssh/ci_legacy
+  IdentitiesOnly yes
+  LocalForward 8080 localhost:80
+  LocalForward 9090 localhost:90
+  serveraliveinterval 30
+  ServerAliveCountMax 3
+  Compression yes
+
+  ControlPath ~/.ssh/cm-%r@%h:%p
+  SendEnv LANG LC_*
+
+Host other
+  HostName other.example.com
+";
+        write!(temp, "{}", original).unwrap();
+
+        let entry = load_host_entries_from_path(temp.path()).unwrap()[0].clone();
+        assert_eq!(entry.hostname(), "build.internal");
+        assert_eq!(entry.user(), "ci");
+        assert_eq!(entry.identity_file(), "~/.ssh/ci");
+        assert_eq!(entry.get("ServerAliveInterval"), "30");
+        assert_eq!(entry.directive_values("LocalForward").count(), 2);
+        assert_eq!(entry.directives[0].1, "  # CI runners");
+
+        update_host_entry_at_path(temp.path(), "build", &entry).unwrap();
+        assert_eq!(fs::read_to_string(temp.path()).unwrap(), original);
+
+        let mut entry = entry;
+        entry.set("User", "builder");
+        update_host_entry_at_path(temp.path(), "build", &entry).unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path()).unwrap(),
+            original.replace("USER ci", "USER builder")
+        );
     }
 }
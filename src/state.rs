@@ -0,0 +1,133 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Data the app remembers between runs, stored next to `config.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct State {
+    /// Unix timestamp of the last connection, keyed by host alias.
+    #[serde(default)]
+    pub last_used: BTreeMap<String, u64>,
+    /// The extra ssh arguments last typed for each host alias.
+    #[serde(default)]
+    pub last_args: BTreeMap<String, String>,
+    /// Recently used extra arguments across hosts, newest first.
+    #[serde(default)]
+    pub args_history: Vec<String>,
+}
+
+/// How many argument strings `args_history` keeps.
+const ARGS_HISTORY_LIMIT: usize = 10;
+
+impl State {
+    /// Loads the state file, treating a missing or unreadable file as empty
+    /// so a corrupt state never prevents startup.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create state directory")?;
+        }
+        let contents = toml::to_string_pretty(self).context("Failed to serialize state")?;
+        fs::write(path, contents).context("Failed to write state file")
+    }
+
+    pub fn record_connection(&mut self, host: &str, at: u64) {
+        self.last_used.insert(host.to_string(), at);
+    }
+
+    /// Remembers `args` as the host's last extra arguments and moves them
+    /// to the front of the history. Empty arguments forget the host's.
+    pub fn record_args(&mut self, host: &str, args: &str) {
+        let args = args.trim();
+        if args.is_empty() {
+            self.last_args.remove(host);
+            return;
+        }
+        self.last_args.insert(host.to_string(), args.to_string());
+        self.args_history.retain(|earlier| earlier != args);
+        self.args_history.insert(0, args.to_string());
+        self.args_history.truncate(ARGS_HISTORY_LIMIT);
+    }
+
+    /// Aliases ordered from most to least recently used.
+    pub fn recent_hosts(&self, limit: usize) -> Vec<&str> {
+        let mut recent: Vec<(&str, u64)> = self
+            .last_used
+            .iter()
+            .map(|(host, &at)| (host.as_str(), at))
+            .collect();
+        recent.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        recent
+            .into_iter()
+            .take(limit)
+            .map(|(host, _)| host)
+            .collect()
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recent_hosts_are_ordered_by_recency_and_capped() {
+        let mut state = State::default();
+        state.record_connection("a", 10);
+        state.record_connection("b", 30);
+        state.record_connection("c", 20);
+        state.record_connection("a", 40);
+
+        assert_eq!(state.recent_hosts(5), vec!["a", "b", "c"]);
+        assert_eq!(state.recent_hosts(2), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn state_round_trips_and_tolerates_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("state.toml");
+        assert_eq!(State::load(&path), State::default());
+
+        let mut state = State::default();
+        state.record_connection("web", 1_700_000_000);
+        state.record_args("web", "-v");
+        state.save(&path).unwrap();
+        assert_eq!(State::load(&path), state);
+    }
+
+    #[test]
+    fn args_are_remembered_per_host_with_a_capped_history() {
+        let mut state = State::default();
+        state.record_args("web", " -v ");
+        state.record_args("db", "-L 5432:localhost:5432");
+        state.record_args("web", "-v");
+        assert_eq!(state.last_args["web"], "-v");
+        assert_eq!(state.args_history, ["-v", "-L 5432:localhost:5432"]);
+
+        state.record_args("web", "");
+        assert!(!state.last_args.contains_key("web"));
+        for n in 0..ARGS_HISTORY_LIMIT + 2 {
+            state.record_args("db", &format!("-p {}", n));
+        }
+        assert_eq!(state.args_history.len(), ARGS_HISTORY_LIMIT);
+        assert_eq!(state.args_history[0], "-p 11");
+        assert_eq!(state.last_args["db"], "-p 11");
+    }
+}
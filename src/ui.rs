@@ -1,16 +1,109 @@
-use crate::app::{App, AppState, FormField};
+use crate::{
+    action::Action,
+    app::{App, AppState, Comparison, FormField, HostSort, Status},
+    history::{format_timestamp, HistoryEntry},
+    search::FilteredList,
+    ssh,
+};
 use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
     Frame,
 };
 
+/// Hosts with more extra directives than this get a `(+N more)` hint in
+/// the list; the directives themselves only show in the details pane.
+const INLINE_EXTRA_LIMIT: usize = 3;
+
+/// Braille frames shown before a pending operation's label, one per tick.
+const SPINNER_FRAMES: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
 pub fn draw(f: &mut Frame, app: &App) {
     match app.state {
-        AppState::Normal => draw_normal(f, app),
+        AppState::Normal | AppState::Search => draw_normal(f, app),
         AppState::Edit | AppState::New => draw_form(f, app),
+        AppState::RawEdit | AppState::Import => draw_raw_editor(f, app),
+        AppState::Palette => {
+            draw_normal(f, app);
+            let entries: Vec<String> = Action::ALL
+                .iter()
+                .map(|action| format!("{}  ({})", action.label(), action.id()))
+                .collect();
+            let labels: Vec<&str> = entries.iter().map(String::as_str).collect();
+            draw_filtered_list(f, " Command Palette ", &app.palette, &labels);
+        }
+        AppState::Recent => {
+            draw_normal(f, app);
+            let labels: Vec<&str> = app.recent_hosts.iter().map(String::as_str).collect();
+            draw_filtered_list(f, " Recent Connections ", &app.recent, &labels);
+        }
+        AppState::KeyPicker => {
+            draw_form(f, app);
+            let labels: Vec<&str> = app.key_candidates.iter().map(String::as_str).collect();
+            draw_filtered_list(f, " Select Identity File ", &app.key_picker, &labels);
+        }
+        AppState::QuickConnect => {
+            draw_normal(f, app);
+            draw_quick_connect(f, &app.quick_connect, app.quick_connect_match());
+        }
+        AppState::ConnectAs => {
+            draw_normal(f, app);
+            let hint = app
+                .connect_as_problem()
+                .map(|problem| format!("  {}", problem))
+                .unwrap_or_default();
+            draw_prompt(f, " Connect as User ", &app.connect_as_user, &hint);
+        }
+        AppState::ConnectArgs => {
+            draw_normal(f, app);
+            let hint = match app.connect_args_problem() {
+                Some(problem) => format!("  {}", problem),
+                None if app.has_args_history() => "  ↑/↓ earlier arguments".to_string(),
+                None => String::new(),
+            };
+            draw_prompt(f, " Extra ssh Arguments ", &app.connect_args, &hint);
+        }
+        AppState::ComparePicker => {
+            draw_normal(f, app);
+            let labels: Vec<&str> = app.compare_candidates.iter().map(String::as_str).collect();
+            draw_filtered_list(f, " Compare With ", &app.compare_picker, &labels);
+        }
+        AppState::ProfilePicker => {
+            draw_normal(f, app);
+            let labels: Vec<&str> = app.profile_candidates.iter().map(String::as_str).collect();
+            draw_filtered_list(f, " SSH Config Profiles ", &app.profile_picker, &labels);
+        }
+        AppState::Compare => {
+            draw_normal(f, app);
+            if let Some(comparison) = &app.comparison {
+                draw_comparison(f, app, comparison);
+            }
+        }
+        AppState::SaveAs => {
+            draw_normal(f, app);
+            let hint = if app.save_as_path.is_empty() {
+                "  path for a copy of all hosts, e.g. ~/ssh_config.backup"
+            } else {
+                ""
+            };
+            draw_prompt(f, " Save Hosts As ", &app.save_as_path, hint);
+        }
+        AppState::Issues => {
+            draw_normal(f, app);
+            draw_issues(f, &app.lint_warnings);
+        }
+        AppState::History => {
+            draw_normal(f, app);
+            draw_history(f, &app.history);
+        }
+        AppState::Confirm => {
+            draw_normal(f, app);
+            if let Some(confirmation) = &app.confirmation {
+                draw_confirmation(f, &confirmation.prompt);
+            }
+        }
     }
 }
 
@@ -24,10 +117,15 @@ fn draw_normal(f: &mut Frame, app: &App) {
         ])
         .split(f.area());
 
-    let title = Block::default().borders(Borders::ALL).title(" SSH TUI ");
-    let title_content =
-        Paragraph::new("↑↓/jk: navigate | Enter: connect | i: edit | n: new | q/Esc: quit")
-            .block(title);
+    let title_text = match &app.active_profile {
+        Some(profile) => format!(" SSH TUI [{}] ", profile),
+        None => " SSH TUI ".to_string(),
+    };
+    let title = Block::default().borders(Borders::ALL).title(title_text);
+    let title_content = Paragraph::new(
+        "↑↓/jk: navigate | Enter/:: connect | /: search | i: edit | n: new | ^P: commands | q/Esc: quit",
+    )
+    .block(title);
     f.render_widget(title_content, chunks[0]);
 
     let main_chunks = Layout::default()
@@ -36,24 +134,71 @@ fn draw_normal(f: &mut Frame, app: &App) {
         .split(chunks[1]);
 
     let items: Vec<ListItem> = app
-        .hosts
-        .iter()
+        .visible_hosts()
         .enumerate()
         .map(|(i, entry)| {
+            let marked = app.marked.contains(&app.visible[i]);
             let style = if i == app.selected {
                 Style::default()
                     .fg(Color::Yellow)
                     .add_modifier(Modifier::BOLD)
+            } else if entry.disabled {
+                Style::default().fg(Color::DarkGray)
             } else {
                 Style::default()
             };
-            let display = format!("{} ({})", entry.host, entry.hostname);
-            ListItem::new(display).style(style)
+            let marker = if marked { "* " } else { "" };
+            let warning = if app.identity_missing(app.visible[i]) {
+                " !"
+            } else {
+                ""
+            };
+            let proxy = if entry.uses_proxy() { " ⤴" } else { "" };
+            let display = format!(
+                "{}{} ({}){}{}",
+                marker,
+                entry.host,
+                entry.hostname(),
+                proxy,
+                warning
+            );
+            let mut spans = vec![Span::raw(display)];
+            if entry.is_read_only() {
+                spans.push(Span::styled(
+                    " [system]",
+                    Style::default().add_modifier(Modifier::DIM),
+                ));
+            }
+            spans.extend(tag_chips(&entry.tags()));
+            let extras = entry.extra_directive_count();
+            if extras > INLINE_EXTRA_LIMIT {
+                spans.push(Span::styled(
+                    format!(" (+{} more)", extras),
+                    Style::default().add_modifier(Modifier::DIM),
+                ));
+            }
+            ListItem::new(Line::from(spans)).style(style)
         })
         .collect();
 
+    // Position within the visible (possibly filtered) hosts.
+    let position = if app.visible.is_empty() {
+        0
+    } else {
+        app.selected + 1
+    };
+    let mut counter = format!("Hosts ({}/{})", position, app.visible.len());
+    if app.sort != HostSort::default() {
+        let arrow = if app.sort.descending { '↓' } else { '↑' };
+        counter.push_str(&format!(" by {} {}", app.sort.key.label(), arrow));
+    }
+    let hosts_title = if app.search_query.is_empty() {
+        format!(" {} ", counter)
+    } else {
+        format!(" {} [/{}] ", counter, app.search_query)
+    };
     let list = List::new(items)
-        .block(Block::default().borders(Borders::ALL).title(" Hosts "))
+        .block(Block::default().borders(Borders::ALL).title(hosts_title))
         .highlight_style(
             Style::default()
                 .fg(Color::Yellow)
@@ -63,10 +208,39 @@ fn draw_normal(f: &mut Frame, app: &App) {
 
     draw_details_pane(f, app, main_chunks[1]);
 
-    let footer_text = if let Some(status) = &app.status {
-        status.clone()
+    let footer_text = if app.state == AppState::Search {
+        let mut spans = vec![Span::raw(format!(
+            "/{} ({}, Ctrl+R: toggle regex | Enter: keep | Esc: clear)",
+            app.search_query,
+            app.match_mode.name()
+        ))];
+        if let Some(error) = &app.search_error {
+            spans.push(Span::styled(
+                format!("  {}", error),
+                Style::default().fg(Color::Red),
+            ));
+        }
+        Line::from(spans)
     } else {
-        "Ready".to_string()
+        let mut spans = vec![match (&app.pending_operation, &app.status) {
+            (Some(operation), _) => Span::styled(
+                format!(
+                    "{} {}…",
+                    SPINNER_FRAMES[app.spinner_frame % SPINNER_FRAMES.len()],
+                    operation.label()
+                ),
+                Style::default().fg(Color::Cyan),
+            ),
+            (None, Some(status)) => Span::styled(status.text(), status_style(status)),
+            (None, None) => Span::raw("Ready"),
+        }];
+        if !app.lint_warnings.is_empty() {
+            spans.push(Span::styled(
+                format!("  ⚠ {} issues (press W)", app.lint_warnings.len()),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        Line::from(spans)
     };
 
     let footer =
@@ -74,68 +248,190 @@ fn draw_normal(f: &mut Frame, app: &App) {
     f.render_widget(footer, chunks[2]);
 }
 
+fn status_style(status: &Status) -> Style {
+    match status {
+        Status::Info(_) => Style::default(),
+        Status::Error(_) => Style::default().fg(Color::Red),
+        Status::Success(_) => Style::default().fg(Color::Green),
+    }
+}
+
+/// Padded width of the field labels in the details pane, so values line
+/// up after `IdentityFile:`, the longest one.
+const DETAIL_LABEL_WIDTH: usize = 14;
+
+/// Panes narrower than this leave labels unpadded, so values keep room.
+const MIN_ALIGNED_DETAILS_WIDTH: u16 = 40;
+
+fn detail_label(label: &str, aligned: bool) -> Span<'static> {
+    let text = format!("{}: ", label);
+    let width = if aligned { DETAIL_LABEL_WIDTH } else { 0 };
+    Span::styled(
+        format!("{:<width$}", text),
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::BOLD),
+    )
+}
+
 fn draw_details_pane(f: &mut Frame, app: &App, area: Rect) {
     let details_block = Block::default().borders(Borders::ALL).title(" Details ");
 
     if let Some(entry) = app.selected_host() {
+        let aligned = area.width >= MIN_ALIGNED_DETAILS_WIDTH;
         let mut lines = vec![
             Line::from(vec![
+                detail_label("Host", aligned),
+                Span::raw(&entry.host),
                 Span::styled(
-                    "Host: ",
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .add_modifier(Modifier::BOLD),
+                    if entry.disabled { " (disabled)" } else { "" },
+                    Style::default().fg(Color::DarkGray),
                 ),
-                Span::raw(&entry.host),
             ]),
             Line::from(vec![
-                Span::styled(
-                    "HostName: ",
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(&entry.hostname),
+                detail_label("HostName", aligned),
+                Span::raw(entry.hostname()),
             ]),
         ];
 
-        if !entry.user.is_empty() {
+        if let Some(ips) = app.selected_resolution() {
+            let resolved = if ips.is_empty() {
+                "(unresolved)".to_string()
+            } else {
+                ips.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            lines.push(Line::from(Span::styled(
+                format!("  → {}", resolved),
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+
+        let note = entry.note();
+        if !note.is_empty() {
             lines.push(Line::from(vec![
-                Span::styled(
-                    "User: ",
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(&entry.user),
+                detail_label("Note", aligned),
+                Span::raw(note),
             ]));
         }
 
-        if !entry.port.is_empty() {
+        if entry.is_read_only() {
+            lines.push(Line::from(Span::styled(
+                format!("Source: {} (read-only)", app.system_config_path().display()),
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+
+        let tags = entry.tags();
+        if !tags.is_empty() {
+            // The chips bring their own leading space.
+            let mut spans = vec![detail_label("Tags", aligned)];
+            spans.extend(tag_chips(&tags).into_iter().skip(1));
+            lines.push(Line::from(spans));
+        }
+
+        if !entry.user().is_empty() {
             lines.push(Line::from(vec![
-                Span::styled(
-                    "Port: ",
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .add_modifier(Modifier::BOLD),
-                ),
-                Span::raw(&entry.port),
+                detail_label("User", aligned),
+                Span::raw(entry.user()),
             ]));
         }
 
-        if !entry.identity_file.is_empty() {
+        if !entry.port().is_empty() {
             lines.push(Line::from(vec![
+                detail_label("Port", aligned),
+                Span::raw(entry.port()),
+            ]));
+        }
+
+        if !entry.identity_file().is_empty() {
+            lines.push(Line::from(vec![
+                detail_label("IdentityFile", aligned),
+                Span::raw(mask(app, "IdentityFile", entry.identity_file())),
                 Span::styled(
-                    "IdentityFile: ",
-                    Style::default()
-                        .fg(Color::Cyan)
-                        .add_modifier(Modifier::BOLD),
+                    if app.selected_identity_missing() {
+                        " (not found)"
+                    } else {
+                        ""
+                    },
+                    Style::default().fg(Color::Red),
                 ),
-                Span::raw(&entry.identity_file),
             ]));
         }
 
-        if !entry.extra.is_empty() {
+        if entry.proxy_command().contains('%') {
+            lines.push(Line::from(Span::styled(
+                format!(
+                    "ProxyCommand → {}",
+                    mask(
+                        app,
+                        "ProxyCommand",
+                        &ssh::expand_ssh_tokens(entry.proxy_command(), entry)
+                    )
+                ),
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+
+        lines.push(Line::from(Span::styled(
+            format!("Without config: {}", entry.ssh_command()),
+            Style::default().fg(Color::DarkGray),
+        )));
+
+        if let Some(encrypted) = app.selected_key_encrypted() {
+            lines.push(Line::from(Span::styled(
+                if encrypted {
+                    "Key: encrypted"
+                } else {
+                    "Key: unencrypted"
+                },
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+
+        if app.selected_multiplexed() {
+            lines.push(Line::from(Span::styled(
+                "multiplexed session active",
+                Style::default().fg(Color::Green),
+            )));
+        }
+
+        if app.selected_identity_missing() {
+            lines.push(Line::from(Span::styled(
+                format!(
+                    "Warning: IdentityFile {} does not exist",
+                    mask(app, "IdentityFile", entry.identity_file())
+                ),
+                Style::default().fg(Color::Yellow),
+            )));
+        }
+
+        let inherited = app.inherited_directives();
+        if !inherited.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(Line::from(vec![Span::styled(
+                "Defaults from patterns:",
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            )]));
+            for (patterns, keyword, value) in inherited {
+                lines.push(Line::from(Span::styled(
+                    format!(
+                        "  {} {}  (Host {})",
+                        keyword,
+                        mask(app, keyword, value),
+                        patterns
+                    ),
+                    Style::default().fg(Color::DarkGray),
+                )));
+            }
+        }
+
+        let extra_lines = entry.extra_lines();
+        if !extra_lines.is_empty() {
             lines.push(Line::from(""));
             lines.push(Line::from(vec![Span::styled(
                 "Additional Config:",
@@ -143,17 +439,27 @@ fn draw_details_pane(f: &mut Frame, app: &App, area: Rect) {
                     .fg(Color::Cyan)
                     .add_modifier(Modifier::BOLD),
             )]));
-            for extra_line in &entry.extra {
+            for extra_line in &extra_lines {
                 if !extra_line.trim().is_empty() {
                     lines.push(Line::from(Span::styled(
-                        format!("  {}", extra_line),
+                        format!("  {}", mask_directive_line(app, extra_line)),
                         Style::default().fg(Color::DarkGray),
                     )));
                 }
             }
         }
 
-        let details = Paragraph::new(lines).block(details_block);
+        let width = details_block.inner(area).width as usize;
+        let lines: Vec<Line> = lines
+            .into_iter()
+            .flat_map(|line| {
+                let indent = hanging_indent(&line);
+                wrap_line(line, width, indent)
+            })
+            .collect();
+        let details = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(details_block);
         f.render_widget(details, area);
     } else {
         let empty_text = Paragraph::new("No host selected")
@@ -163,6 +469,325 @@ fn draw_details_pane(f: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+/// What masked values are shown as.
+const MASK: &str = "••••••";
+
+/// Whether a directive's value is worth hiding while sharing a screen:
+/// key paths, proxy commands (which often carry credentials), and anything
+/// that mentions a password.
+fn is_sensitive(keyword: &str, value: &str) -> bool {
+    keyword.eq_ignore_ascii_case("IdentityFile")
+        || keyword.eq_ignore_ascii_case("ProxyCommand")
+        || keyword.to_ascii_lowercase().contains("password")
+        || value.to_ascii_lowercase().contains("password")
+}
+
+/// `value`, or the mask when masking is on and the directive is sensitive.
+fn mask(app: &App, keyword: &str, value: &str) -> String {
+    if app.mask_sensitive && is_sensitive(keyword, value) {
+        MASK.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// A raw `Keyword value` line with its value masked, keeping the keyword so
+/// the line still says what it is.
+fn mask_directive_line(app: &App, line: &str) -> String {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    let split = trimmed
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(trimmed.len());
+    let (keyword, value) = trimmed.split_at(split);
+    let value = value.trim_start_matches(|c: char| c.is_whitespace() || c == '=');
+    if value.is_empty() || !app.mask_sensitive || !is_sensitive(keyword, value) {
+        return line.to_string();
+    }
+    format!("{}{} {}", indent, keyword, MASK)
+}
+
+/// Where a details line's continuation rows start: under the value, i.e.
+/// after the label of a field line, or after the first word (and any
+/// leading indent) of a single-span line such as `  ProxyCommand ...`.
+fn hanging_indent(line: &Line) -> usize {
+    match line.spans.as_slice() {
+        [label, _, ..] => label.width(),
+        [span] => {
+            let text = span.content.as_ref();
+            let start = text.len() - text.trim_start().len();
+            match text[start..].find(' ') {
+                Some(end) => text[..start + end + 1].chars().count(),
+                None => 0,
+            }
+        }
+        [] => 0,
+    }
+}
+
+/// Breaks `line` into rows of at most `width` characters, after a space
+/// where there is one, indenting every row after the first by `indent`
+/// (dropped when it would leave less than half the width). Lines that fit
+/// come back untouched.
+fn wrap_line(line: Line<'_>, width: usize, indent: usize) -> Vec<Line<'_>> {
+    if width == 0 || line.width() <= width {
+        return vec![line];
+    }
+    let indent = if indent * 2 > width { 0 } else { indent };
+    let chars: Vec<(char, Style)> = line
+        .spans
+        .iter()
+        .flat_map(|span| span.content.chars().map(move |c| (c, span.style)))
+        .collect();
+
+    let mut rows = Vec::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let room = if rows.is_empty() {
+            width
+        } else {
+            width - indent
+        };
+        let mut end = (start + room).min(chars.len());
+        if end < chars.len() {
+            if let Some(space) = chars[start..end].iter().rposition(|(c, _)| *c == ' ') {
+                if space > 0 {
+                    end = start + space + 1;
+                }
+            }
+        }
+        let mut spans = Vec::new();
+        if !rows.is_empty() {
+            spans.push(Span::raw(" ".repeat(indent)));
+        }
+        for &(c, style) in &chars[start..end] {
+            match spans.last_mut() {
+                Some(span) if span.style == style => span.content.to_mut().push(c),
+                _ => spans.push(Span::styled(c.to_string(), style)),
+            }
+        }
+        rows.push(Line::from(spans));
+        start = end;
+    }
+    rows
+}
+
+/// Renders a popup with a query line above the items that match it.
+fn draw_filtered_list(f: &mut Frame, title: &str, list: &FilteredList, labels: &[&str]) {
+    let area = centered_rect(60, 50, f.area());
+    f.render_widget(Clear, area);
+
+    let block = Block::default().borders(Borders::ALL).title(title);
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner);
+
+    let query = Paragraph::new(Line::from(vec![
+        Span::styled("> ", Style::default().fg(Color::Cyan)),
+        Span::raw(format!("{}_", list.query)),
+    ]));
+    f.render_widget(query, chunks[0]);
+
+    let items: Vec<ListItem> = list
+        .matches()
+        .iter()
+        .enumerate()
+        .map(|(position, &index)| {
+            let style = if position == list.selected {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            ListItem::new(labels.get(index).copied().unwrap_or_default()).style(style)
+        })
+        .collect();
+
+    if items.is_empty() {
+        let empty = Paragraph::new("No matches").style(Style::default().fg(Color::DarkGray));
+        f.render_widget(empty, chunks[1]);
+    } else {
+        f.render_widget(List::new(items), chunks[1]);
+    }
+}
+
+fn draw_confirmation(f: &mut Frame, prompt: &str) {
+    let area = centered_rect(60, 20, f.area());
+    f.render_widget(Clear, area);
+    let popup = Paragraph::new(prompt)
+        .style(Style::default().fg(Color::Yellow))
+        .wrap(Wrap { trim: true })
+        .block(Block::default().borders(Borders::ALL).title(" Confirm "));
+    f.render_widget(popup, area);
+}
+
+/// One-line prompt with the alias Enter would connect to shown after it.
+fn draw_quick_connect(f: &mut Frame, query: &str, best: Option<&str>) {
+    let hint = match (query.is_empty(), best) {
+        (true, _) => "  type an alias, Tab completes".to_string(),
+        (false, Some(best)) => format!("  → {}", best),
+        (false, None) => "  (no match)".to_string(),
+    };
+    draw_prompt(f, " Connect to ", query, &hint);
+}
+
+/// A one-line input popup: the typed text with a cursor, then a dim hint.
+fn draw_prompt(f: &mut Frame, title: &str, input: &str, hint: &str) {
+    let area = centered_rect(60, 20, f.area());
+    let area = Rect {
+        height: area.height.min(3),
+        ..area
+    };
+    f.render_widget(Clear, area);
+    let prompt = Paragraph::new(Line::from(vec![
+        Span::styled("> ", Style::default().fg(Color::Cyan)),
+        Span::raw(format!("{}_", input)),
+        Span::styled(hint.to_string(), Style::default().fg(Color::DarkGray)),
+    ]))
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title.to_string()),
+    );
+    f.render_widget(prompt, area);
+}
+
+/// Differing directives in three columns: keyword, left host, right host.
+/// Sensitive values are masked like in the details pane.
+fn draw_comparison(f: &mut Frame, app: &App, comparison: &Comparison) {
+    let area = centered_rect(80, 60, f.area());
+    f.render_widget(Clear, area);
+    let unset = |keyword: &str, value: &str| {
+        if value.is_empty() {
+            "—".to_string()
+        } else {
+            mask(app, keyword, value)
+        }
+    };
+    let keyword_width = comparison
+        .diffs
+        .iter()
+        .map(|diff| diff.keyword.len())
+        .max()
+        .unwrap_or(0)
+        .max("Field".len());
+    let left_width = comparison
+        .diffs
+        .iter()
+        .map(|diff| unset(&diff.keyword, &diff.left).chars().count())
+        .chain([comparison.left.chars().count()])
+        .max()
+        .unwrap_or(0);
+
+    let lines: Vec<Line> = if comparison.diffs.is_empty() {
+        vec![Line::from("No differences")]
+    } else {
+        let header = Line::from(Span::styled(
+            format!(
+                "{:keyword_width$}  {:left_width$}  {}",
+                "Field", comparison.left, comparison.right
+            ),
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        std::iter::once(header)
+            .chain(comparison.diffs.iter().map(|diff| {
+                Line::from(vec![
+                    Span::styled(
+                        format!("{:keyword_width$}  ", diff.keyword),
+                        Style::default().fg(Color::Cyan),
+                    ),
+                    Span::styled(
+                        format!("{:left_width$}  ", unset(&diff.keyword, &diff.left)),
+                        Style::default().fg(Color::Red),
+                    ),
+                    Span::styled(
+                        unset(&diff.keyword, &diff.right),
+                        Style::default().fg(Color::Green),
+                    ),
+                ])
+            }))
+            .collect()
+    };
+    let popup = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(format!(
+        " {} vs {} (any key to close) ",
+        comparison.left, comparison.right
+    )));
+    f.render_widget(popup, area);
+}
+
+fn draw_issues(f: &mut Frame, warnings: &[String]) {
+    let area = centered_rect(70, 50, f.area());
+    f.render_widget(Clear, area);
+    let lines: Vec<Line> = warnings
+        .iter()
+        .map(|warning| Line::from(format!("⚠ {}", warning)))
+        .collect();
+    let popup = Paragraph::new(lines)
+        .style(Style::default().fg(Color::Yellow))
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Config Issues (any key to close) "),
+        );
+    f.render_widget(popup, area);
+}
+
+/// Connection attempts, newest first; failures in red.
+fn draw_history(f: &mut Frame, history: &[HistoryEntry]) {
+    let area = centered_rect(80, 60, f.area());
+    f.render_widget(Clear, area);
+    let lines: Vec<Line> = history
+        .iter()
+        .map(|entry| {
+            let color = if entry.success {
+                Color::Green
+            } else {
+                Color::Red
+            };
+            Line::from(vec![
+                Span::styled(
+                    format!("{}  ", format_timestamp(entry.timestamp)),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(format!("{} ({})  ", entry.alias, entry.target)),
+                Span::styled(entry.result.clone(), Style::default().fg(color)),
+            ])
+        })
+        .collect();
+    let popup = Paragraph::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Connection History, UTC (any key to close) "),
+    );
+    f.render_widget(popup, area);
+}
+
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
 fn draw_form(f: &mut Frame, app: &App) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -176,31 +801,98 @@ fn draw_form(f: &mut Frame, app: &App) {
     let title_text = match app.state {
         AppState::Edit => "Edit Host",
         AppState::New => "New Host",
-        AppState::Normal => "Form",
+        AppState::KeyPicker => match app.form_return_mode() {
+            AppState::Edit => "Edit Host",
+            _ => "New Host",
+        },
+        _ => "Form",
     };
 
     let title = Block::default()
         .borders(Borders::ALL)
         .title(format!(" {} ", title_text));
-    let help_text = "Tab/Shift+Tab: navigate | Enter: save | Esc: cancel";
+    let help_text = "Tab/Shift+Tab: navigate/complete path | Enter: save | Ctrl+O: pick key | Ctrl+Space: next key | Esc: cancel";
     let help = Paragraph::new(help_text).block(title);
     f.render_widget(help, chunks[0]);
 
     let form_area = chunks[1];
     draw_form_fields(f, app, form_area);
 
-    let footer_text = if let Some(error) = &app.form_error {
-        error.clone()
+    let (footer_text, footer_style) = if let Some(error) = &app.form_error {
+        (error.clone(), Style::default().fg(Color::Red))
+    } else if let Some(hint) = &app.form_hint {
+        (hint.clone(), Style::default().fg(Color::Yellow))
     } else {
-        "Fill in the form and press Enter to save".to_string()
+        (
+            "Fill in the form and press Enter to save".to_string(),
+            Style::default(),
+        )
     };
 
-    let footer_style = if app.form_error.is_some() {
-        Style::default().fg(Color::Red)
+    let footer = Paragraph::new(footer_text)
+        .style(footer_style)
+        .block(Block::default().borders(Borders::ALL).title(" Status "));
+    f.render_widget(footer, chunks[2]);
+}
+
+/// The selected block as plain text, with a cursor, filling the screen.
+fn draw_raw_editor(f: &mut Frame, app: &App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(3),
+        ])
+        .split(f.area());
+
+    let importing = app.state == AppState::Import;
+    let (help, title) = if importing {
+        (
+            "Ctrl+S: preview import | Esc: cancel",
+            " Import Hosts ".to_string(),
+        )
     } else {
-        Style::default()
+        (
+            "Ctrl+S: parse and save | Esc: discard changes",
+            format!(" Edit Raw Block: {} ", app.raw_edit_host()),
+        )
     };
+    let help = Paragraph::new(help).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(help, chunks[0]);
 
+    let editor = app.raw_editor.lines();
+    let (row, col) = app.raw_editor.cursor();
+    let area = chunks[1];
+    let height = area.height.saturating_sub(2) as usize;
+    let width = area.width.saturating_sub(2) as usize;
+    let scroll = (row + 1).saturating_sub(height);
+    let h_scroll = (col + 1).saturating_sub(width);
+    let lines: Vec<Line> = editor.iter().map(|line| Line::raw(line.as_str())).collect();
+    let text = Paragraph::new(lines)
+        .scroll((scroll as u16, h_scroll as u16))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow)),
+        );
+    f.render_widget(text, area);
+    f.set_cursor_position((
+        area.x + 1 + (col - h_scroll) as u16,
+        area.y + 1 + (row - scroll) as u16,
+    ));
+
+    let (footer_text, footer_style) = match &app.form_error {
+        Some(error) => (error.clone(), Style::default().fg(Color::Red)),
+        None if importing => (
+            "Paste ssh_config text, or type the path of a file holding it".to_string(),
+            Style::default(),
+        ),
+        None => (
+            "One Host block; it is re-parsed and validated on save".to_string(),
+            Style::default(),
+        ),
+    };
     let footer = Paragraph::new(footer_text)
         .style(footer_style)
         .block(Block::default().borders(Borders::ALL).title(" Status "));
@@ -208,56 +900,91 @@ fn draw_form(f: &mut Frame, app: &App) {
 }
 
 fn draw_form_fields(f: &mut Frame, app: &App, area: Rect) {
-    let field_constraints = vec![
-        Constraint::Length(3),
-        Constraint::Length(3),
-        Constraint::Length(3),
-        Constraint::Length(3),
-        Constraint::Length(3),
-        Constraint::Min(0),
+    let fields = [
+        (
+            "Host (alias)",
+            app.form_entry.host.as_str(),
+            FormField::Host,
+        ),
+        (
+            "HostName (address)",
+            app.form_entry.hostname(),
+            FormField::HostName,
+        ),
+        ("User", app.form_entry.user(), FormField::User),
+        ("Port", app.form_entry.port(), FormField::Port),
+        (
+            "IdentityFile",
+            app.form_entry.identity_file(),
+            FormField::IdentityFile,
+        ),
+        (
+            "Tags (space-separated)",
+            app.form_tags.as_str(),
+            FormField::Tags,
+        ),
+        ("Note", app.form_note.as_str(), FormField::Note),
     ];
 
+    // The focused field's live hint takes a line right below its box.
+    let mut field_constraints = Vec::new();
+    for (_, _, field) in &fields {
+        field_constraints.push(Constraint::Length(3));
+        if *field == app.form_field && app.field_hint.is_some() {
+            field_constraints.push(Constraint::Length(1));
+        }
+    }
+    field_constraints.push(Constraint::Min(0));
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(field_constraints)
         .margin(1)
         .split(area);
 
-    draw_field(
-        f,
-        "Host (alias)",
-        &app.form_entry.host,
-        chunks[0],
-        app.form_field == FormField::Host,
-    );
-    draw_field(
-        f,
-        "HostName (address)",
-        &app.form_entry.hostname,
-        chunks[1],
-        app.form_field == FormField::HostName,
-    );
-    draw_field(
-        f,
-        "User",
-        &app.form_entry.user,
-        chunks[2],
-        app.form_field == FormField::User,
-    );
-    draw_field(
-        f,
-        "Port",
-        &app.form_entry.port,
-        chunks[3],
-        app.form_field == FormField::Port,
-    );
-    draw_field(
-        f,
-        "IdentityFile",
-        &app.form_entry.identity_file,
-        chunks[4],
-        app.form_field == FormField::IdentityFile,
-    );
+    let mut chunk = chunks.iter();
+    for (label, value, field) in fields {
+        let focused = field == app.form_field;
+        if let Some(&area) = chunk.next() {
+            draw_field(f, label, value, area, focused);
+        }
+        if let (true, Some(hint)) = (focused, &app.field_hint) {
+            if let Some(&area) = chunk.next() {
+                let hint =
+                    Paragraph::new(format!(" {}", hint)).style(Style::default().fg(Color::Red));
+                f.render_widget(hint, area);
+            }
+        }
+    }
+}
+
+/// Each tag as a colored chip, preceded by a space. The color follows the
+/// tag's name so a tag looks the same on every host.
+fn tag_chips<'a>(tags: &[&'a str]) -> Vec<Span<'a>> {
+    const COLORS: [Color; 6] = [
+        Color::Blue,
+        Color::Magenta,
+        Color::Green,
+        Color::Cyan,
+        Color::Red,
+        Color::Yellow,
+    ];
+    tags.iter()
+        .flat_map(|&tag| {
+            let hash = tag.to_lowercase().bytes().fold(0usize, |hash, byte| {
+                hash.wrapping_mul(31).wrapping_add(byte.into())
+            });
+            [
+                Span::raw(" "),
+                Span::styled(
+                    format!(" {} ", tag),
+                    Style::default()
+                        .fg(Color::Black)
+                        .bg(COLORS[hash % COLORS.len()]),
+                ),
+            ]
+        })
+        .collect()
 }
 
 fn draw_field(f: &mut Frame, label: &str, value: &str, area: Rect, focused: bool) {
@@ -304,15 +1031,11 @@ mod tests {
     use ratatui::Terminal;
 
     fn test_host(name: &str, hostname: &str) -> HostEntry {
-        HostEntry {
-            host: name.to_string(),
-            hostname: hostname.to_string(),
-            user: "testuser".to_string(),
-            port: "22".to_string(),
-            identity_file: "~/.ssh/id_rsa".to_string(),
-            proxy_command: String::new(),
-            extra: vec![],
-        }
+        HostEntry::new(name)
+            .with("HostName", hostname)
+            .with("User", "testuser")
+            .with("Port", "22")
+            .with("IdentityFile", "~/.ssh/id_rsa")
     }
 
     #[test]
@@ -339,17 +1062,77 @@ mod tests {
         assert!(text.contains("server2"));
     }
 
+    #[test]
+    fn test_hosts_title_counts_selection_and_total() {
+        let mut app = App::test_with_hosts(vec![
+            test_host("server1", "192.168.1.1"),
+            test_host("server2", "192.168.1.2"),
+            test_host("server3", "192.168.1.3"),
+        ]);
+        app.next();
+
+        let backend = TestBackend::new(80, 24);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        let text: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(text.contains("Hosts (2/3)"));
+
+        app.open_search();
+        "server3".chars().for_each(|c| app.search_input(c));
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        let text: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(text.contains("Hosts (1/1) [/server3]"));
+    }
+
+    #[test]
+    fn test_draw_hints_at_many_extra_directives() {
+        let busy = test_host("busy", "busy.example.com")
+            .with("ForwardAgent", "yes")
+            .with("Compression", "yes")
+            .with("ServerAliveInterval", "30")
+            .with("LocalForward", "8080 localhost:80")
+            .with_line("  # just a comment");
+        let quiet = test_host("quiet", "quiet.example.com").with("ForwardAgent", "yes");
+        let app = App::test_with_hosts(vec![busy, quiet]);
+
+        let backend = TestBackend::new(120, 30);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+
+        let buffer = terminal.backend().buffer().clone();
+        let rows: Vec<String> = buffer
+            .content()
+            .chunks(120)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        assert!(rows
+            .iter()
+            .any(|row| row.contains("busy (") && row.contains("(+4 more)")));
+        assert!(!rows
+            .iter()
+            .any(|row| row.contains("quiet") && row.contains("more")));
+    }
+
     #[test]
     fn test_draw_shows_selected_host_details() {
-        let host = HostEntry {
-            host: "myserver".to_string(),
-            hostname: "example.com".to_string(),
-            user: "admin".to_string(),
-            port: "2222".to_string(),
-            identity_file: "~/.ssh/custom_key".to_string(),
-            proxy_command: String::new(),
-            extra: vec!["  ProxyCommand ssh jump".to_string()],
-        };
+        let host = HostEntry::new("myserver")
+            .with("HostName", "example.com")
+            .with("User", "admin")
+            .with("Port", "2222")
+            .with("IdentityFile", "~/.ssh/custom_key")
+            .with("ProxyCommand", "ssh jump");
 
         let app = App::test_with_hosts(vec![host]);
 
@@ -369,6 +1152,206 @@ mod tests {
         assert!(text.contains("ProxyCommand"));
     }
 
+    #[test]
+    fn test_draw_previews_expanded_proxy_command() {
+        let host = HostEntry::new("legacy")
+            .with("HostName", "10.0.0.6")
+            .with("ProxyCommand", "ssh -W %h:%p bastion");
+        let app = App::test_with_hosts(vec![host]);
+
+        let backend = TestBackend::new(120, 30);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        let text: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+
+        assert!(text.contains("ProxyCommand → ssh -W 10.0.0.6:22 bastion"));
+    }
+
+    #[test]
+    fn test_details_values_line_up_in_a_column() {
+        let host = HostEntry::new("web")
+            .with("HostName", "web.example.com")
+            .with("User", "deploy")
+            .with("Port", "2222")
+            .with("IdentityFile", "~/.ssh/web");
+        let draw_rows = |width: u16| {
+            let app = App::test_with_hosts(vec![host.clone()]);
+            let mut terminal = Terminal::new(TestBackend::new(width, 24)).unwrap();
+            terminal.draw(|f| draw(f, &app)).unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            (0..buffer.area.height)
+                .map(|y| {
+                    (0..buffer.area.width)
+                        .map(|x| buffer[(x, y)].symbol())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let column = |rows: &[String], label: &str, value: &str| {
+            let row = rows.iter().find(|row| row.contains(label)).unwrap();
+            row.find(value).unwrap() - row.find(label).unwrap()
+        };
+
+        let rows = draw_rows(120);
+        assert_eq!(column(&rows, "HostName:", "web.example.com"), 14);
+        assert_eq!(column(&rows, "User:", "deploy"), 14);
+        assert_eq!(column(&rows, "Port:", "2222"), 14);
+        assert_eq!(column(&rows, "IdentityFile:", "~/.ssh/web"), 14);
+
+        let rows = draw_rows(60);
+        assert_eq!(column(&rows, "User:", "deploy"), 6);
+    }
+
+    #[test]
+    fn test_details_wrap_long_values_under_their_column() {
+        let command = "ssh -o StrictHostKeyChecking=accept-new -o ServerAliveInterval=15 -W 10.0.0.7:22 bastion.example.com";
+        let host = HostEntry::new("deep")
+            .with("HostName", "10.0.0.7")
+            .with("ProxyCommand", command);
+        let app = App::test_with_hosts(vec![host]);
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let details_x = (20..buffer.area.width)
+            .find(|&x| buffer[(x, 3)].symbol() == "┌")
+            .unwrap()
+            + 1;
+        let rows: Vec<String> = (0..buffer.area.height)
+            .map(|y| {
+                (details_x..buffer.area.width - 1)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect()
+            })
+            .collect();
+
+        let first = rows
+            .iter()
+            .position(|row| row.starts_with("  ProxyCommand ssh"))
+            .unwrap();
+        let continued: Vec<&String> = rows[first + 1..]
+            .iter()
+            .take_while(|row| row.starts_with(&" ".repeat(15)) && !row.trim().is_empty())
+            .collect();
+        assert!(continued.len() >= 2);
+        assert!(continued.iter().all(|row| !row[15..].starts_with(' ')));
+
+        let squeeze = |text: &str| text.split_whitespace().collect::<String>();
+        let shown: String = std::iter::once(&rows[first])
+            .chain(continued)
+            .map(|row| squeeze(row))
+            .collect();
+        assert_eq!(shown, squeeze(&format!("ProxyCommand {}", command)));
+    }
+
+    #[test]
+    fn test_draw_marks_hosts_behind_a_proxy() {
+        let app = App::test_with_hosts(vec![
+            HostEntry::new("legacy")
+                .with("HostName", "10.0.0.6")
+                .with("ProxyCommand", "ssh -W %h:%p bastion"),
+            HostEntry::new("inner")
+                .with("HostName", "10.0.0.7")
+                .with("ProxyJump", "bastion"),
+            HostEntry::new("plain").with("HostName", "10.0.0.8"),
+            HostEntry::new("direct")
+                .with("HostName", "10.0.0.9")
+                .with("ProxyJump", "none"),
+        ]);
+
+        let backend = TestBackend::new(120, 30);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect()
+            })
+            .collect();
+        let row = |alias: &str| {
+            let item = format!("{} (", alias);
+            rows.iter().find(|row| row.contains(&item)).unwrap().clone()
+        };
+
+        assert!(row("legacy").contains("legacy (10.0.0.6) ⤴"));
+        assert!(row("inner").contains("inner (10.0.0.7) ⤴"));
+        assert!(!row("plain").contains('⤴'));
+        assert!(!row("direct").contains('⤴'));
+    }
+
+    #[test]
+    fn test_details_mask_sensitive_values() {
+        let mut app = App::test_with_hosts(vec![HostEntry::new("web")
+            .with("HostName", "10.0.0.5")
+            .with("IdentityFile", "~/.ssh/id_work")
+            .with("ProxyCommand", "ssh -W %h:%p bastion")]);
+        let render = |app: &App| {
+            let backend = TestBackend::new(120, 30);
+            let mut terminal = Terminal::new(backend).unwrap();
+            terminal.draw(|f| draw(f, app)).unwrap();
+            let buffer = terminal.backend().buffer();
+            (0..buffer.area.height)
+                .map(|y| {
+                    (0..buffer.area.width)
+                        .map(|x| buffer[(x, y)].symbol())
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let shown = render(&app);
+        assert!(shown.contains("~/.ssh/id_work"));
+        assert!(shown.contains("bastion"));
+        assert!(!shown.contains("••••••"));
+
+        app.toggle_mask_sensitive();
+        let masked = render(&app);
+        assert!(!masked.contains("~/.ssh/id_work"));
+        assert!(!masked.contains("bastion"));
+        assert!(masked.contains("••••••"));
+        assert!(masked.contains("10.0.0.5"));
+    }
+
+    #[test]
+    fn test_comparison_masks_sensitive_values() {
+        let mut app = App::test_with_hosts(vec![
+            HostEntry::new("web")
+                .with("HostName", "10.0.0.5")
+                .with("IdentityFile", "~/.ssh/id_web"),
+            HostEntry::new("db")
+                .with("HostName", "10.0.0.6")
+                .with("ProxyCommand", "ssh -W %h:%p bastion"),
+        ]);
+        app.toggle_mask_sensitive();
+        app.open_compare_picker();
+        app.compare_with_picked();
+        assert_eq!(app.state, AppState::Compare);
+
+        let backend = TestBackend::new(120, 30);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        let text: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(text.contains("10.0.0.6"));
+        assert!(text.contains("ProxyCommand"));
+        assert!(!text.contains("id_web"));
+        assert!(!text.contains("bastion"));
+    }
+
     #[test]
     fn test_draw_empty_host_list() {
         let app = App::test_with_hosts(vec![]);
@@ -407,6 +1390,25 @@ mod tests {
         assert!(text.contains("IdentityFile"));
     }
 
+    #[test]
+    fn test_draw_form_shows_field_hint_below_field() {
+        let mut app = App::test_with_hosts(vec![]);
+        app.enter_new_mode();
+        app.handle_form_input('*');
+
+        let backend = TestBackend::new(80, 30);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+
+        let buffer = terminal.backend().buffer().clone();
+        let row = |y: u16| -> String { (0..80).map(|x| buffer[(x, y)].symbol()).collect() };
+        let hint_row = (0..30)
+            .find(|&y| row(y).contains("Wildcards make this a pattern"))
+            .unwrap();
+        assert!(row(hint_row - 2).contains("*_"));
+        assert!(row(hint_row + 1).contains("HostName"));
+    }
+
     #[test]
     fn test_draw_form_new_mode() {
         let mut app = App::test_with_hosts(vec![]);
@@ -427,7 +1429,7 @@ mod tests {
     #[test]
     fn test_draw_with_status_message() {
         let mut app = App::test_with_hosts(vec![test_host("server1", "192.168.1.1")]);
-        app.status = Some("Connected successfully".to_string());
+        app.status = Some(Status::Success("Connected successfully".to_string()));
 
         let backend = TestBackend::new(80, 24);
         let mut terminal = Terminal::new(backend).unwrap();
@@ -477,25 +1479,14 @@ mod tests {
 
     #[test]
     fn test_details_pane_shows_optional_fields() {
-        let host_with_all_fields = HostEntry {
-            host: "full".to_string(),
-            hostname: "example.com".to_string(),
-            user: "admin".to_string(),
-            port: "2222".to_string(),
-            identity_file: "~/.ssh/id_rsa".to_string(),
-            proxy_command: String::new(),
-            extra: vec!["  ServerAliveInterval 60".to_string()],
-        };
+        let host_with_all_fields = HostEntry::new("full")
+            .with("HostName", "example.com")
+            .with("User", "admin")
+            .with("Port", "2222")
+            .with("IdentityFile", "~/.ssh/id_rsa")
+            .with("ServerAliveInterval", "60");
 
-        let host_minimal = HostEntry {
-            host: "minimal".to_string(),
-            hostname: "example.org".to_string(),
-            user: String::new(),
-            port: String::new(),
-            identity_file: String::new(),
-            proxy_command: String::new(),
-            extra: vec![],
-        };
+        let host_minimal = HostEntry::new("minimal").with("HostName", "example.org");
 
         let backend = TestBackend::new(120, 30);
         let mut terminal = Terminal::new(backend).unwrap();
@@ -513,6 +1504,22 @@ mod tests {
         assert!(text_full.contains("2222"));
         assert!(text_full.contains("id_rsa"));
         assert!(text_full.contains("ServerAliveInterval"));
+        assert!(text_full.contains("Without config: ssh -p 2222 admin@example.com"));
+
+        let v6 = HostEntry::new("v6")
+            .with("HostName", "fe80::1%eth0")
+            .with("Port", "22");
+        terminal
+            .draw(|f| draw(f, &App::test_with_hosts(vec![v6])))
+            .unwrap();
+        let text_v6: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(text_v6.contains("Without config: ssh -6 -p 22 [fe80::1%eth0]"));
 
         let app_minimal = App::test_with_hosts(vec![host_minimal]);
         terminal.draw(|f| draw(f, &app_minimal)).unwrap();
@@ -526,4 +1533,57 @@ mod tests {
         assert!(text_minimal.contains("minimal"));
         assert!(text_minimal.contains("example.org"));
     }
+
+    #[test]
+    fn test_draw_palette_filters_actions() {
+        let mut app = App::test_with_hosts(vec![test_host("server1", "192.168.1.1")]);
+        app.open_palette();
+        for ch in "reload".chars() {
+            app.palette_input(ch);
+        }
+
+        let backend = TestBackend::new(100, 30);
+        let mut terminal = Terminal::new(backend).unwrap();
+
+        terminal.draw(|f| draw(f, &app)).unwrap();
+
+        let buffer = terminal.backend().buffer().clone();
+        let text: String = buffer.content().iter().map(|cell| cell.symbol()).collect();
+
+        assert!(text.contains("Command Palette"));
+        assert!(text.contains("Reload SSH config"));
+        assert!(!text.contains("Duplicate selected host"));
+    }
+
+    #[test]
+    fn test_draw_dims_disabled_hosts_when_shown() {
+        let mut disabled = test_host("old", "10.0.0.9");
+        disabled.disabled = true;
+        let mut app = App::test_with_hosts(vec![test_host("server1", "192.168.1.1"), disabled]);
+
+        let backend = TestBackend::new(100, 24);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        let text: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(!text.contains("old (10.0.0.9)"));
+
+        app.toggle_show_disabled();
+        app.selected = 1;
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        let text: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(text.contains("old (10.0.0.9)"));
+        assert!(text.contains("(disabled)"));
+    }
 }
@@ -0,0 +1,160 @@
+use std::{fs, process::Command};
+
+fn ssh_tui(home: &std::path::Path, arg: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_ssh-tui"))
+        .arg(arg)
+        .env("SSH_TUI_HOME", home)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn home_with_config() -> tempfile::TempDir {
+    let home = tempfile::tempdir().unwrap();
+    fs::create_dir_all(home.path().join(".ssh")).unwrap();
+    fs::write(
+        home.path().join(".ssh/config"),
+        "Host web\n  HostName web.example.com\n  User deploy\n\n# Host old\n#   HostName old.example.com\n\nHost db\n  HostName 10.0.0.5\n",
+    )
+    .unwrap();
+    home
+}
+
+#[test]
+fn list_prints_enabled_aliases() {
+    let home = home_with_config();
+    assert_eq!(ssh_tui(home.path(), "--list"), "web\ndb\n");
+}
+
+#[test]
+fn list_json_prints_entries() {
+    let home = home_with_config();
+    let json: serde_json::Value =
+        serde_json::from_str(&ssh_tui(home.path(), "--list-json")).unwrap();
+    let hosts = json.as_array().unwrap();
+    assert_eq!(hosts.len(), 2);
+    assert_eq!(hosts[0]["host"], "web");
+    assert_eq!(hosts[0]["hostname"], "web.example.com");
+    assert_eq!(hosts[0]["user"], "deploy");
+    assert_eq!(hosts[1]["host"], "db");
+}
+
+#[test]
+fn connect_refuses_disabled_hosts() {
+    let home = home_with_config();
+    let output = Command::new(env!("CARGO_BIN_EXE_ssh-tui"))
+        .args(["--connect", "old"])
+        .env("SSH_TUI_HOME", home.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("no host named 'old'"));
+}
+
+#[test]
+fn add_writes_a_host_block() {
+    let home = home_with_config();
+    let output = Command::new(env!("CARGO_BIN_EXE_ssh-tui"))
+        .args([
+            "--add",
+            "host=cache",
+            "hostname=10.0.0.9",
+            "user=redis",
+            "port=2200",
+            "identity=~/.ssh/cache_ed25519",
+        ])
+        .env("SSH_TUI_HOME", home.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Saved host 'cache'\n"
+    );
+
+    let config = fs::read_to_string(home.path().join(".ssh/config")).unwrap();
+    assert!(config.ends_with(
+        "\nHost cache\n  HostName 10.0.0.9\n  User redis\n  Port 2200\n  IdentityFile ~/.ssh/cache_ed25519\n\n"
+    ));
+    assert_eq!(ssh_tui(home.path(), "--list"), "web\ndb\ncache\n");
+}
+
+#[test]
+fn add_rejects_invalid_hosts() {
+    let home = home_with_config();
+    let output = Command::new(env!("CARGO_BIN_EXE_ssh-tui"))
+        .args(["--add", "host=cache", "port=http"])
+        .env("SSH_TUI_HOME", home.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Error: "));
+    assert!(!fs::read_to_string(home.path().join(".ssh/config"))
+        .unwrap()
+        .contains("cache"));
+}
+
+#[test]
+fn merge_imports_hosts_with_a_conflict_policy() {
+    let home = home_with_config();
+    let other = home.path().join("other_config");
+    fs::write(
+        &other,
+        "Host web\n  HostName web2.example.com\n\nHost cache\n  HostName 10.0.0.9\n",
+    )
+    .unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_ssh-tui"))
+        .arg("--merge")
+        .arg(&other)
+        .args(["--on-conflict", "rename"])
+        .env("SSH_TUI_HOME", home.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "Merged {}: 2 added, 0 updated, 0 skipped\n",
+            other.display()
+        )
+    );
+    assert_eq!(ssh_tui(home.path(), "--list"), "web\ndb\nweb-2\ncache\n");
+}
+
+#[test]
+fn version_prints_the_crate_version_without_reading_the_config() {
+    let home = tempfile::tempdir().unwrap();
+    let version = ssh_tui(home.path(), "--version");
+    let expected = format!("ssh-tui {}", env!("CARGO_PKG_VERSION"));
+    assert!(version.starts_with(&expected), "{}", version);
+    assert!(version.ends_with('\n'));
+}
+
+#[test]
+#[cfg(unix)]
+fn connect_matches_alias_per_config_and_returns_the_exit_code() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let home = home_with_config();
+    let fake_ssh = home.path().join("fake-ssh");
+    fs::write(&fake_ssh, "#!/bin/sh\nexit 7\n").unwrap();
+    fs::set_permissions(&fake_ssh, fs::Permissions::from_mode(0o755)).unwrap();
+    let config_dir = home.path().join(".config/ssh-tui");
+    fs::create_dir_all(&config_dir).unwrap();
+    fs::write(
+        config_dir.join("config.toml"),
+        format!(
+            "ssh_binary = \"{}\"\nalias_match = \"ignore-case\"\n",
+            fake_ssh.display()
+        ),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ssh-tui"))
+        .args(["--connect", "WEB"])
+        .env("SSH_TUI_HOME", home.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(7));
+}